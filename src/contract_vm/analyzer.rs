@@ -2,7 +2,7 @@
 
 use colored::*;
 use itertools::sorted;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fs::File;
 use std::io::Read;
 use std::path::Path;
@@ -19,6 +19,45 @@ fn get_member_name_from_definition(item: &str) -> &str {
     }
 }
 
+// definition_name returns the name a $ref points to, a pointer in this or another file, or a whole file
+fn definition_name(reference: &str) -> &str {
+    match reference.split_once('#') {
+        Some((_, pointer)) if pointer.len() > 1 => get_member_name_from_definition(pointer),
+        Some((file, _)) => Path::new(file)
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or(file),
+        None => Path::new(reference)
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or(reference),
+    }
+}
+
+fn is_reference(type_name: &str) -> bool {
+    type_name.starts_with('#') || type_name.contains(".json")
+}
+
+// collect_refs gathers every $ref of a schema, however deeply it is nested
+fn collect_refs<'a>(value: &'a serde_json::Value, refs: &mut Vec<&'a str>) {
+    match value {
+        serde_json::Value::Object(map) => {
+            for (key, value) in map {
+                match (key.as_str(), value.as_str()) {
+                    ("$ref", Some(reference)) => refs.push(reference),
+                    _ => collect_refs(value, refs),
+                }
+            }
+        }
+        serde_json::Value::Array(array) => {
+            for value in array {
+                collect_refs(value, refs);
+            }
+        }
+        _ => {}
+    }
+}
+
 fn get_type_name_from_definition(item: &serde_json::Value) -> (&str, bool) {
     let mut optional = false;
     let def = match item.get("type") {
@@ -71,11 +110,115 @@ pub struct Member {
     pub member_def: String,
 }
 
+// numeric bounds of an integer or number field, from the schema keywords and the integer format,
+// or the values of a string enum
+#[derive(Clone, Default)]
+pub struct Constraint {
+    pub values: Vec<String>,
+    pub integer: bool,
+    pub minimum: Option<f64>,
+    pub maximum: Option<f64>,
+    pub exclusive_minimum: Option<f64>,
+    pub exclusive_maximum: Option<f64>,
+}
+
+impl Constraint {
+    // from_schema returns None for fields without constraints
+    pub fn from_schema(proper: &serde_json::Value) -> Option<Constraint> {
+        if let Some(values) = proper["enum"].as_array() {
+            return Some(Constraint {
+                values: values
+                    .iter()
+                    .filter_map(|v| v.as_str().map(|v| v.to_string()))
+                    .collect(),
+                ..Default::default()
+            })
+            .filter(|constraint| !constraint.values.is_empty());
+        }
+        let types = match &proper["type"] {
+            serde_json::Value::Array(types) => types.iter().filter_map(|t| t.as_str()).collect(),
+            t => vec![t.as_str().unwrap_or_default()],
+        };
+        if !types.contains(&"integer") && !types.contains(&"number") {
+            return None;
+        }
+        let mut constraint = Constraint {
+            values: vec![],
+            integer: types.contains(&"integer"),
+            minimum: proper["minimum"].as_f64(),
+            maximum: proper["maximum"].as_f64(),
+            exclusive_minimum: proper["exclusiveMinimum"].as_f64(),
+            exclusive_maximum: proper["exclusiveMaximum"].as_f64(),
+        };
+        // draft 4 marks the minimum and maximum as exclusive with booleans
+        if proper["exclusiveMinimum"].as_bool() == Some(true) {
+            constraint.exclusive_minimum = constraint.minimum.take();
+        }
+        if proper["exclusiveMaximum"].as_bool() == Some(true) {
+            constraint.exclusive_maximum = constraint.maximum.take();
+        }
+        // schemars only writes the minimum of unsigned integers, the format gives the full range
+        let format_range = match proper["format"].as_str().unwrap_or_default() {
+            "uint8" => Some((0.0, u8::MAX as f64)),
+            "uint16" => Some((0.0, u16::MAX as f64)),
+            "uint32" => Some((0.0, u32::MAX as f64)),
+            "uint64" => Some((0.0, u64::MAX as f64)),
+            "int8" => Some((i8::MIN as f64, i8::MAX as f64)),
+            "int16" => Some((i16::MIN as f64, i16::MAX as f64)),
+            "int32" => Some((i32::MIN as f64, i32::MAX as f64)),
+            "int64" => Some((i64::MIN as f64, i64::MAX as f64)),
+            _ => None,
+        };
+        if let Some((min, max)) = format_range {
+            constraint.minimum = constraint.minimum.or(Some(min));
+            constraint.maximum = constraint.maximum.or(Some(max));
+        }
+        Some(constraint)
+    }
+
+    // check tells why the value is not allowed
+    pub fn check(&self, value: &serde_json::Value) -> Result<(), String> {
+        if !self.values.is_empty() {
+            return match value.as_str() {
+                Some(v) if self.values.iter().any(|allowed| allowed.eq(v)) => Ok(()),
+                _ => Err(format!(
+                    "{} is not one of {}",
+                    value,
+                    self.values.join(" | ")
+                )),
+            };
+        }
+        let number = match value.as_f64() {
+            Some(number) => number,
+            None => return Err(format!("{} is not a number", value)),
+        };
+        if self.integer && number.fract() != 0.0 {
+            return Err(format!("{} is not an integer", value));
+        }
+        if let Some(min) = self.minimum.filter(|min| number < *min) {
+            return Err(format!("{} is less than the minimum {}", value, min));
+        }
+        if let Some(max) = self.maximum.filter(|max| number > *max) {
+            return Err(format!("{} is greater than the maximum {}", value, max));
+        }
+        if let Some(min) = self.exclusive_minimum.filter(|min| number <= *min) {
+            return Err(format!("{} must be greater than {}", value, min));
+        }
+        if let Some(max) = self.exclusive_maximum.filter(|max| number >= *max) {
+            return Err(format!("{} must be less than {}", value, max));
+        }
+        Ok(())
+    }
+}
+
 pub struct Analyzer {
     pub map_of_basetype: HashMap<String, String>,
     pub map_of_struct: HashMap<String, HashMap<String, String>>,
     pub map_of_member: HashMap<String, HashMap<String, Vec<Member>>>,
     pub map_of_enum: HashMap<String, bool>,
+    // constraints of members by struct or message name and member name, like "Config.fee",
+    // and of base types by their name
+    pub map_of_constraint: HashMap<String, Constraint>,
 }
 
 impl Analyzer {
@@ -85,6 +228,7 @@ impl Analyzer {
             map_of_struct: HashMap::new(),
             map_of_member: HashMap::new(),
             map_of_enum: HashMap::new(),
+            map_of_constraint: HashMap::new(),
         };
     }
 
@@ -117,13 +261,13 @@ impl Analyzer {
                     },
                 };
                 // array types
-                format!("[{}]", get_member_name_from_definition(item))
+                format!("[{}]", definition_name(item))
             }
             _ => {
                 //base type
-                match type_name.starts_with("#/definitions") {
+                match is_reference(type_name) {
                     // struct
-                    true => get_member_name_from_definition(type_name).to_string(),
+                    true => definition_name(type_name).to_string(),
                     false => type_name.to_string(),
                 }
             }
@@ -147,6 +291,7 @@ impl Analyzer {
         mem_name: &String,
         struct_type: &HashMap<String, HashMap<String, String>>,
         mapper: &mut HashMap<String, Vec<Member>>,
+        constraints: &mut HashMap<String, Constraint>,
     ) -> bool {
         // create new member vector
         mapper.insert(mem_name.to_owned(), Vec::new());
@@ -155,7 +300,7 @@ impl Analyzer {
         let vec_mem = mapper.get_mut(mem_name).unwrap();
 
         if let Some(def_str) = properties.get("$ref") {
-            let type_name = get_member_name_from_definition(def_str.as_str().unwrap());
+            let type_name = definition_name(def_str.as_str().unwrap_or_default());
 
             match struct_type.get(type_name) {
                 None => return false,
@@ -166,6 +311,12 @@ impl Analyzer {
                             member_def: members.1.to_string(),
                         };
                         vec_mem.push(member);
+                        if let Some(constraint) = constraints
+                            .get(&format!("{}.{}", type_name, members.0))
+                            .cloned()
+                        {
+                            constraints.insert(format!("{}.{}", mem_name, members.0), constraint);
+                        }
                     }
                 }
             };
@@ -175,6 +326,9 @@ impl Analyzer {
                 if let Some(member) = Self::get_member(req_str, proper) {
                     vec_mem.push(member);
                 }
+                if let Some(constraint) = Constraint::from_schema(proper) {
+                    constraints.insert(format!("{}.{}", mem_name, req_str), constraint);
+                }
             }
         }
         // sorted by ASC
@@ -183,6 +337,34 @@ impl Analyzer {
         return true;
     }
 
+    // to_json exports the analyzed messages, structs and base types for REST clients
+    pub fn to_json(&self) -> serde_json::Value {
+        let messages: serde_json::Map<String, serde_json::Value> = self
+            .map_of_member
+            .iter()
+            .map(|(msg_name, variants)| {
+                let variants: serde_json::Map<String, serde_json::Value> = variants
+                    .iter()
+                    .map(|(variant, members)| {
+                        let members: Vec<serde_json::Value> = members
+                            .iter()
+                            .map(|m| serde_json::json!({ "name": m.member_name, "type": m.member_def }))
+                            .collect();
+                        (variant.to_owned(), serde_json::Value::from(members))
+                    })
+                    .collect();
+                (msg_name.to_owned(), serde_json::Value::from(variants))
+            })
+            .collect();
+
+        serde_json::json!({
+            "messages": messages,
+            "enums": self.map_of_enum,
+            "structs": self.map_of_struct,
+            "base_types": self.map_of_basetype,
+        })
+    }
+
     pub fn dump_all_definitions(&self) {
         println!();
         // if we make sure about key existed, we can access directly without guarding
@@ -274,6 +456,7 @@ impl Analyzer {
         def: &serde_json::Value,
         base_type: &mut HashMap<String, String>,
         struct_type: &mut HashMap<String, HashMap<String, String>>,
+        constraints: &mut HashMap<String, Constraint>,
     ) -> bool {
         let def_arr = match def.as_object() {
             None => return false,
@@ -281,6 +464,12 @@ impl Analyzer {
         };
 
         for d in def_arr {
+            // definitions nested in a definition
+            for key in &["definitions", "$defs"] {
+                if let Some(nested) = d.1.get(key) {
+                    Self::prepare_definitions(nested, base_type, struct_type, constraints);
+                }
+            }
             let (type_def, _) = get_type_name_from_definition(d.1);
 
             if type_def == "object" {
@@ -300,17 +489,84 @@ impl Analyzer {
                     if let Some(member) = Self::get_member(req_str, proper) {
                         vec_struct.insert(member.member_name, member.member_def);
                     }
+                    if let Some(constraint) = Constraint::from_schema(proper) {
+                        constraints.insert(format!("{}.{}", d.0, req_str), constraint);
+                    }
                 }
 
                 struct_type.insert(d.0.to_string(), vec_struct);
             } else {
                 //base type
                 base_type.insert(d.0.to_owned(), type_def.to_string());
+                // string enums are checked by their definition name
+                if let Some(constraint) = Constraint::from_schema(d.1) {
+                    constraints.insert(d.0.to_owned(), constraint);
+                }
             }
         }
         return true;
     }
 
+    // prepare_external_definitions loads the definitions of other schema files referenced by $ref
+    fn prepare_external_definitions(
+        &mut self,
+        schema: &serde_json::Value,
+        dir: &Path,
+        loaded_files: &mut HashSet<String>,
+    ) {
+        let mut refs = vec![];
+        collect_refs(schema, &mut refs);
+        for reference in refs {
+            let (file, pointer) = match reference.split_once('#') {
+                Some((file, pointer)) => (file, pointer),
+                None => (reference, ""),
+            };
+            if file.is_empty() {
+                continue;
+            }
+            let path = dir.join(file);
+            let path_str = path.display().to_string();
+            if !loaded_files.insert(path_str.to_owned()) {
+                continue;
+            }
+            let external: serde_json::Value = match load_data_from_file(&path_str)
+                .ok()
+                .and_then(|data| serde_json::from_slice(data.as_slice()).ok())
+            {
+                Some(external) => external,
+                None => {
+                    println!("can not load referenced schema {}", path_str.red());
+                    continue;
+                }
+            };
+            for key in &["definitions", "$defs"] {
+                if let Some(definitions) = external.get(key) {
+                    Self::prepare_definitions(
+                        definitions,
+                        &mut self.map_of_basetype,
+                        &mut self.map_of_struct,
+                        &mut self.map_of_constraint,
+                    );
+                }
+            }
+            // a whole file is a definition named by the file stem
+            if pointer.len() <= 1 {
+                let root = serde_json::json!({ definition_name(reference): external });
+                Self::prepare_definitions(
+                    &root,
+                    &mut self.map_of_basetype,
+                    &mut self.map_of_struct,
+                    &mut self.map_of_constraint,
+                );
+            }
+            self.prepare_external_definitions(
+                &external,
+                path.parent().unwrap_or(dir),
+                loaded_files,
+            );
+        }
+    }
+
     fn analyze_schema(&mut self, path: String) -> bool {
         let data = match load_data_from_file(path.as_str()) {
             Err(_e) => return false,
@@ -320,8 +576,24 @@ impl Analyzer {
             Ok(trs) => trs,
             Err(_e) => return false,
         };
+        // definitions of referenced files first, the schema's own definitions take precedence
+        let dir = Path::new(&path).parent().unwrap_or(Path::new("."));
+        self.prepare_external_definitions(&translated, dir, &mut HashSet::new());
         let title_must_exist = match translated["title"].as_str() {
-            None => return false,
+            // shared type files only have definitions
+            None => {
+                for key in &["definitions", "$defs"] {
+                    if let Some(definitions) = translated.get(key) {
+                        Self::prepare_definitions(
+                            definitions,
+                            &mut self.map_of_basetype,
+                            &mut self.map_of_struct,
+                            &mut self.map_of_constraint,
+                        );
+                    }
+                }
+                return true;
+            }
             Some(title) => title,
         };
 
@@ -338,11 +610,12 @@ impl Analyzer {
         };
         // prepare definitions before analyzing
         for iter in mapping.iter() {
-            if iter.0 == "definitions" {
+            if iter.0 == "definitions" || iter.0 == "$defs" {
                 Self::prepare_definitions(
                     &iter.1,
                     &mut self.map_of_basetype,
                     &mut self.map_of_struct,
+                    &mut self.map_of_constraint,
                 );
             }
         }
@@ -359,6 +632,7 @@ impl Analyzer {
                     &title_must_exist.to_string(),
                     &self.map_of_struct,
                     &mut current_member,
+                    &mut self.map_of_constraint,
                 );
             } else if iter.0 == "anyOf" {
                 self.map_of_enum.insert(title_must_exist.to_string(), true);
@@ -397,6 +671,7 @@ impl Analyzer {
                             &name.to_string(),
                             &self.map_of_struct,
                             &mut current_member,
+                            &mut self.map_of_constraint,
                         );
                     }
                 }
@@ -495,3 +770,218 @@ pub fn load_data_from_file(path: &str) -> Result<Vec<u8>, String> {
     };
     Ok(data)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    // schema_dir writes the schema files to a fresh folder of the test, the shared types next to the schema folder
+    fn schema_dir(test: &str, files: &[(&str, serde_json::Value)]) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("analyzer-{}-{}", test, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("schema")).unwrap();
+        std::fs::create_dir_all(dir.join("shared")).unwrap();
+        for (name, schema) in files {
+            std::fs::write(dir.join(name), schema.to_string()).unwrap();
+        }
+        dir
+    }
+
+    fn members(analyzer: &Analyzer, msg: &str, variant: &str) -> Vec<(String, String)> {
+        analyzer.map_of_member[msg][variant]
+            .iter()
+            .map(|m| (m.member_name.to_owned(), m.member_def.to_owned()))
+            .collect()
+    }
+
+    fn pairs(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(name, def)| (name.to_string(), def.to_string()))
+            .collect()
+    }
+
+    fn handle_msg() -> serde_json::Value {
+        json!({
+            "$schema": "http://json-schema.org/draft-07/schema#",
+            "title": "HandleMsg",
+            "anyOf": [
+                {
+                    "type": "object",
+                    "required": ["transfer"],
+                    "properties": {
+                        "transfer": {
+                            "type": "object",
+                            "required": ["amount", "recipient"],
+                            "properties": {
+                                "amount": { "$ref": "#/definitions/Uint128" },
+                                "recipient": { "$ref": "#/definitions/HumanAddr" },
+                                "memo": { "type": ["string", "null"] },
+                                "fees": { "type": "array", "items": { "$ref": "../shared/fee.json#/definitions/Fee" } }
+                            }
+                        }
+                    }
+                },
+                {
+                    "type": "object",
+                    "required": ["update_config"],
+                    "properties": {
+                        "update_config": { "$ref": "#/definitions/Config" }
+                    }
+                },
+                {
+                    "type": "object",
+                    "required": ["set_limits"],
+                    "properties": {
+                        "set_limits": {
+                            "type": "object",
+                            "required": ["limits"],
+                            "properties": { "limits": { "$ref": "../shared/limits.json" } }
+                        }
+                    }
+                }
+            ],
+            "definitions": {
+                "Config": {
+                    "type": "object",
+                    "required": ["owner", "rate"],
+                    "properties": {
+                        "owner": { "$ref": "#/definitions/HumanAddr" },
+                        "rate": { "type": "integer", "format": "uint16", "minimum": 0.0, "maximum": 1000.0 }
+                    }
+                },
+                "HumanAddr": { "type": "string" },
+                "Uint128": { "type": "string" }
+            }
+        })
+    }
+
+    fn shared_files() -> Vec<(&'static str, serde_json::Value)> {
+        vec![
+            (
+                "shared/fee.json",
+                json!({
+                    "definitions": {
+                        "Fee": {
+                            "type": "object",
+                            "required": ["percent"],
+                            "properties": {
+                                "percent": { "type": "integer", "format": "uint8", "maximum": 100.0 }
+                            }
+                        }
+                    }
+                }),
+            ),
+            (
+                "shared/limits.json",
+                json!({
+                    "type": "object",
+                    "required": ["min", "max"],
+                    "properties": {
+                        "min": { "type": "integer", "format": "int32", "exclusiveMinimum": -10 },
+                        "max": { "type": "number", "maximum": 5.5, "exclusiveMaximum": true }
+                    }
+                }),
+            ),
+        ]
+    }
+
+    #[test]
+    fn resolves_refs() {
+        let mut files = shared_files();
+        files.push(("schema/handle_msg.json", handle_msg()));
+        let dir = schema_dir("refs", &files);
+        let mut analyzer = Analyzer::default();
+        assert!(analyzer.try_load_json_schema(dir.join("schema")));
+
+        assert_eq!(analyzer.map_of_enum.get("HandleMsg"), Some(&true));
+        assert_eq!(
+            members(&analyzer, "HandleMsg", "transfer"),
+            pairs(&[
+                ("amount", "Uint128"),
+                ("fees", "[Fee]"),
+                ("memo", "string?"),
+                ("recipient", "HumanAddr"),
+            ])
+        );
+        // a variant that is a $ref takes the members of the struct
+        assert_eq!(
+            members(&analyzer, "HandleMsg", "update_config"),
+            pairs(&[("owner", "HumanAddr"), ("rate", "integer")])
+        );
+        assert_eq!(analyzer.map_of_basetype["Uint128"], "string");
+        // definitions of another file, and a whole file named by its stem
+        assert!(analyzer.map_of_struct["Fee"].contains_key("percent"));
+        assert_eq!(
+            members(&analyzer, "HandleMsg", "set_limits"),
+            pairs(&[("limits", "limits")])
+        );
+        assert_eq!(analyzer.map_of_struct["limits"]["min"], "integer");
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn resolves_bounds() {
+        let mut files = shared_files();
+        files.push(("schema/handle_msg.json", handle_msg()));
+        let dir = schema_dir("bounds", &files);
+        let mut analyzer = Analyzer::default();
+        assert!(analyzer.try_load_json_schema(dir.join("schema")));
+        let constraint = |name: &str| analyzer.map_of_constraint[name].clone();
+
+        // bounds of a struct follow the variant that refers to it
+        let rate = constraint("update_config.rate");
+        assert!(rate.integer);
+        assert_eq!((rate.minimum, rate.maximum), (Some(0.0), Some(1000.0)));
+        assert_eq!(rate.check(&json!(1000)), Ok(()));
+        assert!(rate
+            .check(&json!(1001))
+            .unwrap_err()
+            .contains("maximum 1000"));
+        assert!(rate.check(&json!(-1)).unwrap_err().contains("minimum 0"));
+        assert!(rate
+            .check(&json!(1.5))
+            .unwrap_err()
+            .contains("not an integer"));
+        assert!(rate
+            .check(&json!("7"))
+            .unwrap_err()
+            .contains("not a number"));
+
+        // the format fills in the bound the schema leaves out
+        let percent = constraint("Fee.percent");
+        assert_eq!((percent.minimum, percent.maximum), (Some(0.0), Some(100.0)));
+        let min = constraint("limits.min");
+        assert_eq!(min.exclusive_minimum, Some(-10.0));
+        assert_eq!(min.maximum, Some(i32::MAX as f64));
+        assert!(min
+            .check(&json!(-10))
+            .unwrap_err()
+            .contains("greater than -10"));
+        assert_eq!(min.check(&json!(-9)), Ok(()));
+
+        // draft 4 exclusive flags turn the bound exclusive
+        let max = constraint("limits.max");
+        assert!(!max.integer);
+        assert_eq!((max.maximum, max.exclusive_maximum), (None, Some(5.5)));
+        assert_eq!(max.check(&json!(5.4)), Ok(()));
+        assert!(max
+            .check(&json!(5.5))
+            .unwrap_err()
+            .contains("less than 5.5"));
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn string_enums() {
+        let constraint =
+            Constraint::from_schema(&json!({ "type": "string", "enum": ["yes", "no"] })).unwrap();
+        assert_eq!(constraint.check(&json!("yes")), Ok(()));
+        assert_eq!(
+            constraint.check(&json!("maybe")),
+            Err("\"maybe\" is not one of yes | no".to_string())
+        );
+        assert!(Constraint::from_schema(&json!({ "type": "string" })).is_none());
+    }
+}
@@ -0,0 +1,660 @@
+// chain runs the messages returned by contracts, moves balances and creates new instances of loaded codes. The
+// binary keeps its contracts in the global config and the embedded Simulator in a registry of its thread, both
+// implement Chain so contracts behave the same in either
+use crate::contract_vm::engine::{error_code, ContractInstance, MAX_QUERY_DEPTH};
+use crate::contract_vm::ibc::{IbcModule, IbcMsg, Packet, PacketState};
+use crate::contract_vm::mock::{self, MockStorage, SpecialMsg, SpecialQuery};
+use crate::contract_vm::querier::{BankQuerier, GovQuerier};
+use crate::contract_vm::receipt::MessageReceipt;
+use cosmwasm_std::testing::MockQuerierCustomHandlerResult;
+use cosmwasm_std::{
+    BankMsg, Binary, Coin, ContractResult, CosmosMsg, HumanAddr, MessageInfo, QuerierResult,
+    SystemError, SystemResult, WasmMsg, WasmQuery,
+};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// depth of the contract queries in progress, a query made by a queried contract is one level deeper
+static QUERY_DEPTH: AtomicUsize = AtomicUsize::new(0);
+
+pub trait Chain {
+    // engine returns a loaded contract by address or alias
+    fn engine(&mut self, contract_addr: &str) -> Option<&mut ContractInstance>;
+
+    fn engines(&mut self) -> Vec<&mut ContractInstance>;
+
+    // bank returns the balances of accounts and contracts
+    fn bank(&mut self) -> BankQuerier;
+
+    // set_bank replaces the balances, every contract keeps a copy so its bank queries see them
+    fn set_bank(&mut self, bank: BankQuerier) {
+        for engine in self.engines() {
+            engine
+                .with_querier(|querier| {
+                    querier.set_bank(bank.clone());
+                    Ok(())
+                })
+                .unwrap();
+        }
+    }
+
+    // code returns the wasm file of a code id, code ids start from 1
+    fn code(&self, code_id: u64) -> Option<String>;
+
+    // instance_address returns a free address for a new instance of the wasm file, the label when it is given
+    fn instance_address(&mut self, wasm_file: &str, label: Option<&str>) -> String;
+
+    // load compiles the wasm file at the address with empty storage
+    fn load(&mut self, wasm_file: &str, contract_addr: &str) -> Result<(), String>;
+
+    fn remove(&mut self, contract_addr: &str);
+
+    // resolve_address returns the address a name stands for
+    fn resolve_address(&self, addr: &str) -> String {
+        addr.to_string()
+    }
+
+    fn gov(&mut self) -> &mut GovQuerier;
+
+    fn ibc(&mut self) -> &mut IbcModule;
+}
+
+pub fn is_error_result(result: &str) -> bool {
+    match serde_json::from_str::<serde_json::Value>(result) {
+        Ok(value) => value.get("error").is_some(),
+        Err(_) => false,
+    }
+}
+
+// message_receipt is the receipt of a message without a contract call
+pub fn message_receipt(kind: &str, target: &str, result: Result<String, String>) -> MessageReceipt {
+    MessageReceipt {
+        kind: kind.to_string(),
+        target: target.to_string(),
+        failed: result.is_err(),
+        result: result.unwrap_or_else(|e| e),
+        receipt: None,
+    }
+}
+
+// update_bank changes the balances, then copies them to every contract so bank queries see sends made earlier in
+// the call, a failed func leaves all balances as they were
+pub fn update_bank<C: Chain, T>(
+    chain: &mut C,
+    func: impl FnOnce(&mut BankQuerier) -> Result<T, String>,
+) -> Result<T, String> {
+    let mut bank = chain.bank();
+    let result = func(&mut bank)?;
+    chain.set_bank(bank);
+    Ok(result)
+}
+
+// transfer moves the funds sent with a message to the called contract before it runs
+pub fn transfer<C: Chain>(
+    chain: &mut C,
+    sender_addr: &str,
+    contract_addr: &HumanAddr,
+    send: &[Coin],
+) -> Result<(), String> {
+    match send.is_empty() {
+        true => Ok(()),
+        false => bank_send(chain, sender_addr, contract_addr, send).map(|_| ()),
+    }
+}
+
+// bank_send moves coins from the balance of the sender to the recipient, coins sent to an alias go to the contract
+// it names
+pub fn bank_send<C: Chain>(
+    chain: &mut C,
+    sender_addr: &str,
+    to_address: &HumanAddr,
+    amount: &[Coin],
+) -> Result<String, String> {
+    let sender = HumanAddr::from(sender_addr);
+    let to_address = &HumanAddr::from(chain.resolve_address(to_address.as_str()));
+    update_bank(chain, |bank| {
+        for coin in amount {
+            bank.debit(&sender, coin)?;
+            bank.credit(to_address, coin);
+        }
+        Ok(())
+    })?;
+    Ok(format!(
+        "sent {} to {}",
+        amount
+            .iter()
+            .map(|coin| format!("{}{}", coin.amount, coin.denom))
+            .collect::<Vec<String>>()
+            .join(","),
+        to_address
+    ))
+}
+
+// State is a copy of everything a call can change, failed calls and snapshot restores rewind to it
+#[derive(Clone)]
+pub struct State {
+    // storage of every loaded contract by address
+    pub contracts: HashMap<String, MockStorage>,
+    bank: BankQuerier,
+    gov: GovQuerier,
+    packets: Vec<Packet>,
+}
+
+pub fn snapshot<C: Chain>(chain: &mut C) -> State {
+    let contracts = chain
+        .engines()
+        .into_iter()
+        .map(|engine| {
+            let storage = engine.with_storage(|storage| Ok(storage.clone())).unwrap();
+            (engine.env.contract.address.to_string(), storage)
+        })
+        .collect();
+    State {
+        contracts,
+        bank: chain.bank(),
+        gov: chain.gov().clone(),
+        packets: chain.ibc().packets.clone(),
+    }
+}
+
+// revert rewinds to a snapshot, packets sent and votes cast since are dropped with the balances they changed.
+// Contracts loaded after the snapshot are removed, their addresses are returned
+pub fn revert<C: Chain>(chain: &mut C, mut state: State) -> Vec<String> {
+    let loaded: Vec<String> = chain
+        .engines()
+        .iter()
+        .map(|engine| engine.env.contract.address.to_string())
+        .filter(|contract_addr| !state.contracts.contains_key(contract_addr))
+        .collect();
+    for contract_addr in loaded.iter() {
+        chain.remove(contract_addr);
+    }
+    for engine in chain.engines() {
+        if let Some(storage) = state.contracts.remove(engine.env.contract.address.as_str()) {
+            engine
+                .with_storage(|s| {
+                    *s = storage;
+                    Ok(())
+                })
+                .unwrap();
+        }
+    }
+    chain.set_bank(state.bank);
+    *chain.gov() = state.gov;
+    chain.ibc().packets = state.packets;
+    loaded
+}
+
+// deliver_packet sets the outcome of a pending packet, failed and timed out transfers are refunded to the sender.
+// Returns the refunded packet with the new balance of its sender
+pub fn deliver_packet<C: Chain>(
+    chain: &mut C,
+    sequence: u64,
+    state: PacketState,
+) -> Result<Option<(Packet, Vec<Coin>)>, String> {
+    let packet = match chain.ibc().deliver(sequence, state)? {
+        Some(packet) => packet.clone(),
+        None => return Ok(None),
+    };
+    let balance = update_bank(
+        chain,
+        |bank| Ok(bank.credit(&packet.sender, &packet.amount)),
+    )?;
+    Ok(Some((packet, balance)))
+}
+
+// instantiate loads a new instance of a code and runs its init with run, a failed init leaves neither the contract
+// nor the balances it changed behind. Returns the address and the init result, which carries the address as data
+// like the instantiate response of wasmd
+pub fn instantiate<C: Chain>(
+    chain: &mut C,
+    code_id: u64,
+    label: Option<&str>,
+    run: impl FnOnce(&mut C, &str) -> String,
+) -> Result<(String, String), String> {
+    let wasm_file = chain
+        .code(code_id)
+        .ok_or_else(|| format!("No such code: {}", code_id))?;
+    let contract_addr = chain.instance_address(&wasm_file, label);
+    chain.load(&wasm_file, &contract_addr)?;
+    let bank = chain.bank();
+    let result = run(chain, &contract_addr);
+    if is_error_result(&result) {
+        chain.remove(&contract_addr);
+        chain.set_bank(bank);
+        return Ok((contract_addr, result));
+    }
+    let result = match serde_json::from_str::<serde_json::Value>(&result) {
+        Ok(serde_json::Value::Object(mut value)) => {
+            value.insert(
+                "data".to_string(),
+                serde_json::Value::String(Binary::from(contract_addr.as_bytes()).to_base64()),
+            );
+            serde_json::Value::Object(value).to_string()
+        }
+        _ => result,
+    };
+    Ok((contract_addr, result))
+}
+
+// dispatch runs the messages of a response in order with the contract as sender and returns their receipts, later
+// messages are not dispatched once one fails
+pub fn dispatch<C: Chain>(
+    chain: &mut C,
+    sender_addr: &str,
+    messages: Vec<CosmosMsg<SpecialMsg>>,
+) -> Vec<MessageReceipt> {
+    let mut receipts: Vec<MessageReceipt> = vec![];
+    for msg in messages {
+        let receipt = match &msg {
+            // custom gov message, vote on behalf of the contract
+            CosmosMsg::Custom(SpecialMsg::Gov(gov_msg)) => message_receipt(
+                "gov",
+                "gov",
+                chain
+                    .gov()
+                    .execute(sender_addr, gov_msg)
+                    .map(|_| format!("{:?}", gov_msg)),
+            ),
+            // ics20 transfer, debit the contract then record the outgoing packet
+            CosmosMsg::Custom(SpecialMsg::Ibc(ibc_msg)) => {
+                let IbcMsg::Transfer { amount, .. } = ibc_msg;
+                let debited = update_bank(chain, |bank| {
+                    bank.debit(&HumanAddr::from(sender_addr), amount)
+                });
+                message_receipt(
+                    "ibc",
+                    "ibc",
+                    debited.map(|_| {
+                        let packet = chain.ibc().send_packet(sender_addr, ibc_msg);
+                        format!(
+                            "packet {} on {}: {}{} to {} ({:?})",
+                            packet.sequence,
+                            packet.channel_id,
+                            packet.amount.amount,
+                            packet.amount.denom,
+                            packet.to_address,
+                            packet.state
+                        )
+                    }),
+                )
+            }
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address, amount, ..
+            }) => message_receipt(
+                "bank",
+                to_address.as_str(),
+                bank_send(chain, sender_addr, to_address, amount),
+            ),
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr,
+                msg,
+                send,
+            }) => match chain.engine(contract_addr.as_str()).is_some() {
+                false => message_receipt(
+                    "wasm_execute",
+                    contract_addr.as_str(),
+                    Err(format!("No such contract: {}", contract_addr)),
+                ),
+                true => match transfer(chain, sender_addr, contract_addr, send) {
+                    Err(e) => message_receipt("wasm_execute", contract_addr.as_str(), Err(e)),
+                    Ok(_) => {
+                        let engine = chain.engine(contract_addr.as_str()).unwrap();
+                        let result = engine.handle_raw(
+                            msg.as_slice(),
+                            &MessageInfo {
+                                sender: HumanAddr::from(sender_addr),
+                                sent_funds: send.clone(),
+                            },
+                        );
+                        MessageReceipt {
+                            kind: "wasm_execute".to_string(),
+                            target: contract_addr.to_string(),
+                            failed: is_error_result(&result),
+                            result,
+                            receipt: Some(Box::new(engine.last_receipt.clone())),
+                        }
+                    }
+                },
+            },
+            CosmosMsg::Wasm(WasmMsg::Instantiate {
+                code_id, msg, send, ..
+            }) => {
+                let info = MessageInfo {
+                    sender: HumanAddr::from(sender_addr),
+                    sent_funds: send.clone(),
+                };
+                let instantiated =
+                    instantiate(
+                        chain,
+                        *code_id,
+                        None,
+                        |chain, contract_addr| match transfer(
+                            chain,
+                            sender_addr,
+                            &HumanAddr::from(contract_addr),
+                            send,
+                        ) {
+                            Err(e) => serde_json::json!({ "error": e }).to_string(),
+                            Ok(_) => chain
+                                .engine(contract_addr)
+                                .unwrap()
+                                .init_raw(msg.as_slice(), &info),
+                        },
+                    );
+                match instantiated {
+                    Err(e) => message_receipt("wasm_instantiate", &code_id.to_string(), Err(e)),
+                    Ok((contract_addr, result)) => {
+                        // a failed init removed the contract
+                        let receipt = chain
+                            .engine(&contract_addr)
+                            .map(|engine| Box::new(engine.last_receipt.clone()));
+                        MessageReceipt {
+                            kind: "wasm_instantiate".to_string(),
+                            target: contract_addr,
+                            failed: receipt.is_none() || is_error_result(&result),
+                            result,
+                            receipt,
+                        }
+                    }
+                }
+            }
+            msg => message_receipt(
+                "unsupported",
+                sender_addr,
+                // skipped rather than failed, the simulator can not run it
+                Ok(format!("message is not simulated: {:?}", msg)),
+            ),
+        };
+        let failed = receipt.failed;
+        receipts.push(receipt);
+        if failed {
+            break;
+        }
+    }
+    receipts
+}
+
+// query_wasm answers the smart and raw queries of contracts, smart queries nest up to MAX_QUERY_DEPTH
+pub fn query_wasm<C: Chain>(chain: &mut C, request: &WasmQuery) -> QuerierResult {
+    match request {
+        WasmQuery::Smart { contract_addr, msg } => match chain.engine(contract_addr.as_str()) {
+            None => SystemResult::Err(SystemError::NoSuchContract {
+                addr: contract_addr.to_owned(),
+            }),
+            Some(_) if QUERY_DEPTH.load(Ordering::SeqCst) >= unsafe { MAX_QUERY_DEPTH } => {
+                SystemResult::Err(SystemError::InvalidRequest {
+                    error: format!(
+                        "query depth limit of {} exceeded when querying {}",
+                        unsafe { MAX_QUERY_DEPTH },
+                        contract_addr
+                    ),
+                    request: msg.to_owned(),
+                })
+            }
+            Some(engine) => {
+                QUERY_DEPTH.fetch_add(1, Ordering::SeqCst);
+                let result = engine.executor.query(&engine.env, msg.as_slice());
+                QUERY_DEPTH.fetch_sub(1, Ordering::SeqCst);
+
+                // response can not unwrap, so it is empty
+                match result {
+                    Ok(response) => SystemResult::Ok(response),
+                    Err(err) => SystemResult::Err(SystemError::InvalidResponse {
+                        error: format!("[{}] {}", error_code(&err), err),
+                        response: Binary::from([]),
+                    }),
+                }
+            }
+        },
+        // raw reads go straight to the storage of the contract, missing keys are empty like on chain
+        WasmQuery::Raw { contract_addr, key } => match chain.engine(contract_addr.as_str()) {
+            None => SystemResult::Err(SystemError::NoSuchContract {
+                addr: contract_addr.to_owned(),
+            }),
+            Some(engine) => {
+                let value = engine
+                    .with_storage(|storage| Ok(storage.data.get(key.as_slice()).cloned()))
+                    .unwrap();
+                SystemResult::Ok(ContractResult::Ok(Binary(value.unwrap_or_default())))
+            }
+        },
+    }
+}
+
+pub fn query_special<C: Chain>(
+    chain: &mut C,
+    request: &SpecialQuery,
+) -> MockQuerierCustomHandlerResult {
+    match request {
+        SpecialQuery::Gov(gov_query) => chain.gov().query(gov_query),
+        SpecialQuery::Chain(query) => mock::custom_query(query),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contract_vm::engine::{restore_block, save_block, BLOCK_HEIGHT};
+    use crate::contract_vm::querier::{GovMsg, VoteOption};
+
+    // Bank is a chain of accounts only, the bank, gov and ibc paths need no contract
+    #[derive(Default)]
+    struct Bank {
+        bank: BankQuerier,
+        gov: GovQuerier,
+        ibc: IbcModule,
+    }
+
+    impl Chain for Bank {
+        fn engine(&mut self, _contract_addr: &str) -> Option<&mut ContractInstance> {
+            None
+        }
+
+        fn engines(&mut self) -> Vec<&mut ContractInstance> {
+            vec![]
+        }
+
+        fn bank(&mut self) -> BankQuerier {
+            self.bank.clone()
+        }
+
+        fn set_bank(&mut self, bank: BankQuerier) {
+            self.bank = bank;
+        }
+
+        fn code(&self, _code_id: u64) -> Option<String> {
+            None
+        }
+
+        fn instance_address(&mut self, _wasm_file: &str, _label: Option<&str>) -> String {
+            String::new()
+        }
+
+        fn load(&mut self, wasm_file: &str, _contract_addr: &str) -> Result<(), String> {
+            Err(format!("can not load {}", wasm_file))
+        }
+
+        fn remove(&mut self, _contract_addr: &str) {}
+
+        fn gov(&mut self) -> &mut GovQuerier {
+            &mut self.gov
+        }
+
+        fn ibc(&mut self) -> &mut IbcModule {
+            &mut self.ibc
+        }
+    }
+
+    fn coin(amount: u128) -> Coin {
+        cosmwasm_std::coin(amount, "orai")
+    }
+
+    fn funded() -> Bank {
+        let mut chain = Bank::default();
+        chain.bank.credit(&HumanAddr::from("token"), &coin(100));
+        chain
+    }
+
+    fn balance(chain: &Bank) -> Vec<Coin> {
+        chain.bank.balance(&HumanAddr::from("token"))
+    }
+
+    fn transfer_msg(amount: u128) -> CosmosMsg<SpecialMsg> {
+        CosmosMsg::Custom(SpecialMsg::Ibc(IbcMsg::Transfer {
+            channel_id: "channel-0".to_string(),
+            to_address: "cosmos1receiver".to_string(),
+            amount: coin(amount),
+            timeout_block: None,
+            timeout_timestamp: None,
+        }))
+    }
+
+    #[test]
+    fn refunds_failed_transfers_once() {
+        let mut chain = funded();
+        let receipts = dispatch(&mut chain, "token", vec![transfer_msg(40)]);
+        assert!(!receipts[0].failed);
+        assert_eq!(balance(&chain), vec![coin(60)]);
+
+        let (packet, refunded) = deliver_packet(&mut chain, 1, PacketState::TimedOut)
+            .unwrap()
+            .unwrap();
+        assert_eq!(packet.sender, HumanAddr::from("token"));
+        assert_eq!(refunded, vec![coin(100)]);
+        // a packet has one outcome, it can not be refunded twice
+        assert!(deliver_packet(&mut chain, 1, PacketState::Failed("no".to_string())).is_err());
+        assert_eq!(balance(&chain), vec![coin(100)]);
+    }
+
+    #[test]
+    fn acknowledged_transfers_are_not_refunded() {
+        let mut chain = funded();
+        dispatch(&mut chain, "token", vec![transfer_msg(40)]);
+        assert!(deliver_packet(&mut chain, 1, PacketState::Acknowledged)
+            .unwrap()
+            .is_none());
+        assert_eq!(balance(&chain), vec![coin(60)]);
+    }
+
+    #[test]
+    fn unaffordable_transfers_send_no_packet() {
+        let mut chain = funded();
+        let receipts = dispatch(&mut chain, "token", vec![transfer_msg(101)]);
+        assert!(receipts[0].failed);
+        assert!(chain.ibc.packets.is_empty());
+        assert_eq!(balance(&chain), vec![coin(100)]);
+    }
+
+    #[test]
+    fn revert_drops_packets_of_a_failed_call() {
+        let mut chain = funded();
+        let state = snapshot(&mut chain);
+        // the transfer is followed by a failing message, the call fails and is reverted
+        let receipts = dispatch(
+            &mut chain,
+            "token",
+            vec![
+                transfer_msg(40),
+                CosmosMsg::Bank(BankMsg::Send {
+                    from_address: HumanAddr::from("token"),
+                    to_address: HumanAddr::from("alice"),
+                    amount: vec![coin(1000)],
+                }),
+            ],
+        );
+        assert!(receipts[1].failed);
+        revert(&mut chain, state);
+        assert_eq!(balance(&chain), vec![coin(100)]);
+        assert!(chain.ibc.packets.is_empty());
+
+        // a later timeout finds no packet to refund, the reverted debit is not credited again
+        assert!(deliver_packet(&mut chain, 1, PacketState::TimedOut).is_err());
+        assert_eq!(balance(&chain), vec![coin(100)]);
+    }
+
+    #[test]
+    fn revert_drops_votes_of_a_failed_call() {
+        let mut chain = funded();
+        let proposal_id = chain.gov.propose("upgrade", "v2");
+        let state = snapshot(&mut chain);
+        let vote = CosmosMsg::Custom(SpecialMsg::Gov(GovMsg::Vote {
+            proposal_id,
+            vote: VoteOption::Yes,
+        }));
+        assert!(!dispatch(&mut chain, "token", vec![vote])[0].failed);
+        assert_eq!(chain.gov.votes(proposal_id).len(), 1);
+
+        revert(&mut chain, state);
+        assert!(chain.gov.votes(proposal_id).is_empty());
+        assert_eq!(chain.gov.proposals.len(), 1);
+    }
+
+    #[test]
+    fn revert_restores_bank_sends() {
+        let mut chain = funded();
+        let state = snapshot(&mut chain);
+        let send = CosmosMsg::Bank(BankMsg::Send {
+            from_address: HumanAddr::from("token"),
+            to_address: HumanAddr::from("alice"),
+            amount: vec![coin(30)],
+        });
+        assert!(!dispatch(&mut chain, "token", vec![send])[0].failed);
+        assert_eq!(
+            chain.bank.balance(&HumanAddr::from("alice")),
+            vec![coin(30)]
+        );
+
+        revert(&mut chain, state);
+        assert_eq!(balance(&chain), vec![coin(100)]);
+        assert!(chain.bank.balance(&HumanAddr::from("alice")).is_empty());
+    }
+
+    #[test]
+    fn error_acknowledgements_refund_the_sender() {
+        let mut chain = funded();
+        dispatch(&mut chain, "token", vec![transfer_msg(40)]);
+        let (_, refunded) =
+            deliver_packet(&mut chain, 1, PacketState::Failed("denied".to_string()))
+                .unwrap()
+                .unwrap();
+        assert_eq!(refunded, vec![coin(100)]);
+        assert!(chain
+            .bank
+            .balance(&HumanAddr::from("cosmos1receiver"))
+            .is_empty());
+    }
+
+    #[test]
+    fn auto_acknowledged_transfers_can_not_time_out() {
+        let mut chain = funded();
+        chain.ibc.auto_ack = true;
+        dispatch(&mut chain, "token", vec![transfer_msg(40)]);
+        assert!(deliver_packet(&mut chain, 1, PacketState::TimedOut).is_err());
+        assert_eq!(balance(&chain), vec![coin(60)]);
+    }
+
+    #[test]
+    fn failed_tx_reverts_earlier_messages_and_block() {
+        let mut chain = funded();
+        // a signed tx is reverted with its block when one of its messages fails
+        let (state, block) = (snapshot(&mut chain), save_block());
+        let height = unsafe { BLOCK_HEIGHT };
+        assert!(!dispatch(&mut chain, "token", vec![transfer_msg(40)])[0].failed);
+        unsafe {
+            BLOCK_HEIGHT += 1;
+        }
+        let send = CosmosMsg::Bank(BankMsg::Send {
+            from_address: HumanAddr::from("token"),
+            to_address: HumanAddr::from("alice"),
+            amount: vec![coin(61)],
+        });
+        assert!(dispatch(&mut chain, "token", vec![send])[0].failed);
+
+        revert(&mut chain, state);
+        restore_block(block);
+        assert_eq!(unsafe { BLOCK_HEIGHT }, height);
+        assert_eq!(balance(&chain), vec![coin(100)]);
+        assert!(chain.ibc.packets.is_empty());
+    }
+}
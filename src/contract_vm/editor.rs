@@ -1,11 +1,15 @@
 use std::borrow::Cow::{self, Borrowed, Owned};
 
 use colored::*;
+use rustyline::config::Configurer;
 use rustyline::highlight::{Highlighter, MatchingBracketHighlighter};
 use rustyline::hint::{Hinter, HistoryHinter};
 use rustyline::validate::{self, MatchingBracketValidator, Validator};
-use rustyline::{CompletionType, Config, Context, Editor};
+use rustyline::{CompletionType, Config, Context, EditMode, Editor};
 use rustyline_derive::{Completer, Helper};
+use std::sync::atomic::Ordering;
+
+use crate::contract_vm::engine::CANCELLED;
 
 #[derive(Completer, Helper)]
 pub struct MyHelper {
@@ -69,6 +73,7 @@ pub struct TerminalEditor {
 
 impl TerminalEditor {
     pub fn new() -> Self {
+        // rustyline moves the cursor and deletes by grapheme, so multi-byte input is edited as a whole
         let config = Config::builder()
             .history_ignore_space(true)
             .completion_type(CompletionType::List)
@@ -89,8 +94,17 @@ impl TerminalEditor {
         }
     }
 
+    /// vi or emacs line editing and history navigation, emacs is the default
+    pub fn set_keybindings(&mut self, keybindings: &str) {
+        self.rl.set_edit_mode(match keybindings {
+            "vi" => EditMode::Vi,
+            _ => EditMode::Emacs,
+        })
+    }
+
+    /// drops the suggested entries, stored input stays searchable
     pub fn clear_history(&mut self) {
-        self.rl.clear_history()
+        self.update_history_entries(vec![]);
     }
 
     pub fn add_history_entry(&mut self, line: &str) -> bool {
@@ -99,15 +113,25 @@ impl TerminalEditor {
 
     /// this is permanent
     pub fn add_input_history_entry(&mut self, line: String) {
+        self.rl.add_history_entry(line.as_str());
         self.history_entries.push(line)
     }
 
     pub fn update_input_history_entry(&mut self) -> bool {
-        self.update_history_entries(self.history_entries.clone())
+        self.update_history_entries(vec![])
     }
 
+    /// stored input goes before the suggested entries so Ctrl-R searches it too, the history can only be cleared as a
+    /// whole so stored input is added again
     pub fn update_history_entries(&mut self, lines: Vec<String>) -> bool {
         self.rl.clear_history();
+        let stored = self
+            .history_entries
+            .iter()
+            .filter(|line| !lines.contains(line));
+        for line in stored {
+            self.rl.add_history_entry(line.as_str());
+        }
         for line in lines {
             if !self.rl.add_history_entry(line) {
                 return false;
@@ -117,6 +141,10 @@ impl TerminalEditor {
     }
 
     pub fn readline(&mut self, input_data: &mut String, store_input: bool) -> bool {
+        // the prompts left of a cancelled operation are skipped
+        if CANCELLED.load(Ordering::SeqCst) {
+            return false;
+        }
         let p = ">> ";
         self.rl.helper_mut().expect("No helper").colored_prompt = format!("{}", p.green().bold());
         let readline = self.rl.readline(&p);
@@ -130,8 +158,13 @@ impl TerminalEditor {
                 }
             }
 
-            // Ctrl + C to break
+            // Ctrl + C cancels the current operation, Ctrl + D quits
             Err(rustyline::error::ReadlineError::Interrupted) => {
+                println!("{}", "cancelled".yellow());
+                CANCELLED.store(true, Ordering::SeqCst);
+                return false;
+            }
+            Err(rustyline::error::ReadlineError::Eof) => {
                 std::process::exit(0);
             }
 
@@ -5,17 +5,23 @@ extern crate serde_json;
 use colored::*;
 
 use cosmwasm_std::{
-    Attribute, BlockInfo, Coin, ContractInfo, ContractResult, CosmosMsg, Empty, Env, HumanAddr,
-    MessageInfo, Uint128,
+    Attribute, BlockInfo, ContractInfo, ContractResult, CosmosMsg, Env, HumanAddr, MessageInfo,
 };
 
-use cosmwasm_vm::{Instance, InstanceOptions, Size};
+use cosmwasm_vm::{BackendError, VmError, VmResult};
 
+use crate::contract_vm::executor::{CosmwasmExecutor, Executor};
+use crate::contract_vm::mock::StorageOp;
+use crate::contract_vm::native::{self, NativeExecutor};
+use crate::contract_vm::profiler;
 use crate::contract_vm::querier::WasmHandler;
+use crate::contract_vm::receipt::{MessageReceipt, Receipt};
 use crate::contract_vm::singlepass_backend::ModuleCodeGenerator as SinglePassMCG;
+use crate::contract_vm::watcher::{readable_bytes, storage_key_name};
 use crate::contract_vm::{analyzer, mock};
-use cosmwasm_vm::testing::MockApi;
 use std::fmt::Write;
+use std::sync::atomic::AtomicBool;
+use std::sync::Mutex;
 use wasmer_middleware_common::metering;
 use wasmer_runtime_core::{
     backend::Compiler,
@@ -23,26 +29,199 @@ use wasmer_runtime_core::{
     module::Module,
 };
 
-const DEFAULT_CONTRACT_BALANCE: u64 = 10_000_000_000_000_000;
-const DEFAULT_GAS_LIMIT: u64 = 500_000_000_000_000;
 const COMPILE_GAS_LIMIT: u64 = 10_000_000_000;
-const DEFAULT_MEMORY_LIMIT: Size = Size::mebi(16);
-const DEFAULT_PRINT_DEBUG: bool = true;
 pub const DENOM: &str = "orai";
-pub const CHAIN_ID: &str = "Oraichain";
+// chain id of the simulated chain, set with --chain-id or :env set chain-id
+pub static mut CHAIN_ID: &str = "Oraichain";
+pub const BECH32_PREFIX: &str = "orai";
 const SCHEMA_FOLDER: &str = "schema";
 
-pub static mut BLOCK_HEIGHT: u64 = 12_345;
+const GENESIS_HEIGHT: u64 = 12_345;
+const GENESIS_TIME_NANOS: u64 = 1_571_797_419_879_305_533;
+
+pub static mut BLOCK_HEIGHT: u64 = GENESIS_HEIGHT;
+// nanoseconds between blocks, the block time stays at genesis time when it is 0
+pub static mut BLOCK_TIME: u64 = 0;
+// height and time in nanoseconds the block time is counted from, moved when the height or time is set
+static mut TIME_ANCHOR: (u64, u64) = (GENESIS_HEIGHT, GENESIS_TIME_NANOS);
+// annotate every step of a call for learning the execution model
+pub static mut EXPLAIN: bool = false;
+// list keys read and written after each call
+pub static mut STORAGE_REPORT: bool = false;
+// print the storage every call changed as a diff of the values before and after it
+pub static mut STORAGE_DIFF: bool = false;
+// failed calls revert the storage and balances of every contract like a failed transaction
+pub static mut ROLLBACK: bool = true;
+// print the executed instructions after each call
+pub static mut COUNT_INSTRUCTIONS: bool = false;
+// print the instructions of every wasm function after each call, the call runs a second time to count them
+pub static mut PROFILE: bool = false;
+lazy_static! {
+    // native builds of contracts run instead of their wasm, matched by file stem
+    pub static ref NATIVE_DEBUG: Mutex<Vec<String>> = Mutex::new(vec![]);
+}
+// set by Ctrl-C, the running call fails at its next storage access and open prompts are left
+pub static CANCELLED: AtomicBool = AtomicBool::new(false);
+pub const CALL_CANCELLED: &str = "call cancelled";
+// nested contract queries deeper than this fail, like the query stack limit of wasmd
+pub static mut MAX_QUERY_DEPTH: usize = 10;
+// gas limits of init and handle calls with their messages and of queries, nodes give smart queries a smaller limit
+pub static mut GAS_LIMIT: Option<u64> = None;
+pub static mut QUERY_GAS_LIMIT: Option<u64> = None;
+// gas left of the limited call in progress, the messages of a call run with what the call left of its limit
+static mut GAS_LEFT: Option<u64> = None;
 // callback handle for Handle Response, like send native balance, execute other smart contract
-pub type CallBackHandler = fn(&str, Vec<CosmosMsg>) -> Vec<Attribute>;
+pub type CallBackHandler = fn(&str, Vec<CosmosMsg<mock::SpecialMsg>>) -> Vec<MessageReceipt>;
+// messages and attributes of an init, handle or migrate response, or the contract error
+type ResponseResult = Result<(Vec<CosmosMsg<mock::SpecialMsg>>, Vec<Attribute>), String>;
 
 pub struct ContractInstance {
     pub module: Module,
-    pub instance: Instance<MockApi, mock::MockStorage, mock::MockQuerier<mock::SpecialQuery>>,
+    pub executor: Box<dyn Executor>,
     pub wasm_file: String,
     pub env: Env,
     pub analyzer: analyzer::Analyzer,
     pub handle_callback: CallBackHandler,
+    // gas used by the last call
+    pub last_gas_used: u64,
+    // wasm instructions executed by the last call, host functions excluded
+    pub last_instructions: u64,
+    // attributes and json messages of the last init or handle response
+    pub last_attributes: Vec<Attribute>,
+    pub last_messages: String,
+    // storage operations of the last call
+    pub last_storage_ops: Vec<StorageOp>,
+    // results, events and gas of the last call and the messages it dispatched
+    pub last_receipt: Receipt,
+}
+
+fn explain(step: &str, detail: String) {
+    if unsafe { EXPLAIN } {
+        println!(
+            "{} {:<12} {}",
+            "explain".magenta().bold(),
+            step.cyan(),
+            detail
+        );
+    }
+}
+
+// error_code classifies vm errors into stable codes that scripts can branch on
+pub fn error_code(err: &VmError) -> &'static str {
+    match err {
+        VmError::BackendErr {
+            source: BackendError::Unknown { msg: Some(msg) },
+            ..
+        } if msg.eq(CALL_CANCELLED) => "cancelled",
+        VmError::GasDepletion { .. }
+        | VmError::BackendErr {
+            source: BackendError::OutOfGas {},
+            ..
+        } => "out_of_gas",
+        // traps like unreachable, which is how a contract panic ends
+        VmError::RuntimeErr { .. } => "panic",
+        VmError::ParseErr { .. }
+        | VmError::SerializeErr { .. }
+        | VmError::ConversionErr { .. }
+        | VmError::CommunicationErr { .. } => "serialization",
+        VmError::BackendErr { .. } => "backend",
+        VmError::CompileErr { .. }
+        | VmError::InstantiationErr { .. }
+        | VmError::StaticValidationErr { .. }
+        | VmError::ResolveErr { .. }
+        | VmError::CacheErr { .. }
+        | VmError::IntegrityErr { .. } => "compile",
+        _ => "vm",
+    }
+}
+
+// error_result prints the error with its code and returns it as json
+fn error_result(code: &str, error: &str) -> String {
+    println!("[{}] {}", code.yellow().bold(), error.red());
+    serde_json::json!({ "error": error, "code": code }).to_string()
+}
+
+// block_time is the time of the current block in nanoseconds, derived from the height and the block time
+fn block_time() -> u64 {
+    unsafe {
+        let (height, time) = TIME_ANCHOR;
+        time + BLOCK_HEIGHT.saturating_sub(height) * BLOCK_TIME
+    }
+}
+
+// block_info is the current block
+pub fn block_info() -> BlockInfo {
+    let time = block_time();
+    BlockInfo {
+        height: unsafe { BLOCK_HEIGHT },
+        time: time / 1_000_000_000,
+        time_nanos: time % 1_000_000_000,
+        chain_id: unsafe { CHAIN_ID }.to_string(),
+    }
+}
+
+// set_block moves the chain to a later height or time, like a chain it can not go back
+pub fn set_block(height: Option<u64>, time_nanos: Option<u64>) -> Result<(), String> {
+    let current = block_info();
+    let height = height.unwrap_or(current.height);
+    if height < current.height {
+        return Err(format!(
+            "block height {} is before the current height {}",
+            height, current.height
+        ));
+    }
+    // the time moves with the skipped blocks unless it is given
+    let (anchor_height, anchor_time) = unsafe { TIME_ANCHOR };
+    let time = time_nanos.unwrap_or_else(|| {
+        anchor_time + height.saturating_sub(anchor_height) * unsafe { BLOCK_TIME }
+    });
+    if time < block_time() {
+        return Err(format!(
+            "block time {} is before the current time {}",
+            time / 1_000_000_000,
+            current.time
+        ));
+    }
+    unsafe {
+        BLOCK_HEIGHT = height;
+        TIME_ANCHOR = (height, time);
+    }
+    Ok(())
+}
+
+// BlockState is the height, time and chain id kept by named snapshots
+#[derive(Clone, Copy)]
+pub struct BlockState {
+    height: u64,
+    time_anchor: (u64, u64),
+    chain_id: &'static str,
+}
+
+pub fn save_block() -> BlockState {
+    unsafe {
+        BlockState {
+            height: BLOCK_HEIGHT,
+            time_anchor: TIME_ANCHOR,
+            chain_id: CHAIN_ID,
+        }
+    }
+}
+
+// restore_block moves the chain back to a saved block, unlike set_block the height and time can go back
+pub fn restore_block(block: BlockState) {
+    unsafe {
+        BLOCK_HEIGHT = block.height;
+        TIME_ANCHOR = block.time_anchor;
+        CHAIN_ID = block.chain_id;
+    }
+}
+
+// genesis starts the chain at another height or time, before any block is made
+pub fn genesis(height: Option<u64>, time_nanos: Option<u64>) {
+    unsafe {
+        BLOCK_HEIGHT = height.unwrap_or(BLOCK_HEIGHT);
+        TIME_ANCHOR = (BLOCK_HEIGHT, time_nanos.unwrap_or(TIME_ANCHOR.1));
+    }
 }
 
 fn compiler() -> Box<dyn Compiler> {
@@ -59,15 +238,10 @@ impl ContractInstance {
         wasm_file: &str,
         contract_addr: &str,
         query_wasm: WasmHandler,
+        query_special: mock::SpecialHandler,
         storage: &mock::MockStorage,
         handle_callback: CallBackHandler,
     ) -> Result<Self, String> {
-        let balances = &[Coin {
-            denom: DENOM.to_string(),
-            amount: Uint128::from(DEFAULT_CONTRACT_BALANCE),
-        }];
-        let deps = mock::new_mock(balances, contract_addr, query_wasm, storage.to_owned());
-
         let wasm = match analyzer::load_data_from_file(wasm_file) {
             Err(e) => return Err(e),
             Ok(code) => code,
@@ -88,25 +262,26 @@ impl ContractInstance {
             Ok(m) => m,
         };
 
-        let inst_options = InstanceOptions {
-            gas_limit: DEFAULT_GAS_LIMIT,
-            /// Memory limit in bytes. Use a value that is divisible by the Wasm page size 65536, e.g. full MiBs.
-            memory_limit: DEFAULT_MEMORY_LIMIT,
-            print_debug: DEFAULT_PRINT_DEBUG,
-        };
-        let inst = match cosmwasm_vm::Instance::from_code(wasm.as_slice(), deps, inst_options) {
-            Err(e) => {
-                println!(
-                    "cosmwasm_vm::Instance::from_code return error {}",
-                    e.to_string().red()
-                );
-                return Err("Instance from code execute failed!".to_string());
-            }
-            Ok(i) => i,
+        // the wasm is still compiled for the module info and the schema
+        let executor: Box<dyn Executor> = match native::library_for(wasm_file) {
+            Some(library) => Box::new(NativeExecutor::new(
+                &library,
+                contract_addr,
+                query_wasm,
+                query_special,
+                storage,
+            )?),
+            None => Box::new(CosmwasmExecutor::new(
+                wasm,
+                contract_addr,
+                query_wasm,
+                query_special,
+                storage,
+            )?),
         };
         return Ok(ContractInstance::make_instance(
             md,
-            inst,
+            executor,
             wasm_file.to_string(),
             contract_addr,
             handle_callback,
@@ -115,36 +290,160 @@ impl ContractInstance {
 
     fn make_instance(
         md: Module,
-        inst: cosmwasm_vm::Instance<
-            MockApi,
-            mock::MockStorage,
-            mock::MockQuerier<mock::SpecialQuery>,
-        >,
+        executor: Box<dyn Executor>,
         file: String,
         contract_addr: &str,
         handle_callback: CallBackHandler,
     ) -> ContractInstance {
         let alz = analyzer::from_json_schema(&file, SCHEMA_FOLDER);
 
-        unsafe {
-            ContractInstance {
-                module: md,
-                instance: inst,
-                wasm_file: file,
-                env: Env {
-                    block: BlockInfo {
-                        height: BLOCK_HEIGHT,
-                        time: 1_571_797_419,
-                        time_nanos: 879305533,
-                        chain_id: CHAIN_ID.to_string(),
-                    },
-                    contract: ContractInfo {
-                        address: HumanAddr::from(contract_addr),
-                    },
+        ContractInstance {
+            module: md,
+            executor,
+            wasm_file: file,
+            env: Env {
+                block: block_info(),
+                contract: ContractInfo {
+                    address: HumanAddr::from(contract_addr),
                 },
-                analyzer: alz,
-                handle_callback,
+            },
+            analyzer: alz,
+            handle_callback,
+            last_gas_used: 0,
+            last_instructions: 0,
+            last_attributes: vec![],
+            last_messages: String::new(),
+            last_storage_ops: vec![],
+            last_receipt: Receipt::default(),
+        }
+    }
+
+    // refresh_gas lets the executor top up gas before a call, a limited call gets exactly the gas it has left
+    pub fn refresh_gas(&mut self) {
+        self.executor.prepare(unsafe { GAS_LEFT });
+    }
+
+    // spend_gas takes the gas of a finished entry point from the limit of the call in progress
+    fn spend_gas(gas_used: u64) {
+        unsafe {
+            GAS_LEFT = GAS_LEFT.map(|gas| gas.saturating_sub(gas_used));
+        }
+    }
+
+    // with_storage runs func on the storage of the executor
+    pub fn with_storage<F: FnOnce(&mut mock::MockStorage) -> VmResult<T>, T>(
+        &mut self,
+        func: F,
+    ) -> VmResult<T> {
+        let mut func = Some(func);
+        let mut result = None;
+        self.executor.storage(&mut |storage| {
+            if let Some(func) = func.take() {
+                result = Some(func(storage));
             }
+        })?;
+        result.unwrap_or_else(|| Err(BackendError::unknown("storage is not available").into()))
+    }
+
+    // with_querier runs func on the querier of the executor
+    pub fn with_querier<F: FnOnce(&mut mock::MockQuerier<mock::SpecialQuery>) -> VmResult<T>, T>(
+        &mut self,
+        func: F,
+    ) -> VmResult<T> {
+        let mut func = Some(func);
+        let mut result = None;
+        self.executor.querier(&mut |querier| {
+            if let Some(func) = func.take() {
+                result = Some(func(querier));
+            }
+        })?;
+        result.unwrap_or_else(|| Err(BackendError::unknown("querier is not available").into()))
+    }
+
+    fn explain_entry_point(&mut self, func_type: &str, info: Option<&MessageInfo>) {
+        if unsafe { !EXPLAIN } {
+            return;
+        }
+        // drop operations of previous calls
+        self.with_storage(|storage| Ok(storage.take_ops())).unwrap();
+        explain(
+            "entry point",
+            format!(
+                "exported function `{}` of [{}] is called at block {} ({})",
+                func_type,
+                self.env.contract.address,
+                self.env.block.height,
+                match info {
+                    Some(info) => format!(
+                        "sender {} with {} coins",
+                        info.sender,
+                        info.sent_funds.len()
+                    ),
+                    None => "queries are read only and have no sender".to_string(),
+                }
+            ),
+        );
+    }
+
+    fn explain_storage(&mut self) {
+        if unsafe { !EXPLAIN } {
+            return;
+        }
+        let ops = self.with_storage(|storage| Ok(storage.ops())).unwrap();
+        if ops.is_empty() {
+            explain("storage", "storage is not touched".to_string());
+        }
+        for op in ops {
+            let detail = match op {
+                StorageOp::Read(key) => format!("read {}", storage_key_name(&key)),
+                StorageOp::Write(key) => format!("write {}", storage_key_name(&key)),
+                StorageOp::Remove(key) => format!("remove {}", storage_key_name(&key)),
+                StorageOp::Scan(start, end) => format!(
+                    "scan from {} to {}",
+                    start.map_or("start".to_string(), |k| readable_bytes(&k)),
+                    end.map_or("end".to_string(), |k| readable_bytes(&k))
+                ),
+                StorageOp::Next(iterator_id) => format!("next item of scan {}", iterator_id),
+            };
+            explain("storage", detail);
+        }
+    }
+
+    // report_storage lists the distinct keys a call has read, written and removed
+    fn report_storage(&mut self) {
+        let ops = self.with_storage(|storage| Ok(storage.take_ops())).unwrap();
+        let mut reads: Vec<String> = vec![];
+        let mut writes: Vec<String> = vec![];
+        let mut removes: Vec<String> = vec![];
+        for op in ops {
+            let (keys, name) = match op {
+                StorageOp::Read(key) => (&mut reads, storage_key_name(&key)),
+                StorageOp::Write(key) => (&mut writes, storage_key_name(&key)),
+                StorageOp::Remove(key) => (&mut removes, storage_key_name(&key)),
+                StorageOp::Scan(start, _) => (
+                    &mut reads,
+                    format!(
+                        "{}..",
+                        start.map_or(String::new(), |k| storage_key_name(&k))
+                    ),
+                ),
+                StorageOp::Next(_) => continue,
+            };
+            if !keys.contains(&name) {
+                keys.push(name);
+            }
+        }
+        println!("{}", "storage access".blue().bold());
+        for (label, keys) in [("read", reads), ("written", writes), ("removed", removes)].iter() {
+            println!(
+                "{}{:<8}: {}",
+                analyzer::INDENT,
+                label,
+                match keys.is_empty() {
+                    true => "-".to_string(),
+                    false => keys.join(", ").yellow().to_string(),
+                }
+            );
         }
     }
 
@@ -180,6 +479,111 @@ impl ContractInstance {
         }
     }
 
+    fn dump_messages(messages: &[MessageReceipt]) {
+        ContractInstance::dump_results(
+            &messages
+                .iter()
+                .map(|msg| Attribute {
+                    key: msg.target.to_owned(),
+                    value: msg.result.to_owned(),
+                })
+                .collect(),
+        );
+    }
+
+    // receipt keeps the receipt of a finished call and returns its result
+    fn receipt(
+        &mut self,
+        result: String,
+        gas_used: u64,
+        attributes: &[Attribute],
+        messages: Vec<MessageReceipt>,
+    ) -> String {
+        self.last_receipt = Receipt::new(
+            self.env.contract.address.as_str(),
+            result.to_owned(),
+            gas_used,
+            attributes,
+            messages,
+        );
+        result
+    }
+
+    // commit_state hashes the storage and saves it as a version of the iavl tree at the current height, only
+    // successful calls that can write are versioned, failed calls are reverted and queries change nothing
+    fn commit_state(&mut self) {
+        let height = self.env.block.height;
+        let (state_hash, iavl_root) = self
+            .with_storage(|storage| Ok((storage.state_hash(), storage.commit(height))))
+            .unwrap();
+        self.last_receipt.state_hash = state_hash;
+        self.last_receipt.iavl_root = iavl_root;
+    }
+
+    // finish_response dispatches the messages of a successful init, handle or migrate and records the receipt, a
+    // failed message fails the call
+    fn finish_response(
+        &mut self,
+        entry_point: &str,
+        gas_used: u64,
+        result: VmResult<ResponseResult>,
+    ) -> String {
+        match result {
+            Ok(Ok((messages, attributes))) => {
+                self.explain_storage();
+                explain(
+                    "response",
+                    format!(
+                        "{} messages are dispatched, {} attributes are emitted",
+                        messages.len(),
+                        attributes.len()
+                    ),
+                );
+                self.last_attributes = attributes.clone();
+                self.last_messages = serde_json::to_string(&messages).unwrap_or_default();
+                let messages = (self.handle_callback)(self.env.contract.address.as_str(), messages);
+                ContractInstance::dump_messages(&messages);
+                // like on chain a failing message fails the whole call, the caller reverts the state
+                let failed =
+                    messages
+                        .iter()
+                        .enumerate()
+                        .find(|(_, msg)| msg.failed)
+                        .map(|(index, msg)| {
+                            format!("message {} to {} failed: {}", index, msg.target, msg.result)
+                        });
+                if let Some(failed) = failed {
+                    let error = error_result("message_failed", &failed);
+                    return self.receipt(error, gas_used, &attributes, messages);
+                }
+
+                // simulate block height increase for later expire check
+                unsafe {
+                    BLOCK_HEIGHT += 1;
+                }
+                self.env.block = block_info();
+
+                let result = self.receipt(
+                    format!(r#"{{"message":"{} succeeded"}}"#, entry_point),
+                    gas_used,
+                    &attributes,
+                    messages,
+                );
+                self.commit_state();
+                result
+            }
+            Ok(Err(err)) => {
+                self.receipt(error_result("contract_error", &err), gas_used, &[], vec![])
+            }
+            Err(err) => self.receipt(
+                error_result(error_code(&err), &err.to_string()),
+                gas_used,
+                &[],
+                vec![],
+            ),
+        }
+    }
+
     fn dump_result(key: &str, value: &[u8], len: usize) -> String {
         let mut value_str = match std::str::from_utf8(value) {
             Ok(result) => result.to_string(),
@@ -207,37 +611,21 @@ impl ContractInstance {
     }
 
     pub fn init_raw(&mut self, param: &[u8], info: &MessageInfo) -> String {
-        let result =
-            cosmwasm_vm::call_init::<_, _, _, Empty>(&mut self.instance, &self.env, info, param);
-
-        match result {
-            Ok(response) => match response {
-                ContractResult::Ok(val) => {
-                    ContractInstance::dump_results(&(self.handle_callback)(
-                        self.env.contract.address.as_str(),
-                        val.messages,
-                    ));
-
-                    ContractInstance::dump_results(&val.attributes);
-
-                    // simulate block height increase for later expire check
-                    unsafe {
-                        BLOCK_HEIGHT += 1;
-                        self.env.block.height = BLOCK_HEIGHT;
-                    }
-
-                    r#"{"message":"init succeeded"}"#.to_string()
-                }
-                ContractResult::Err(err) => {
-                    println!("{}", err.red());
-                    format!(r#"{{"error":"{}"}}"#, err)
-                }
-            },
-            Err(err) => {
-                println!("{}", err.to_string().red());
-                format!(r#"{{"error":"{}"}}"#, err.to_string())
-            }
-        }
+        self.refresh_gas();
+        // contracts loaded earlier or called by other contracts see the current block too
+        self.env.block = block_info();
+        self.explain_entry_point("init", Some(info));
+        let gas_init = self.executor.gas_left();
+        let result = self.executor.init(&self.env, info, param);
+        let gas_used = gas_init.saturating_sub(self.executor.gas_left());
+        ContractInstance::spend_gas(gas_used);
+
+        let result = result.map(|response| {
+            response
+                .into_result()
+                .map(|val| (val.messages, val.attributes))
+        });
+        self.finish_response("init", gas_used, result)
     }
 
     pub fn handle(&mut self, param: &str, info: &MessageInfo) -> String {
@@ -245,38 +633,42 @@ impl ContractInstance {
     }
 
     pub fn handle_raw(&mut self, param: &[u8], info: &MessageInfo) -> String {
-        let result =
-            cosmwasm_vm::call_handle::<_, _, _, Empty>(&mut self.instance, &self.env, info, param);
-
-        match result {
-            Ok(response) => match response {
-                ContractResult::Ok(val) => {
-                    ContractInstance::dump_results(&(self.handle_callback)(
-                        self.env.contract.address.as_str(),
-                        val.messages,
-                    ));
-
-                    ContractInstance::dump_results(&val.attributes);
-
-                    // simulate block height increase for later expire check
-                    unsafe {
-                        BLOCK_HEIGHT += 1;
-                        self.env.block.height = BLOCK_HEIGHT;
-                    }
+        self.refresh_gas();
+        self.env.block = block_info();
+        self.explain_entry_point("handle", Some(info));
+        let gas_init = self.executor.gas_left();
+        let result = self.executor.handle(&self.env, info, param);
+        let gas_used = gas_init.saturating_sub(self.executor.gas_left());
+        ContractInstance::spend_gas(gas_used);
+
+        let result = result.map(|response| {
+            response
+                .into_result()
+                .map(|val| (val.messages, val.attributes))
+        });
+        self.finish_response("handle", gas_used, result)
+    }
 
-                    r#"{"message":"handle succeeded"}"#.to_string()
-                }
-                ContractResult::Err(err) => {
-                    println!("{}", err.red());
-                    format!(r#"{{"error":"{}"}}"#, err)
-                }
-            },
+    pub fn migrate(&mut self, param: &str, info: &MessageInfo) -> String {
+        self.migrate_raw(param.as_bytes(), info)
+    }
 
-            Err(err) => {
-                println!("{}", err.to_string().red());
-                format!(r#"{{"error":"{}"}}"#, err.to_string())
-            }
-        }
+    // migrate runs the loaded code, a new code is swapped in by the caller before
+    pub fn migrate_raw(&mut self, param: &[u8], info: &MessageInfo) -> String {
+        self.refresh_gas();
+        self.env.block = block_info();
+        self.explain_entry_point("migrate", Some(info));
+        let gas_init = self.executor.gas_left();
+        let result = self.executor.migrate(&self.env, info, param);
+        let gas_used = gas_init.saturating_sub(self.executor.gas_left());
+        ContractInstance::spend_gas(gas_used);
+
+        let result = result.map(|response| {
+            response
+                .into_result()
+                .map(|val| (val.messages, val.attributes))
+        });
+        self.finish_response("migrate", gas_used, result)
     }
 
     pub fn query(&mut self, param: &str) -> String {
@@ -284,28 +676,42 @@ impl ContractInstance {
     }
 
     pub fn query_raw(&mut self, param: &[u8]) -> String {
+        self.refresh_gas();
+        self.env.block = block_info();
+        self.explain_entry_point("query", None);
         // check param if it is custom, we will try to check for oracle special query to implement, otherwise forward
         // to virtual machine
-        let result = cosmwasm_vm::call_query(&mut self.instance, &self.env, param);
+        let gas_init = self.executor.gas_left();
+        let result = self.executor.query(&self.env, param);
+        let gas_used = gas_init.saturating_sub(self.executor.gas_left());
+        ContractInstance::spend_gas(gas_used);
 
         match result {
             Ok(response) => match response {
                 ContractResult::Ok(val) => {
-                    ContractInstance::dump_result("query data", val.as_slice(), 10)
+                    self.explain_storage();
+                    explain(
+                        "response",
+                        format!("{} bytes of query data are returned", val.len()),
+                    );
+                    let data = ContractInstance::dump_result("query data", val.as_slice(), 10);
+                    self.receipt(data, gas_used, &[], vec![])
                 }
                 ContractResult::Err(err) => {
-                    println!("{}", err.red());
-                    format!(r#"{{"error":"{}"}}"#, err)
+                    self.receipt(error_result("contract_error", &err), gas_used, &[], vec![])
                 }
             },
-            Err(err) => {
-                println!("{}", err.to_string().red());
-                format!(r#"{{"error":"{}"}}"#, err.to_string())
-            }
+            Err(err) => self.receipt(
+                error_result(error_code(&err), &err.to_string()),
+                gas_used,
+                &[],
+                vec![],
+            ),
         }
     }
 
-    pub fn call(&mut self, func_type: &str, param: &str, info: &MessageInfo) -> String {
+    // call runs an entry point and returns the receipt of the call with the messages it dispatched
+    pub fn call(&mut self, func_type: &str, param: &str, info: &MessageInfo) -> Receipt {
         println!();
         println!("===========================call started===========================");
         println!(
@@ -313,25 +719,142 @@ impl ContractInstance {
             func_type.green().bold(),
             param.yellow()
         );
-        let gas_init = self.instance.get_gas_left();
-        let res = match func_type {
+        // the vm stops the call once it used its limit, init and handle share theirs with the calls of their messages
+        unsafe {
+            GAS_LEFT = match func_type {
+                "query" => QUERY_GAS_LIMIT,
+                _ => GAS_LIMIT,
+            };
+        }
+        // refresh before measuring so the gas used is not negative
+        self.refresh_gas();
+        let gas_init = self.executor.gas_left();
+        // the metering middleware charges 1 per wasm operator, host functions are charged externally
+        let instructions_init = self.executor.gas_report().used_internally;
+        self.last_attributes.clear();
+        self.last_messages.clear();
+        // only operations of this call are explained and reported
+        self.with_storage(|storage| Ok(storage.take_ops())).unwrap();
+        explain(
+            "deserialize",
+            match serde_json::from_str::<serde_json::Value>(param) {
+                Ok(serde_json::Value::Object(msg)) if msg.len() == 1 => format!(
+                    "message is json, the contract matches variant `{}` of its message enum",
+                    msg.keys().next().unwrap()
+                ),
+                Ok(_) => "message is json, the contract reads it as its message struct".to_string(),
+                Err(e) => format!(
+                    "message is not json, the contract will fail to parse it: {}",
+                    e
+                ),
+            },
+        );
+        // the profiled call starts from the same storage and block
+        let profiled = match unsafe { PROFILE } {
+            true => Some((
+                self.with_storage(|storage| Ok(storage.clone())).unwrap(),
+                Env {
+                    block: block_info(),
+                    contract: self.env.contract.clone(),
+                },
+            )),
+            false => None,
+        };
+        // the entry points keep the receipt of the call
+        match func_type {
             "init" => self.init(param, info),
             "handle" => self.handle(param, info),
+            "migrate" => self.migrate(param, info),
             "query" => self.query(param),
             _ => {
                 println!("wrong dispatcher call {}", func_type.green().bold());
-                format!(r#"{{"error":"wrong dispatcher call {}"}}"#, func_type)
+                self.receipt(
+                    format!(r#"{{"error":"wrong dispatcher call {}"}}"#, func_type),
+                    0,
+                    &[],
+                    vec![],
+                )
             }
         };
 
-        let gas_used = gas_init - self.instance.get_gas_left();
+        unsafe {
+            GAS_LEFT = None;
+        }
+
+        // successful calls also return their events, the attributes of the contract and of the contracts it called
+        if let Ok(serde_json::Value::Object(mut result)) =
+            serde_json::from_str::<serde_json::Value>(&self.last_receipt.result)
+        {
+            if func_type.ne("query")
+                && !result.contains_key("error")
+                && !self.last_receipt.events.is_empty()
+            {
+                result.insert(
+                    "events".to_string(),
+                    serde_json::json!(self.last_receipt.wasm_events()),
+                );
+                self.last_receipt.result = serde_json::Value::Object(result).to_string();
+                self.last_receipt.print_events();
+            }
+        }
+
+        let gas_used = gas_init.saturating_sub(self.executor.gas_left());
+        self.last_gas_used = gas_used;
+        self.last_instructions = self
+            .executor
+            .gas_report()
+            .used_internally
+            .saturating_sub(instructions_init);
+        self.last_storage_ops = self.with_storage(|storage| Ok(storage.ops())).unwrap();
+        if unsafe { STORAGE_REPORT } {
+            self.report_storage();
+        }
         println!(
             "{}   : {}",
             "gas used".blue().bold(),
             gas_used.to_string().yellow()
         );
+        let [reads, writes, removes, scans, nexts] = mock::count_ops(&self.last_storage_ops);
+        println!(
+            "{}: {} reads, {} writes, {} removes, {} scans, {} iterator steps",
+            "storage ops".blue().bold(),
+            reads.to_string().yellow(),
+            writes.to_string().yellow(),
+            removes.to_string().yellow(),
+            scans.to_string().yellow(),
+            nexts.to_string().yellow()
+        );
+        if !self.last_receipt.state_hash.is_empty() {
+            println!(
+                "{} : {}",
+                "state hash".blue().bold(),
+                self.last_receipt.state_hash.yellow()
+            );
+        }
+        if let Some(root) = self.last_receipt.iavl_root.as_ref() {
+            println!("{}  : {}", "iavl root".blue().bold(), root.yellow());
+        }
+        if unsafe { COUNT_INSTRUCTIONS } {
+            println!(
+                "{} : {}",
+                "instructions".blue().bold(),
+                self.last_instructions.to_string().yellow()
+            );
+        }
+        if !self.last_receipt.messages.is_empty() {
+            self.last_receipt.print();
+        }
+        if let Some((storage, env)) = profiled {
+            match self
+                .executor
+                .profile(func_type, &env, info, param.as_bytes(), &storage)
+            {
+                Ok(functions) => profiler::print_profile(&functions),
+                Err(e) => println!("{}: {}", "can not profile".yellow(), e.red()),
+            }
+        }
         println!("===========================call finished===========================");
         println!();
-        return res;
+        return self.last_receipt.clone();
     }
 }
@@ -0,0 +1,263 @@
+// execution backends, the repl, rest server and watcher only go through ContractInstance which calls the contract
+// through this trait, so another vm can be plugged in by implementing it
+use colored::*;
+
+use cosmwasm_std::{
+    Coin, ContractResult, Env, HandleResponse, InitResponse, MessageInfo, MigrateResponse,
+    QueryResponse, Uint128,
+};
+use cosmwasm_vm::{Backend, GasReport, Instance, InstanceOptions, Size, VmResult};
+
+use crate::contract_vm::engine::DENOM;
+use crate::contract_vm::mock::{
+    new_mock, MockApi, MockQuerier, MockStorage, SpecialHandler, SpecialMsg, SpecialQuery,
+};
+use crate::contract_vm::profiler::{FunctionProfile, Profiler};
+use crate::contract_vm::querier::WasmHandler;
+
+const DEFAULT_CONTRACT_BALANCE: u64 = 10_000_000_000_000_000;
+const DEFAULT_GAS_LIMIT: u64 = 500_000_000_000_000;
+// instance is recreated with full gas when gas left falls below this
+const GAS_REFRESH_THRESHOLD: u64 = DEFAULT_GAS_LIMIT / 10;
+const DEFAULT_MEMORY_LIMIT: Size = Size::mebi(16);
+const DEFAULT_PRINT_DEBUG: bool = true;
+
+// executors are Send so contracts can be compiled on loader threads
+pub trait Executor: Send {
+    // prepare runs before every call, backends can top up gas or reset per call state here. gas_limit is the gas the
+    // call may use when it is limited
+    fn prepare(&mut self, _gas_limit: Option<u64>) {}
+    fn init(
+        &mut self,
+        env: &Env,
+        info: &MessageInfo,
+        msg: &[u8],
+    ) -> VmResult<ContractResult<InitResponse<SpecialMsg>>>;
+    fn handle(
+        &mut self,
+        env: &Env,
+        info: &MessageInfo,
+        msg: &[u8],
+    ) -> VmResult<ContractResult<HandleResponse<SpecialMsg>>>;
+    fn migrate(
+        &mut self,
+        env: &Env,
+        info: &MessageInfo,
+        msg: &[u8],
+    ) -> VmResult<ContractResult<MigrateResponse<SpecialMsg>>>;
+    fn query(&mut self, env: &Env, msg: &[u8]) -> VmResult<ContractResult<QueryResponse>>;
+    fn gas_left(&self) -> u64;
+    // used_internally of the report counts executed wasm operators
+    fn gas_report(&self) -> GasReport;
+    // storage and querier take a callback so the trait stays object safe
+    fn storage(&mut self, func: &mut dyn FnMut(&mut MockStorage)) -> VmResult<()>;
+    fn querier(&mut self, func: &mut dyn FnMut(&mut MockQuerier<SpecialQuery>)) -> VmResult<()>;
+    // profile runs an entry point again on a copy of the storage and counts the operators of every function
+    fn profile(
+        &mut self,
+        _func_type: &str,
+        _env: &Env,
+        _info: &MessageInfo,
+        _msg: &[u8],
+        _storage: &MockStorage,
+    ) -> Result<Vec<FunctionProfile>, String> {
+        Err("this backend can not be profiled".to_string())
+    }
+}
+
+// new_backend creates the storage and querier of a contract with its initial balance
+pub fn new_backend(
+    contract_addr: &str,
+    query_wasm: WasmHandler,
+    query_special: SpecialHandler,
+    storage: &MockStorage,
+) -> Backend<MockApi, MockStorage, MockQuerier<SpecialQuery>> {
+    let balances = &[Coin {
+        denom: DENOM.to_string(),
+        amount: Uint128::from(DEFAULT_CONTRACT_BALANCE),
+    }];
+    new_mock(
+        balances,
+        contract_addr,
+        query_wasm,
+        query_special,
+        storage.to_owned(),
+    )
+}
+
+fn instance_options(gas_limit: u64) -> InstanceOptions {
+    InstanceOptions {
+        gas_limit,
+        /// Memory limit in bytes. Use a value that is divisible by the Wasm page size 65536, e.g. full MiBs.
+        memory_limit: DEFAULT_MEMORY_LIMIT,
+        print_debug: DEFAULT_PRINT_DEBUG,
+    }
+}
+
+// CosmwasmExecutor runs the contract in cosmwasm-vm
+pub struct CosmwasmExecutor {
+    instance: Instance<MockApi, MockStorage, MockQuerier<SpecialQuery>>,
+    // wasm code kept to recreate the instance without reading the file again
+    code: Vec<u8>,
+    contract_addr: String,
+    query_wasm: WasmHandler,
+    query_special: SpecialHandler,
+    // instrumented build of the code, compiled on the first profiled call
+    profiler: Option<Profiler>,
+}
+
+impl CosmwasmExecutor {
+    pub fn new(
+        code: Vec<u8>,
+        contract_addr: &str,
+        query_wasm: WasmHandler,
+        query_special: SpecialHandler,
+        storage: &MockStorage,
+    ) -> Result<Self, String> {
+        let deps = new_backend(contract_addr, query_wasm, query_special, storage);
+        let instance =
+            match Instance::from_code(code.as_slice(), deps, instance_options(DEFAULT_GAS_LIMIT)) {
+                Err(e) => {
+                    println!(
+                        "cosmwasm_vm::Instance::from_code return error {}",
+                        e.to_string().red()
+                    );
+                    return Err("Instance from code execute failed!".to_string());
+                }
+                Ok(i) => i,
+            };
+        Ok(CosmwasmExecutor {
+            instance,
+            code,
+            contract_addr: contract_addr.to_string(),
+            query_wasm,
+            query_special,
+            profiler: None,
+        })
+    }
+}
+
+impl Executor for CosmwasmExecutor {
+    // prepare recreates the instance with full gas once it runs low, storage and querier are moved over. The vm can
+    // not change the gas of a running instance, so a limited call gets a new instance with its limit
+    fn prepare(&mut self, gas_limit: Option<u64>) {
+        let gas_left = self.instance.get_gas_left();
+        match gas_limit {
+            Some(gas_limit) if gas_limit == gas_left => return,
+            None if gas_left > GAS_REFRESH_THRESHOLD => return,
+            _ => {}
+        }
+        let deps = new_mock(
+            &[],
+            &self.contract_addr,
+            self.query_wasm,
+            self.query_special,
+            MockStorage::default(),
+        );
+        let options = instance_options(gas_limit.unwrap_or(DEFAULT_GAS_LIMIT));
+        let inst = match Instance::from_code(&self.code, deps, options) {
+            Err(e) => {
+                println!("can not refresh gas: {}", e.to_string().red());
+                return;
+            }
+            Ok(i) => i,
+        };
+        let old = std::mem::replace(&mut self.instance, inst);
+        if let Some(mut backend) = old.recycle() {
+            self.instance
+                .with_storage(|storage| {
+                    std::mem::swap(storage, &mut backend.storage);
+                    Ok(())
+                })
+                .unwrap();
+            self.instance
+                .with_querier(|querier| {
+                    std::mem::swap(querier, &mut backend.querier);
+                    Ok(())
+                })
+                .unwrap();
+        }
+    }
+
+    fn init(
+        &mut self,
+        env: &Env,
+        info: &MessageInfo,
+        msg: &[u8],
+    ) -> VmResult<ContractResult<InitResponse<SpecialMsg>>> {
+        cosmwasm_vm::call_init(&mut self.instance, env, info, msg)
+    }
+
+    fn handle(
+        &mut self,
+        env: &Env,
+        info: &MessageInfo,
+        msg: &[u8],
+    ) -> VmResult<ContractResult<HandleResponse<SpecialMsg>>> {
+        cosmwasm_vm::call_handle(&mut self.instance, env, info, msg)
+    }
+
+    fn migrate(
+        &mut self,
+        env: &Env,
+        info: &MessageInfo,
+        msg: &[u8],
+    ) -> VmResult<ContractResult<MigrateResponse<SpecialMsg>>> {
+        cosmwasm_vm::call_migrate(&mut self.instance, env, info, msg)
+    }
+
+    fn query(&mut self, env: &Env, msg: &[u8]) -> VmResult<ContractResult<QueryResponse>> {
+        cosmwasm_vm::call_query(&mut self.instance, env, msg)
+    }
+
+    fn gas_left(&self) -> u64 {
+        self.instance.get_gas_left()
+    }
+
+    fn gas_report(&self) -> GasReport {
+        self.instance.create_gas_report()
+    }
+
+    fn storage(&mut self, func: &mut dyn FnMut(&mut MockStorage)) -> VmResult<()> {
+        self.instance.with_storage(|storage| {
+            func(storage);
+            Ok(())
+        })
+    }
+
+    fn querier(&mut self, func: &mut dyn FnMut(&mut MockQuerier<SpecialQuery>)) -> VmResult<()> {
+        self.instance.with_querier(|querier| {
+            func(querier);
+            Ok(())
+        })
+    }
+
+    // the profiled call sees the balances of the contract, its messages are not dispatched
+    fn profile(
+        &mut self,
+        func_type: &str,
+        env: &Env,
+        info: &MessageInfo,
+        msg: &[u8],
+        storage: &MockStorage,
+    ) -> Result<Vec<FunctionProfile>, String> {
+        if self.profiler.is_none() {
+            self.profiler = Some(Profiler::new(&self.code)?);
+        }
+        let mut backend = new_backend(
+            &self.contract_addr,
+            self.query_wasm,
+            self.query_special,
+            storage,
+        );
+        let bank = self
+            .instance
+            .with_querier(|querier| Ok(querier.bank()))
+            .map_err(|e| e.to_string())?;
+        backend.querier.set_bank(bank);
+        self.profiler
+            .as_mut()
+            .unwrap()
+            .run(func_type, env, info, msg, backend)
+    }
+}
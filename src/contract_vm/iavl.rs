@@ -0,0 +1,694 @@
+// iavl like merkle tree over the storage of a contract, nodes are hashed like cosmos iavl so roots and proofs can be
+// checked by the same light client code, the writes of a call are applied in key order like the cache store of a chain
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+// nodes are shared between versions, a write only copies the path to its leaf
+#[derive(Debug)]
+struct Node {
+    // leaf key, or the smallest key of the right subtree for inner nodes
+    key: Vec<u8>,
+    value: Option<Vec<u8>>,
+    version: i64,
+    height: i8,
+    size: i64,
+    left: Option<Arc<Node>>,
+    right: Option<Arc<Node>>,
+    hash: Vec<u8>,
+}
+
+fn sha256(data: &[u8]) -> Vec<u8> {
+    Sha256::digest(data).to_vec()
+}
+
+// encode_uvarint writes like binary.PutUvarint of go
+fn encode_uvarint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+// encode_varint writes like binary.PutVarint of go, zigzag encoded
+fn encode_varint(out: &mut Vec<u8>, value: i64) {
+    encode_uvarint(out, ((value << 1) ^ (value >> 63)) as u64);
+}
+
+fn encode_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    encode_uvarint(out, bytes.len() as u64);
+    out.extend_from_slice(bytes);
+}
+
+fn leaf_hash(key: &[u8], value: &[u8], version: i64) -> Vec<u8> {
+    let mut data = vec![];
+    encode_varint(&mut data, 0);
+    encode_varint(&mut data, 1);
+    encode_varint(&mut data, version);
+    encode_bytes(&mut data, key);
+    encode_bytes(&mut data, &sha256(value));
+    sha256(&data)
+}
+
+fn inner_hash(height: i8, size: i64, version: i64, left: &[u8], right: &[u8]) -> Vec<u8> {
+    let mut data = vec![];
+    encode_varint(&mut data, height as i64);
+    encode_varint(&mut data, size);
+    encode_varint(&mut data, version);
+    encode_bytes(&mut data, left);
+    encode_bytes(&mut data, right);
+    sha256(&data)
+}
+
+fn leaf(key: &[u8], value: &[u8], version: i64) -> Arc<Node> {
+    Arc::new(Node {
+        key: key.to_vec(),
+        value: Some(value.to_vec()),
+        version,
+        height: 0,
+        size: 1,
+        left: None,
+        right: None,
+        hash: leaf_hash(key, value, version),
+    })
+}
+
+fn inner(key: Vec<u8>, left: Arc<Node>, right: Arc<Node>, version: i64) -> Arc<Node> {
+    let height = left.height.max(right.height) + 1;
+    let size = left.size + right.size;
+    Arc::new(Node {
+        hash: inner_hash(height, size, version, &left.hash, &right.hash),
+        key,
+        value: None,
+        version,
+        height,
+        size,
+        left: Some(left),
+        right: Some(right),
+    })
+}
+
+impl Node {
+    fn is_leaf(&self) -> bool {
+        self.height == 0
+    }
+
+    fn children(&self) -> (&Arc<Node>, &Arc<Node>) {
+        (self.left.as_ref().unwrap(), self.right.as_ref().unwrap())
+    }
+
+    fn leaves<'a>(&'a self, out: &mut Vec<(&'a [u8], &'a [u8])>) {
+        match self.value.as_ref() {
+            Some(value) => out.push((&self.key, value)),
+            None => {
+                let (left, right) = self.children();
+                left.leaves(out);
+                right.leaves(out);
+            }
+        }
+    }
+
+    // predecessor is the largest key below key
+    fn predecessor(&self, key: &[u8]) -> Option<&[u8]> {
+        if self.is_leaf() {
+            return Some(self.key.as_slice()).filter(|k| *k < key);
+        }
+        let (left, right) = self.children();
+        match key > self.key.as_slice() {
+            true => right.predecessor(key).or_else(|| left.predecessor(key)),
+            false => left.predecessor(key),
+        }
+    }
+
+    // successor is the smallest key above key
+    fn successor(&self, key: &[u8]) -> Option<&[u8]> {
+        if self.is_leaf() {
+            return Some(self.key.as_slice()).filter(|k| *k > key);
+        }
+        let (left, right) = self.children();
+        match key < self.key.as_slice() {
+            true => left.successor(key).or_else(|| right.successor(key)),
+            false => right.successor(key),
+        }
+    }
+}
+
+fn rotate_right(key: Vec<u8>, left: &Arc<Node>, right: Arc<Node>, version: i64) -> Arc<Node> {
+    let (left_left, left_right) = left.children();
+    let right = inner(key, left_right.clone(), right, version);
+    inner(left.key.clone(), left_left.clone(), right, version)
+}
+
+fn rotate_left(key: Vec<u8>, left: Arc<Node>, right: &Arc<Node>, version: i64) -> Arc<Node> {
+    let (right_left, right_right) = right.children();
+    let left = inner(key, left, right_left.clone(), version);
+    inner(right.key.clone(), left, right_right.clone(), version)
+}
+
+// balance builds an inner node and rotates it like iavl when the heights of its children differ by more than one
+fn balance(key: Vec<u8>, left: Arc<Node>, right: Arc<Node>, version: i64) -> Arc<Node> {
+    if left.height > right.height + 1 {
+        let (left_left, left_right) = left.children();
+        let left = match left_left.height >= left_right.height {
+            true => left.clone(),
+            false => rotate_left(left.key.clone(), left_left.clone(), left_right, version),
+        };
+        return rotate_right(key, &left, right, version);
+    }
+    if right.height > left.height + 1 {
+        let (right_left, right_right) = right.children();
+        let right = match right_right.height >= right_left.height {
+            true => right.clone(),
+            false => rotate_right(right.key.clone(), right_left, right_right.clone(), version),
+        };
+        return rotate_left(key, left, &right, version);
+    }
+    inner(key, left, right, version)
+}
+
+fn set(node: &Arc<Node>, key: &[u8], value: &[u8], version: i64) -> Arc<Node> {
+    if node.is_leaf() {
+        return match key.cmp(&node.key) {
+            std::cmp::Ordering::Less => inner(
+                node.key.clone(),
+                leaf(key, value, version),
+                node.clone(),
+                version,
+            ),
+            std::cmp::Ordering::Greater => inner(
+                key.to_vec(),
+                node.clone(),
+                leaf(key, value, version),
+                version,
+            ),
+            std::cmp::Ordering::Equal => leaf(key, value, version),
+        };
+    }
+    let (left, right) = node.children();
+    match key < node.key.as_slice() {
+        true => balance(
+            node.key.clone(),
+            set(left, key, value, version),
+            right.clone(),
+            version,
+        ),
+        false => balance(
+            node.key.clone(),
+            left.clone(),
+            set(right, key, value, version),
+            version,
+        ),
+    }
+}
+
+// remove returns None when the key is missing, otherwise the new subtree and the key that replaces a removed
+// smallest key of a right subtree
+fn remove(
+    node: &Arc<Node>,
+    key: &[u8],
+    version: i64,
+) -> Option<(Option<Arc<Node>>, Option<Vec<u8>>)> {
+    if node.is_leaf() {
+        return match node.key.as_slice().eq(key) {
+            true => Some((None, None)),
+            false => None,
+        };
+    }
+    let (left, right) = node.children();
+    if key < node.key.as_slice() {
+        let (new_left, new_key) = remove(left, key, version)?;
+        return Some(match new_left {
+            None => (Some(right.clone()), Some(node.key.clone())),
+            Some(new_left) => (
+                Some(balance(node.key.clone(), new_left, right.clone(), version)),
+                new_key,
+            ),
+        });
+    }
+    let (new_right, new_key) = remove(right, key, version)?;
+    Some(match new_right {
+        None => (Some(left.clone()), None),
+        Some(new_right) => (
+            Some(balance(
+                new_key.unwrap_or_else(|| node.key.clone()),
+                left.clone(),
+                new_right,
+                version,
+            )),
+            None,
+        ),
+    })
+}
+
+// step of a proof from a node to its parent, the sibling hash is on the side it is stored
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct PathStep {
+    pub height: i8,
+    pub size: i64,
+    pub version: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub left: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub right: Option<String>,
+}
+
+// keys and values are base64, hashes hex
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ExistenceProof {
+    pub key: String,
+    pub value: String,
+    pub version: i64,
+    // from the leaf up to the root
+    pub path: Vec<PathStep>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct NonExistenceProof {
+    pub key: String,
+    // neighbours of the missing key, missing at the edges of the tree
+    pub left: Option<ExistenceProof>,
+    pub right: Option<ExistenceProof>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Proof {
+    Exist(ExistenceProof),
+    Nonexist(NonExistenceProof),
+}
+
+fn decode(text: &str) -> Result<Vec<u8>, String> {
+    base64::decode(text).map_err(|e| e.to_string())
+}
+
+impl ExistenceProof {
+    // root computes the root hash the proof leads to
+    fn root(&self) -> Result<Vec<u8>, String> {
+        let mut hash = leaf_hash(&decode(&self.key)?, &decode(&self.value)?, self.version);
+        for step in self.path.iter() {
+            let sibling = |side: &Option<String>| {
+                side.as_ref()
+                    .map(|h| hex::decode(h).map_err(|e| e.to_string()))
+                    .transpose()
+            };
+            hash = match (sibling(&step.left)?, sibling(&step.right)?) {
+                (Some(left), None) => {
+                    inner_hash(step.height, step.size, step.version, &left, &hash)
+                }
+                (None, Some(right)) => {
+                    inner_hash(step.height, step.size, step.version, &hash, &right)
+                }
+                _ => return Err("proof step must have exactly one sibling".to_string()),
+            };
+        }
+        Ok(hash)
+    }
+
+    fn verify(&self, root: &[u8]) -> Result<(), String> {
+        match self.root()?.eq(root) {
+            true => Ok(()),
+            false => Err(format!("proof of {} does not lead to the root", self.key)),
+        }
+    }
+}
+
+// rightmost tells if every step comes from the right child, so nothing is on the right of the leaf
+fn rightmost(path: &[PathStep]) -> bool {
+    path.iter().all(|step| step.left.is_some())
+}
+
+fn leftmost(path: &[PathStep]) -> bool {
+    path.iter().all(|step| step.right.is_some())
+}
+
+// adjacent tells if right is the next leaf after left: their paths are the same down to the node where left goes to
+// its left child and right to its right child, below it left only goes right and right only goes left
+fn adjacent(left: &[PathStep], right: &[PathStep]) -> bool {
+    let same = left
+        .iter()
+        .rev()
+        .zip(right.iter().rev())
+        .take_while(|(l, r)| l.eq(r))
+        .count();
+    if same >= left.len() || same >= right.len() {
+        return false;
+    }
+    let (left_below, right_below) = (left.len() - same - 1, right.len() - same - 1);
+    let (left_join, right_join) = (&left[left_below], &right[right_below]);
+    (left_join.height, left_join.size, left_join.version).eq(&(
+        right_join.height,
+        right_join.size,
+        right_join.version,
+    )) && left_join.right.is_some()
+        && right_join.left.is_some()
+        && rightmost(&left[..left_below])
+        && leftmost(&right[..right_below])
+}
+
+impl Proof {
+    // verify checks the proof against a root hash, a non existence proof also checks its neighbours are next to
+    // each other and around the key
+    pub fn verify(&self, root: &[u8]) -> Result<(), String> {
+        let proof = match self {
+            Proof::Exist(proof) => return proof.verify(root),
+            Proof::Nonexist(proof) => proof,
+        };
+        let key = decode(&proof.key)?;
+        if let Some(left) = proof.left.as_ref() {
+            left.verify(root)?;
+            if decode(&left.key)? >= key {
+                return Err("left neighbour is not below the key".to_string());
+            }
+        }
+        if let Some(right) = proof.right.as_ref() {
+            right.verify(root)?;
+            if decode(&right.key)? <= key {
+                return Err("right neighbour is not above the key".to_string());
+            }
+        }
+        let neighbours = match (proof.left.as_ref(), proof.right.as_ref()) {
+            (Some(left), Some(right)) => adjacent(&left.path, &right.path),
+            (Some(left), None) => rightmost(&left.path),
+            (None, Some(right)) => leftmost(&right.path),
+            (None, None) => root.eq(sha256(&[]).as_slice()),
+        };
+        match neighbours {
+            true => Ok(()),
+            false => Err("neighbours of the missing key are not adjacent".to_string()),
+        }
+    }
+}
+
+#[derive(Default, Debug, Clone)]
+pub struct IavlTree {
+    root: Option<Arc<Node>>,
+    // last saved version, 0 before the first commit
+    pub version: i64,
+}
+
+impl IavlTree {
+    // root_hash of an empty tree is the hash of nothing
+    pub fn root_hash(&self) -> Vec<u8> {
+        self.root
+            .as_ref()
+            .map_or_else(|| sha256(&[]), |root| root.hash.clone())
+    }
+
+    fn set(&mut self, key: &[u8], value: &[u8], version: i64) {
+        self.root = Some(match self.root.as_ref() {
+            None => leaf(key, value, version),
+            Some(root) => set(root, key, value, version),
+        });
+    }
+
+    fn remove(&mut self, key: &[u8], version: i64) {
+        if let Some((root, _)) = self
+            .root
+            .as_ref()
+            .and_then(|root| remove(root, key, version))
+        {
+            self.root = root;
+        }
+    }
+
+    // commit brings the tree in line with the storage as a new version, writes and removes are applied in key order.
+    // versions only grow, so a commit at a height already saved gets the next version
+    pub fn commit(&mut self, data: &BTreeMap<Vec<u8>, Vec<u8>>, height: u64) {
+        let mut leaves = vec![];
+        if let Some(root) = self.root.as_ref() {
+            root.leaves(&mut leaves);
+        }
+        let mut changes: BTreeMap<Vec<u8>, Option<Vec<u8>>> = leaves
+            .into_iter()
+            .filter(|(key, _)| !data.contains_key(*key))
+            .map(|(key, _)| (key.to_vec(), None))
+            .collect();
+        for (key, value) in data.iter() {
+            if self.get(key).map_or(true, |old| old.ne(value.as_slice())) {
+                changes.insert(key.to_owned(), Some(value.to_owned()));
+            }
+        }
+        if changes.is_empty() {
+            return;
+        }
+        let version = (height as i64).max(self.version + 1);
+        for (key, value) in changes {
+            match value {
+                Some(value) => self.set(&key, &value, version),
+                None => self.remove(&key, version),
+            }
+        }
+        self.version = version;
+    }
+
+    pub fn get(&self, key: &[u8]) -> Option<&[u8]> {
+        let mut node = self.root.as_ref()?;
+        while !node.is_leaf() {
+            let (left, right) = node.children();
+            node = match key < node.key.as_slice() {
+                true => left,
+                false => right,
+            };
+        }
+        match node.key.as_slice().eq(key) {
+            true => node.value.as_deref(),
+            false => None,
+        }
+    }
+
+    fn prove_existence(&self, key: &[u8]) -> Option<ExistenceProof> {
+        let mut node = self.root.as_ref()?;
+        let mut path = vec![];
+        while !node.is_leaf() {
+            let (left, right) = node.children();
+            let (height, size, version) = (node.height, node.size, node.version);
+            let step = |left: Option<&Arc<Node>>, right: Option<&Arc<Node>>| PathStep {
+                height,
+                size,
+                version,
+                left: left.map(|n| hex::encode(&n.hash)),
+                right: right.map(|n| hex::encode(&n.hash)),
+            };
+            node = match key < node.key.as_slice() {
+                true => {
+                    path.push(step(None, Some(right)));
+                    left
+                }
+                false => {
+                    path.push(step(Some(left), None));
+                    right
+                }
+            };
+        }
+        if node.key.as_slice().ne(key) {
+            return None;
+        }
+        path.reverse();
+        Some(ExistenceProof {
+            key: base64::encode(key),
+            value: base64::encode(node.value.as_deref().unwrap_or_default()),
+            version: node.version,
+            path,
+        })
+    }
+
+    // prove returns an existence proof of the key, or the proofs of its neighbours when it is missing
+    pub fn prove(&self, key: &[u8]) -> Proof {
+        if let Some(proof) = self.prove_existence(key) {
+            return Proof::Exist(proof);
+        }
+        let neighbour = |find: for<'a> fn(&'a Node, &[u8]) -> Option<&'a [u8]>| {
+            self.root
+                .as_ref()
+                .and_then(|root| find(root, key))
+                .and_then(|neighbour| self.prove_existence(neighbour))
+        };
+        Proof::Nonexist(NonExistenceProof {
+            key: base64::encode(key),
+            left: neighbour(Node::predecessor),
+            right: neighbour(Node::successor),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data(keys: impl IntoIterator<Item = u32>) -> BTreeMap<Vec<u8>, Vec<u8>> {
+        keys.into_iter()
+            .map(|k| {
+                (
+                    format!("key{:04}", k).into_bytes(),
+                    format!("value{}", k).into_bytes(),
+                )
+            })
+            .collect()
+    }
+
+    // check asserts the avl invariants of a subtree and returns its keys in order
+    fn check(node: &Node) -> Vec<Vec<u8>> {
+        if node.is_leaf() {
+            assert_eq!(node.size, 1);
+            return vec![node.key.clone()];
+        }
+        let (left, right) = node.children();
+        assert!((left.height - right.height).abs() <= 1);
+        assert_eq!(node.height, left.height.max(right.height) + 1);
+        assert_eq!(node.size, left.size + right.size);
+        assert_eq!(
+            node.hash,
+            inner_hash(
+                node.height,
+                node.size,
+                node.version,
+                &left.hash,
+                &right.hash
+            )
+        );
+        let mut keys = check(left);
+        let right_keys = check(right);
+        // the key of an inner node is the smallest key of its right subtree
+        assert_eq!(node.key, right_keys[0]);
+        assert!(keys.last().unwrap() < &right_keys[0]);
+        keys.extend(right_keys);
+        keys
+    }
+
+    fn keys(tree: &IavlTree) -> Vec<Vec<u8>> {
+        tree.root.as_ref().map_or(vec![], |root| check(root))
+    }
+
+    fn height(tree: &IavlTree) -> i8 {
+        tree.root.as_ref().map_or(0, |root| root.height)
+    }
+
+    #[test]
+    fn insert_rebalances() {
+        let mut tree = IavlTree::default();
+        // keys written in order rotate the tree at every level
+        let all = data(0..100);
+        tree.commit(&all, 1);
+        assert_eq!(keys(&tree), all.keys().cloned().collect::<Vec<_>>());
+        assert!(height(&tree) <= 9);
+        for (key, value) in all.iter() {
+            assert_eq!(tree.get(key), Some(value.as_slice()));
+        }
+        assert_eq!(tree.get(b"key0100"), None);
+        assert_eq!(tree.version, 1);
+    }
+
+    #[test]
+    fn remove_rebalances() {
+        let mut tree = IavlTree::default();
+        tree.commit(&data(0..100), 1);
+        let even = data((0..100).filter(|k| k % 2 == 0));
+        tree.commit(&even, 2);
+        assert_eq!(keys(&tree), even.keys().cloned().collect::<Vec<_>>());
+        assert_eq!(tree.get(b"key0001"), None);
+        assert_eq!(tree.get(b"key0002"), Some(&b"value2"[..]));
+
+        // removing the smallest keys replaces the keys of the inner nodes above them
+        let rest = data((60..100).filter(|k| k % 2 == 0));
+        tree.commit(&rest, 3);
+        assert_eq!(keys(&tree), rest.keys().cloned().collect::<Vec<_>>());
+        assert!(height(&tree) <= 6);
+
+        tree.commit(&BTreeMap::new(), 4);
+        assert!(tree.root.is_none());
+        assert_eq!(tree.root_hash(), sha256(&[]));
+    }
+
+    #[test]
+    fn commit_versions() {
+        let mut tree = IavlTree::default();
+        tree.commit(&data(0..10), 5);
+        let root = tree.root_hash();
+        // nothing changed, no new version
+        tree.commit(&data(0..10), 6);
+        assert_eq!((tree.version, tree.root_hash()), (5, root.clone()));
+        // a second commit at the same height gets the next version
+        tree.commit(&data(0..11), 5);
+        assert_eq!(tree.version, 6);
+        assert_ne!(tree.root_hash(), root);
+    }
+
+    #[test]
+    fn existence_proofs() {
+        let mut tree = IavlTree::default();
+        tree.commit(&data(0..20), 1);
+        let root = tree.root_hash();
+        for key in data(0..20).keys() {
+            let proof = tree.prove(key);
+            assert!(matches!(proof, Proof::Exist(_)));
+            assert_eq!(proof.verify(&root), Ok(()));
+        }
+
+        let mut proof = match tree.prove(b"key0007") {
+            Proof::Exist(proof) => proof,
+            _ => unreachable!(),
+        };
+        // proofs survive a json round trip
+        let json = serde_json::to_string(&Proof::Exist(proof.clone())).unwrap();
+        let decoded: Proof = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded.verify(&root), Ok(()));
+
+        // a proof of the old version does not lead to the new root
+        tree.commit(&data(0..21), 2);
+        assert!(Proof::Exist(proof.clone())
+            .verify(&tree.root_hash())
+            .is_err());
+
+        proof.value = base64::encode(b"forged");
+        assert!(Proof::Exist(proof).verify(&root).is_err());
+    }
+
+    #[test]
+    fn non_existence_proofs() {
+        let mut tree = IavlTree::default();
+        tree.commit(&data((0..20).map(|k| k * 2)), 1);
+        let root = tree.root_hash();
+        for key in [&b"key0007"[..], b"a", b"z"].iter() {
+            let proof = tree.prove(key);
+            assert!(matches!(proof, Proof::Nonexist(_)));
+            assert_eq!(proof.verify(&root), Ok(()));
+        }
+        match tree.prove(b"a") {
+            Proof::Nonexist(proof) => assert!(proof.left.is_none() && proof.right.is_some()),
+            _ => unreachable!(),
+        }
+        match tree.prove(b"z") {
+            Proof::Nonexist(proof) => assert!(proof.left.is_some() && proof.right.is_none()),
+            _ => unreachable!(),
+        }
+
+        // the neighbours must be next to each other and around the key
+        let mut proof = match tree.prove(b"key0007") {
+            Proof::Nonexist(proof) => proof,
+            _ => unreachable!(),
+        };
+        let far = match tree.prove(b"key0002") {
+            Proof::Exist(proof) => proof,
+            _ => unreachable!(),
+        };
+        proof.left = Some(far);
+        assert!(Proof::Nonexist(proof.clone()).verify(&root).is_err());
+        proof.left = None;
+        assert!(Proof::Nonexist(proof).verify(&root).is_err());
+        match tree.prove(b"key0008") {
+            Proof::Exist(exist) => {
+                let lie = Proof::Nonexist(NonExistenceProof {
+                    key: base64::encode(b"key0008"),
+                    left: None,
+                    right: Some(exist),
+                });
+                assert!(lie.verify(&root).is_err());
+            }
+            _ => unreachable!(),
+        }
+
+        let empty = IavlTree::default();
+        assert_eq!(empty.prove(b"key").verify(&empty.root_hash()), Ok(()));
+    }
+}
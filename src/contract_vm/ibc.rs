@@ -0,0 +1,95 @@
+// mock of ICS20 transfers, there is no relayer so packets stay here until they are acknowledged
+use cosmwasm_std::{Coin, HumanAddr};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct IbcTimeoutBlock {
+    pub revision: u64,
+    pub height: u64,
+}
+
+/// IbcMsg is the custom message a contract sends as `{"custom":{"ibc":{...}}}`,
+/// it follows the shape of IbcMsg::Transfer from later cosmwasm versions
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum IbcMsg {
+    Transfer {
+        channel_id: String,
+        to_address: String,
+        amount: Coin,
+        timeout_block: Option<IbcTimeoutBlock>,
+        timeout_timestamp: Option<u64>,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum PacketState {
+    Pending,
+    Acknowledged,
+    // error acknowledgement, the transfer is refunded
+    Failed(String),
+    TimedOut,
+}
+
+#[derive(Clone, Debug)]
+pub struct Packet {
+    pub sequence: u64,
+    pub channel_id: String,
+    pub sender: HumanAddr,
+    pub to_address: String,
+    pub amount: Coin,
+    pub state: PacketState,
+}
+
+#[derive(Clone, Default)]
+pub struct IbcModule {
+    pub packets: Vec<Packet>,
+    // acknowledge every packet as soon as it is sent
+    pub auto_ack: bool,
+}
+
+impl IbcModule {
+    // record outgoing packet, the sender balance must be debited before
+    pub fn send_packet(&mut self, sender: &str, msg: &IbcMsg) -> &Packet {
+        let IbcMsg::Transfer {
+            channel_id,
+            to_address,
+            amount,
+            ..
+        } = msg;
+        let state = match self.auto_ack {
+            true => PacketState::Acknowledged,
+            false => PacketState::Pending,
+        };
+        self.packets.push(Packet {
+            sequence: self.packets.len() as u64 + 1,
+            channel_id: channel_id.to_string(),
+            sender: HumanAddr::from(sender),
+            to_address: to_address.to_string(),
+            amount: amount.clone(),
+            state,
+        });
+        self.packets.last().unwrap()
+    }
+
+    // deliver sets the outcome of a pending packet, it returns the packet when it must be refunded
+    pub fn deliver(
+        &mut self,
+        sequence: u64,
+        state: PacketState,
+    ) -> Result<Option<&Packet>, String> {
+        let packet = match self.packets.iter_mut().find(|p| p.sequence == sequence) {
+            Some(p) => p,
+            None => return Err(format!("Packet {} not existed", sequence)),
+        };
+        if packet.state.ne(&PacketState::Pending) {
+            return Err(format!("Packet {} is already {:?}", sequence, packet.state));
+        }
+        packet.state = state;
+        match packet.state {
+            PacketState::Acknowledged => Ok(None),
+            _ => Ok(Some(packet)),
+        }
+    }
+}
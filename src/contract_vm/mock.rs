@@ -1,22 +1,36 @@
 extern crate base64;
+use bech32::{FromBase32, ToBase32, Variant};
 use cosmwasm_std::testing::MockQuerierCustomHandlerResult;
 use std::convert::TryInto;
 use std::ops::{Bound, RangeBounds};
 
 use cosmwasm_std::{
-    from_slice, to_binary, to_vec, Binary, Coin, ContractResult, CustomQuery, Empty, HumanAddr,
-    Querier as StdQuerier, QuerierResult, QueryRequest, SystemError, SystemResult,
+    from_slice, to_binary, to_vec, Binary, CanonicalAddr, Coin, ContractResult, CustomQuery,
+    Decimal, Empty, FullDelegation, HumanAddr, Querier as StdQuerier, QuerierResult, QueryRequest,
+    SystemError, SystemResult, Uint128, Validator,
 };
 
 use cosmwasm_std::{Order, KV};
 
-use cosmwasm_vm::testing::MockApi;
-use cosmwasm_vm::{Backend, BackendError, BackendResult, GasInfo, Querier, Storage};
-use serde::de::DeserializeOwned;
-use serde::{Deserialize, Serialize};
+use cosmwasm_vm::testing::MockApi as StdMockApi;
+use cosmwasm_vm::{Api, Backend, BackendError, BackendResult, GasInfo, Querier, Storage};
+use schemars::JsonSchema;
+use serde::de::{self, DeserializeOwned};
+use serde::ser::SerializeMap;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::cell::RefCell;
 use std::collections::{BTreeMap, HashMap};
-
-use crate::contract_vm::querier::{CustomHandler, StdMockQuerier, WasmHandler};
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+
+use crate::contract_vm::engine::{BECH32_PREFIX, CALL_CANCELLED, CANCELLED};
+use crate::contract_vm::iavl::IavlTree;
+use crate::contract_vm::ibc::IbcMsg;
+use crate::contract_vm::querier::{
+    BankQuerier, CustomHandler, GovMsg, GovQuery, StakingQuerier, StdMockQuerier, WasmHandler,
+};
 use crate::contract_vm::watcher;
 
 /// Implement MockQuerier
@@ -30,6 +44,77 @@ const GAS_COST_QUERY_RESPONSE_MULTIPLIER: u64 = 100;
 const GAS_COST_LAST_ITERATION: u64 = 37;
 const GAS_COST_RANGE: u64 = 11;
 
+const GAS_COST_HUMANIZE: u64 = 44;
+const GAS_COST_CANONICALIZE: u64 = 55;
+
+// when enabled addresses must be valid bech32 with the chain prefix, otherwise any string is accepted
+pub static mut STRICT_ADDRESS: bool = false;
+// seed of --seed, mixed into derived addresses and used by generators that have no seed of their own
+pub static mut SEED: Option<u64> = None;
+// keep an iavl tree of every contract storage for root hashes and proofs
+pub static mut IAVL_STORAGE: bool = false;
+lazy_static! {
+    // validators and delegations answered to staking queries, contracts see none when it is not set
+    pub static ref STAKING: Mutex<Option<StakingQuerier>> = Mutex::new(None);
+    // handlers are shared by the threads that call contracts, like the watcher and the REST server
+    static ref CUSTOM_QUERY_HANDLERS: Mutex<Vec<Arc<dyn CustomQueryHandler>>> = Mutex::new(vec![]);
+}
+
+/// MockApi accepts any address like the cosmwasm MockApi does, or validates bech32 checksum and prefix in strict mode
+#[derive(Copy, Clone)]
+pub struct MockApi {
+    api: StdMockApi,
+    strict: bool,
+}
+
+impl MockApi {
+    pub fn new(strict: bool) -> Self {
+        MockApi {
+            api: StdMockApi::default(),
+            strict,
+        }
+    }
+}
+
+impl Api for MockApi {
+    fn canonical_address(&self, human: &HumanAddr) -> BackendResult<CanonicalAddr> {
+        if !self.strict {
+            return self.api.canonical_address(human);
+        }
+
+        let gas_info = GasInfo::with_cost(GAS_COST_CANONICALIZE);
+        let result = match bech32::decode(human.as_str()) {
+            Err(e) => Err(BackendError::user_err(format!("Invalid input: {}", e))),
+            Ok((prefix, _, _)) if prefix.ne(BECH32_PREFIX) => Err(BackendError::user_err(format!(
+                "Invalid input: wrong address prefix {}",
+                prefix
+            ))),
+            Ok((_, data, _)) => match Vec::<u8>::from_base32(&data) {
+                Err(e) => Err(BackendError::user_err(format!("Invalid input: {}", e))),
+                Ok(canonical) => Ok(CanonicalAddr::from(canonical)),
+            },
+        };
+        (result, gas_info)
+    }
+
+    fn human_address(&self, canonical: &CanonicalAddr) -> BackendResult<HumanAddr> {
+        if !self.strict {
+            return self.api.human_address(canonical);
+        }
+
+        let gas_info = GasInfo::with_cost(GAS_COST_HUMANIZE);
+        let result = match bech32::encode(
+            BECH32_PREFIX,
+            canonical.as_slice().to_base32(),
+            Variant::Bech32,
+        ) {
+            Err(e) => Err(BackendError::user_err(format!("Invalid input: {}", e))),
+            Ok(human) => Ok(HumanAddr(human)),
+        };
+        (result, gas_info)
+    }
+}
+
 /// MockQuerier holds an immutable table of bank balances
 /// TODO: also allow querying contracts
 pub struct MockQuerier<C: CustomQuery + DeserializeOwned = Empty> {
@@ -56,6 +141,27 @@ impl<C: CustomQuery + DeserializeOwned> MockQuerier<C> {
         self.querier.update_balance(addr, balance)
     }
 
+    // subtract coin from the given address and return the new balance
+    pub fn debit(&mut self, addr: &HumanAddr, coin: &Coin) -> Result<Vec<Coin>, String> {
+        self.querier.debit(addr, coin)
+    }
+
+    pub fn bank(&self) -> BankQuerier {
+        self.querier.bank()
+    }
+
+    pub fn set_bank(&mut self, bank: BankQuerier) {
+        self.querier.set_bank(bank)
+    }
+
+    pub fn credit(&mut self, addr: &HumanAddr, coin: &Coin) -> Vec<Coin> {
+        self.querier.credit(addr, coin)
+    }
+
+    pub fn update_staking(&mut self, staking: StakingQuerier) {
+        self.querier.update_staking(staking)
+    }
+
     pub fn with_custom_handler<CH: 'static>(mut self, handler: CH) -> Self
     where
         CH: Fn(&C) -> MockQuerierCustomHandlerResult,
@@ -129,31 +235,241 @@ impl MockQuerier {
     }
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
-#[serde(rename_all = "snake_case")]
+#[derive(Clone, Debug, PartialEq)]
 /// An implementation of QueryRequest::Custom to show this works and can be extended in the contract
 pub enum SpecialQuery {
     // define custom query here for blockchain extension
+    Gov(GovQuery),
+    // custom queries of other chains, answered by the registered CustomQueryHandlers
+    Chain(Value),
 }
 
 impl CustomQuery for SpecialQuery {}
 
-pub fn custom_query_execute(_query: &SpecialQuery) -> MockQuerierCustomHandlerResult {
+// gov queries are {"gov": ..} like before, any other custom query is kept as it is sent
+impl Serialize for SpecialQuery {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            SpecialQuery::Gov(query) => {
+                let mut map = serializer.serialize_map(Some(1))?;
+                map.serialize_entry("gov", query)?;
+                map.end()
+            }
+            SpecialQuery::Chain(query) => query.serialize(serializer),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for SpecialQuery {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let query = Value::deserialize(deserializer)?;
+        match query.get("gov") {
+            Some(gov) => GovQuery::deserialize(gov)
+                .map(SpecialQuery::Gov)
+                .map_err(de::Error::custom),
+            None => Ok(SpecialQuery::Chain(query)),
+        }
+    }
+}
+
+/// CustomQueryHandler answers the QueryRequest::Custom queries of a chain module, like the
+/// `{"route": "market", "query_data": ..}` queries of Terra, so contracts of other chains can be simulated
+pub trait CustomQueryHandler: Send + Sync {
+    // None when the query is not for this handler, the next handler is asked
+    fn handle(&self, query: &Value) -> Option<ContractResult<Binary>>;
+}
+
+// register_custom_query_handler adds a handler, handlers are asked in the order they are registered
+pub fn register_custom_query_handler(handler: Box<dyn CustomQueryHandler>) {
+    CUSTOM_QUERY_HANDLERS
+        .lock()
+        .unwrap()
+        .push(Arc::from(handler));
+}
+
+// custom_query answers a chain query with the first handler that knows it
+pub fn custom_query(query: &Value) -> MockQuerierCustomHandlerResult {
+    // handlers run without the lock, a handler may query contracts that make custom queries again
+    let handlers = CUSTOM_QUERY_HANDLERS.lock().unwrap().clone();
+    for handler in handlers.iter() {
+        if let Some(result) = handler.handle(query) {
+            return SystemResult::Ok(result);
+        }
+    }
     SystemResult::Err(SystemError::UnsupportedRequest {
-        kind: "Not implemented".to_string(),
+        kind: format!("custom query {}", query),
     })
 }
 
+/// An implementation of CosmosMsg::Custom for modules cosmwasm 0.13 does not expose natively
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SpecialMsg {
+    Gov(GovMsg),
+    Ibc(IbcMsg),
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ValidatorConfig {
+    pub address: HumanAddr,
+    // 0 when it is not given
+    #[serde(default)]
+    pub commission: Option<Decimal>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct DelegationConfig {
+    pub delegator: HumanAddr,
+    pub validator: HumanAddr,
+    pub amount: Uint128,
+    // accumulated rewards returned with the delegation, cosmwasm 0.13 has no distribution query
+    #[serde(default)]
+    pub rewards: Uint128,
+}
+
+/// StakingConfig is the yaml or json file of --staking, amounts and rewards are in the bonded denom
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct StakingConfig {
+    #[serde(default)]
+    pub bonded_denom: Option<String>,
+    #[serde(default)]
+    pub validators: Vec<ValidatorConfig>,
+    #[serde(default)]
+    pub delegations: Vec<DelegationConfig>,
+}
+
+impl StakingConfig {
+    pub fn load(file: &str) -> Result<Self, String> {
+        let data = std::fs::read_to_string(file).map_err(|e| e.to_string())?;
+        serde_yaml::from_str(&data).map_err(|e| e.to_string())
+    }
+
+    pub fn add_validator(&mut self, address: &str) {
+        if !self
+            .validators
+            .iter()
+            .any(|v| v.address.as_str().eq(address))
+        {
+            self.validators.push(ValidatorConfig {
+                address: HumanAddr::from(address),
+                commission: None,
+            });
+        }
+    }
+
+    // add_delegation parses delegator:validator:amount with optional :rewards
+    pub fn add_delegation(&mut self, text: &str) -> Result<(), String> {
+        let wrong = || {
+            format!(
+                "wrong delegation {}, must be DELEGATOR:VALIDATOR:AMOUNT[:REWARDS]",
+                text
+            )
+        };
+        let parts: Vec<&str> = text.split(':').collect();
+        let amount = |index: usize| match parts.get(index) {
+            Some(amount) => amount
+                .parse::<u128>()
+                .map(Uint128::from)
+                .map_err(|_| wrong()),
+            None => Ok(Uint128::zero()),
+        };
+        if parts.len() < 3 || parts.len() > 4 {
+            return Err(wrong());
+        }
+        self.delegations.push(DelegationConfig {
+            delegator: HumanAddr::from(parts[0]),
+            validator: HumanAddr::from(parts[1]),
+            amount: amount(2)?,
+            rewards: amount(3)?,
+        });
+        Ok(())
+    }
+
+    // querier builds the staking module, validators only named by a delegation are added with 0 commission
+    pub fn querier(&self, default_denom: &str) -> StakingQuerier {
+        let denom = self
+            .bonded_denom
+            .to_owned()
+            .unwrap_or(default_denom.to_string());
+        let coin = |amount: Uint128| Coin {
+            denom: denom.to_owned(),
+            amount,
+        };
+        let mut config = self.clone();
+        for delegation in self.delegations.iter() {
+            config.add_validator(delegation.validator.as_str());
+        }
+        let validators: Vec<Validator> = config
+            .validators
+            .iter()
+            .map(|v| Validator {
+                address: v.address.to_owned(),
+                commission: v.commission.unwrap_or(Decimal::zero()),
+                max_commission: Decimal::one(),
+                max_change_rate: Decimal::one(),
+            })
+            .collect();
+        let delegations: Vec<FullDelegation> = config
+            .delegations
+            .iter()
+            .map(|d| FullDelegation {
+                delegator: d.delegator.to_owned(),
+                validator: d.validator.to_owned(),
+                amount: coin(d.amount),
+                can_redelegate: coin(d.amount),
+                accumulated_rewards: match d.rewards.is_zero() {
+                    true => vec![],
+                    false => vec![coin(d.rewards)],
+                },
+            })
+            .collect();
+        StakingQuerier::new(&denom, &validators, &delegations)
+    }
+}
+
+// callback handle for custom query, it is shared state so it is resolved outside of the contract
+pub type SpecialHandler = fn(&SpecialQuery) -> MockQuerierCustomHandlerResult;
+
 #[derive(Default, Debug, Clone)]
 pub struct Iter {
     data: Vec<KV>,
     position: usize,
 }
 
+// storage operation recorded during a call
+#[derive(Debug, Clone, PartialEq)]
+pub enum StorageOp {
+    Read(Vec<u8>),
+    Write(Vec<u8>),
+    Remove(Vec<u8>),
+    Scan(Option<Vec<u8>>, Option<Vec<u8>>),
+    // next item of a scan iterator
+    Next(u32),
+}
+
+// count_ops counts reads, writes, removes, scans and iterator steps
+pub fn count_ops(ops: &[StorageOp]) -> [usize; 5] {
+    let mut counts = [0; 5];
+    for op in ops {
+        counts[match op {
+            StorageOp::Read(_) => 0,
+            StorageOp::Write(_) => 1,
+            StorageOp::Remove(_) => 2,
+            StorageOp::Scan(..) => 3,
+            StorageOp::Next(_) => 4,
+        }] += 1;
+    }
+    counts
+}
+
 #[derive(Default, Debug, Clone)]
 pub struct MockStorage {
     pub data: BTreeMap<Vec<u8>, Vec<u8>>,
     pub iterators: HashMap<u32, Iter>,
+    // reads happen on shared reference so operations are kept in a cell
+    ops: RefCell<Vec<StorageOp>>,
+    // saved versions of the storage, only kept with IAVL_STORAGE
+    pub tree: Option<IavlTree>,
 }
 
 impl MockStorage {
@@ -161,6 +477,62 @@ impl MockStorage {
         MockStorage::default()
     }
 
+    // from_state restores storage from a json object of base64 key to base64 value
+    pub fn from_state(state: &[u8]) -> Result<Self, String> {
+        let entries: BTreeMap<String, String> =
+            serde_json::from_slice(state).map_err(|e| e.to_string())?;
+        let mut storage = MockStorage::default();
+        for (key, value) in entries {
+            storage.data.insert(
+                base64::decode(&key).map_err(|e| e.to_string())?,
+                base64::decode(&value).map_err(|e| e.to_string())?,
+            );
+        }
+        Ok(storage)
+    }
+
+    // state_hash is the sha256 of the sorted entries with every key and value prefixed by its length as 4 big endian
+    // bytes, the same state gives the same hash on every machine
+    pub fn state_hash(&self) -> String {
+        let mut hasher = Sha256::new();
+        for (key, value) in self.data.iter() {
+            hasher.update((key.len() as u32).to_be_bytes());
+            hasher.update(key);
+            hasher.update((value.len() as u32).to_be_bytes());
+            hasher.update(value);
+        }
+        hex::encode(hasher.finalize())
+    }
+
+    // commit saves the storage as a new version of its iavl tree and returns the root hash, the tree is built on the
+    // first commit so storage replaced by a reset or a state file starts a new tree
+    pub fn commit(&mut self, height: u64) -> Option<String> {
+        if unsafe { !IAVL_STORAGE } {
+            return None;
+        }
+        let tree = self.tree.get_or_insert_with(IavlTree::default);
+        tree.commit(&self.data, height);
+        Some(hex::encode(tree.root_hash()))
+    }
+
+    pub fn to_state(&self) -> String {
+        let entries: BTreeMap<String, String> = self
+            .data
+            .iter()
+            .map(|(key, value)| (base64::encode(key), base64::encode(value)))
+            .collect();
+        serde_json::to_string_pretty(&entries).unwrap_or_default()
+    }
+
+    pub fn ops(&self) -> Vec<StorageOp> {
+        self.ops.borrow().clone()
+    }
+
+    // take_ops returns the operations recorded since the last take
+    pub fn take_ops(&self) -> Vec<StorageOp> {
+        self.ops.take()
+    }
+
     pub fn all(&mut self, iterator_id: u32) -> BackendResult<Vec<KV>> {
         let mut out: Vec<KV> = Vec::new();
         let mut total = GasInfo::free();
@@ -182,9 +554,21 @@ impl MockStorage {
     }
 }
 
+// cancelled fails the storage access once Ctrl-C was pressed, which aborts the running call
+fn cancelled<T>() -> Option<BackendResult<T>> {
+    match CANCELLED.load(Ordering::SeqCst) {
+        true => Some((Err(BackendError::unknown(CALL_CANCELLED)), GasInfo::free())),
+        false => None,
+    }
+}
+
 impl Storage for MockStorage {
     fn get(&self, key: &[u8]) -> BackendResult<Option<Vec<u8>>> {
+        if let Some(result) = cancelled() {
+            return result;
+        }
         let gas_info = GasInfo::with_externally_used(key.len() as u64);
+        self.ops.borrow_mut().push(StorageOp::Read(key.to_vec()));
         (Ok(self.data.get(key).cloned()), gas_info)
     }
 
@@ -194,7 +578,14 @@ impl Storage for MockStorage {
         end: Option<&[u8]>,
         order: Order,
     ) -> BackendResult<u32> {
+        if let Some(result) = cancelled() {
+            return result;
+        }
         let gas_info = GasInfo::with_externally_used(GAS_COST_RANGE);
+        self.ops.borrow_mut().push(StorageOp::Scan(
+            start.map(|k| k.to_vec()),
+            end.map(|k| k.to_vec()),
+        ));
         let bounds = range_bounds(start, end);
 
         let values: Vec<KV> = match (bounds.start_bound(), bounds.end_bound()) {
@@ -223,6 +614,9 @@ impl Storage for MockStorage {
     }
 
     fn next(&mut self, iterator_id: u32) -> BackendResult<Option<KV>> {
+        if let Some(result) = cancelled() {
+            return result;
+        }
         let iterator = match self.iterators.get_mut(&iterator_id) {
             Some(i) => i,
             None => {
@@ -232,6 +626,7 @@ impl Storage for MockStorage {
                 )
             }
         };
+        self.ops.borrow_mut().push(StorageOp::Next(iterator_id));
 
         let (value, gas_info): (Option<KV>, GasInfo) = if iterator.data.len() > iterator.position {
             let item = iterator.data[iterator.position].clone();
@@ -247,14 +642,22 @@ impl Storage for MockStorage {
 
     // watch changes
     fn set(&mut self, key: &[u8], value: &[u8]) -> BackendResult<()> {
+        if let Some(result) = cancelled() {
+            return result;
+        }
         self.data.insert(key.to_vec(), value.to_vec());
+        self.ops.borrow_mut().push(StorageOp::Write(key.to_vec()));
         let gas_info = GasInfo::with_externally_used((key.len() + value.len()) as u64);
         watcher::logger_storage_event_insert(key, value);
         (Ok(()), gas_info)
     }
 
     fn remove(&mut self, key: &[u8]) -> BackendResult<()> {
+        if let Some(result) = cancelled() {
+            return result;
+        }
         self.data.remove(key);
+        self.ops.borrow_mut().push(StorageOp::Remove(key.to_vec()));
         let gas_info = GasInfo::with_externally_used(key.len() as u64);
         watcher::logger_storage_event_remove(key);
         (Ok(()), gas_info)
@@ -281,20 +684,24 @@ pub fn new_mock(
     contract_balance: &[Coin],
     contract_addr: &str,
     wasm_handler: WasmHandler,
+    special_handler: SpecialHandler,
     storage: MockStorage,
 ) -> Backend<MockApi, MockStorage, MockQuerier<SpecialQuery>> {
     let human_addr = HumanAddr::from(contract_addr);
     // update custom_querier
-    let custom_querier: MockQuerier<SpecialQuery> = MockQuerier::new(
+    let mut custom_querier: MockQuerier<SpecialQuery> = MockQuerier::new(
         &[(&human_addr, contract_balance)],
-        Box::new(|query| -> MockQuerierCustomHandlerResult { custom_query_execute(&query) }),
+        Box::new(move |query| -> MockQuerierCustomHandlerResult { special_handler(&query) }),
         wasm_handler,
     );
+    if let Some(staking) = STAKING.lock().unwrap().as_ref() {
+        custom_querier.update_staking(staking.clone());
+    }
     // let api = MockApi::default();
     // // orai default is 54 on wasmer and 54 on testing
     // api.canonical_length = 54;
     Backend {
-        api: MockApi::default(),
+        api: MockApi::new(unsafe { STRICT_ADDRESS }),
         storage,
         querier: custom_querier,
     }
@@ -1,7 +1,14 @@
 pub mod analyzer;
+pub mod chain;
 pub mod editor;
 pub mod engine;
+pub mod executor;
+pub mod iavl;
+pub mod ibc;
 pub mod mock;
+pub mod native;
+pub mod profiler;
 pub mod querier;
+pub mod receipt;
 mod singlepass_backend;
 pub mod watcher;
@@ -0,0 +1,386 @@
+// native debug executor, the contract is a cdylib built for the host from the same crate so gdb or lldb can
+// break inside it, storage and queries still go through the simulator
+use colored::*;
+use std::os::raw::c_void;
+
+use cosmwasm_std::{
+    to_vec, Binary, ContractResult, Env, HandleResponse, InitResponse, MessageInfo,
+    MigrateResponse, Order, QueryResponse,
+};
+use cosmwasm_vm::{BackendError, GasReport, Querier, Storage, VmResult};
+use libloading::Library;
+use serde::de::DeserializeOwned;
+
+use crate::contract_vm::executor::{new_backend, Executor};
+use crate::contract_vm::mock::{
+    MockQuerier, MockStorage, SpecialHandler, SpecialMsg, SpecialQuery,
+};
+use crate::contract_vm::querier::WasmHandler;
+
+// bytes handed over the ffi boundary, they are freed by the side that allocated them
+#[repr(C)]
+pub struct Buffer {
+    ptr: *mut u8,
+    len: usize,
+    cap: usize,
+}
+
+// functions of the simulator the native contract calls for storage and queries, ctx is passed back as is
+#[repr(C)]
+pub struct Host {
+    ctx: *mut c_void,
+    // returns 1 and fills value when the key exists
+    db_read: extern "C" fn(*mut c_void, *const u8, usize, *mut Buffer) -> i32,
+    db_write: extern "C" fn(*mut c_void, *const u8, usize, *const u8, usize),
+    db_remove: extern "C" fn(*mut c_void, *const u8, usize),
+    // fills out with the json list of [key, value] base64 pairs, null start or end is unbounded,
+    // order is 1 for ascending and 2 for descending
+    db_range: extern "C" fn(*mut c_void, *const u8, usize, *const u8, usize, i32, *mut Buffer),
+    // fills out with the json SystemResult of a raw QueryRequest
+    query_chain: extern "C" fn(*mut c_void, *const u8, usize, *mut Buffer),
+    free: extern "C" fn(Buffer),
+}
+
+// cosmwasm_simulate_init, cosmwasm_simulate_handle and the optional cosmwasm_simulate_migrate take env, info and
+// msg, cosmwasm_simulate_query env and msg, all of them return the json ContractResult
+type CallFn = unsafe extern "C" fn(
+    *const Host,
+    *const u8,
+    usize,
+    *const u8,
+    usize,
+    *const u8,
+    usize,
+) -> Buffer;
+type QueryFn = unsafe extern "C" fn(*const Host, *const u8, usize, *const u8, usize) -> Buffer;
+type FreeFn = unsafe extern "C" fn(Buffer);
+
+struct Context {
+    storage: MockStorage,
+    querier: MockQuerier<SpecialQuery>,
+    // the contract can not handle host errors like a cancelled storage access, they fail the call once it returns
+    error: Option<BackendError>,
+}
+
+fn context<'a>(ctx: *mut c_void) -> &'a mut Context {
+    unsafe { &mut *(ctx as *mut Context) }
+}
+
+fn slice<'a>(ptr: *const u8, len: usize) -> &'a [u8] {
+    match ptr.is_null() {
+        true => &[],
+        false => unsafe { std::slice::from_raw_parts(ptr, len) },
+    }
+}
+
+fn into_buffer(data: Vec<u8>) -> Buffer {
+    let mut data = std::mem::ManuallyDrop::new(data);
+    Buffer {
+        ptr: data.as_mut_ptr(),
+        len: data.len(),
+        cap: data.capacity(),
+    }
+}
+
+extern "C" fn free(buffer: Buffer) {
+    if !buffer.ptr.is_null() {
+        unsafe { drop(Vec::from_raw_parts(buffer.ptr, buffer.len, buffer.cap)) };
+    }
+}
+
+impl Context {
+    fn fail(&mut self, error: BackendError) {
+        if self.error.is_none() {
+            self.error = Some(error);
+        }
+    }
+}
+
+extern "C" fn db_read(ctx: *mut c_void, key: *const u8, key_len: usize, value: *mut Buffer) -> i32 {
+    let context = context(ctx);
+    match context.storage.get(slice(key, key_len)).0 {
+        Ok(Some(data)) => {
+            unsafe { *value = into_buffer(data) };
+            1
+        }
+        Ok(None) => 0,
+        Err(e) => {
+            context.fail(e);
+            0
+        }
+    }
+}
+
+extern "C" fn db_write(
+    ctx: *mut c_void,
+    key: *const u8,
+    key_len: usize,
+    value: *const u8,
+    value_len: usize,
+) {
+    let context = context(ctx);
+    if let Err(e) = context
+        .storage
+        .set(slice(key, key_len), slice(value, value_len))
+        .0
+    {
+        context.fail(e);
+    }
+}
+
+extern "C" fn db_remove(ctx: *mut c_void, key: *const u8, key_len: usize) {
+    let context = context(ctx);
+    if let Err(e) = context.storage.remove(slice(key, key_len)).0 {
+        context.fail(e);
+    }
+}
+
+extern "C" fn db_range(
+    ctx: *mut c_void,
+    start: *const u8,
+    start_len: usize,
+    end: *const u8,
+    end_len: usize,
+    order: i32,
+    out: *mut Buffer,
+) {
+    let context = context(ctx);
+    let bound = |ptr: *const u8, len| match ptr.is_null() {
+        true => None,
+        false => Some(slice(ptr, len)),
+    };
+    let order = match order {
+        2 => Order::Descending,
+        _ => Order::Ascending,
+    };
+    let pairs = context
+        .storage
+        .scan(bound(start, start_len), bound(end, end_len), order)
+        .0
+        .and_then(|iterator_id| context.storage.all(iterator_id).0);
+    match pairs {
+        Ok(pairs) => {
+            let pairs: Vec<(Binary, Binary)> = pairs
+                .into_iter()
+                .map(|(key, value)| (Binary(key), Binary(value)))
+                .collect();
+            unsafe { *out = into_buffer(to_vec(&pairs).unwrap_or_default()) };
+        }
+        Err(e) => context.fail(e),
+    }
+}
+
+extern "C" fn query_chain(ctx: *mut c_void, request: *const u8, len: usize, out: *mut Buffer) {
+    let context = context(ctx);
+    match context.querier.query_raw(slice(request, len), u64::MAX).0 {
+        Ok(result) => unsafe { *out = into_buffer(to_vec(&result).unwrap_or_default()) },
+        Err(e) => context.fail(e),
+    }
+}
+
+// library_for finds the native build of a wasm file among the --native-debug libraries,
+// target/debug/libcw20_base.so belongs to cw20_base.wasm
+pub fn library_for(wasm_file: &str) -> Option<String> {
+    let stem = |file: &str| {
+        std::path::Path::new(file)
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().replace('-', "_"))
+    };
+    let wasm_stem = stem(wasm_file)?;
+    crate::contract_vm::engine::NATIVE_DEBUG
+        .lock()
+        .unwrap()
+        .iter()
+        .find(|library| {
+            stem(library).map_or(false, |lib_stem| {
+                lib_stem
+                    .strip_prefix("lib")
+                    .unwrap_or(&lib_stem)
+                    .eq(&wasm_stem)
+            })
+        })
+        .cloned()
+}
+
+pub struct NativeExecutor {
+    // kept loaded while the entry points are used
+    _library: Library,
+    init: CallFn,
+    handle: CallFn,
+    // contracts without migration do not export it
+    migrate: Option<CallFn>,
+    query: QueryFn,
+    free: FreeFn,
+    context: Box<Context>,
+}
+
+// the querier holds handlers that are not Send, like the cosmwasm instance the executor is only used by one thread
+// at a time
+unsafe impl Send for NativeExecutor {}
+
+impl NativeExecutor {
+    pub fn new(
+        library_file: &str,
+        contract_addr: &str,
+        query_wasm: WasmHandler,
+        query_special: SpecialHandler,
+        storage: &MockStorage,
+    ) -> Result<Self, String> {
+        let library = Library::new(library_file)
+            .map_err(|e| format!("can not load native library {}: {}", library_file, e))?;
+        let (init, handle, migrate, query, free) = unsafe {
+            let symbol_error = |e: libloading::Error| {
+                format!(
+                    "{} does not export the native debug entry points: {}",
+                    library_file, e
+                )
+            };
+            (
+                *library
+                    .get::<CallFn>(b"cosmwasm_simulate_init\0")
+                    .map_err(symbol_error)?,
+                *library
+                    .get::<CallFn>(b"cosmwasm_simulate_handle\0")
+                    .map_err(symbol_error)?,
+                library
+                    .get::<CallFn>(b"cosmwasm_simulate_migrate\0")
+                    .ok()
+                    .map(|migrate| *migrate),
+                *library
+                    .get::<QueryFn>(b"cosmwasm_simulate_query\0")
+                    .map_err(symbol_error)?,
+                *library
+                    .get::<FreeFn>(b"cosmwasm_simulate_free\0")
+                    .map_err(symbol_error)?,
+            )
+        };
+        let backend = new_backend(contract_addr, query_wasm, query_special, storage);
+        println!(
+            "running [{}] natively from [{}]",
+            contract_addr.blue().bold(),
+            library_file.blue().bold()
+        );
+        Ok(NativeExecutor {
+            _library: library,
+            init,
+            handle,
+            migrate,
+            query,
+            free,
+            context: Box::new(Context {
+                storage: backend.storage,
+                querier: backend.querier,
+                error: None,
+            }),
+        })
+    }
+
+    fn host(&mut self) -> Host {
+        self.context.error = None;
+        Host {
+            ctx: &mut *self.context as *mut Context as *mut c_void,
+            db_read,
+            db_write,
+            db_remove,
+            db_range,
+            query_chain,
+            free,
+        }
+    }
+
+    // finish reads the result of an entry point, a host error during the call takes precedence
+    fn finish<T: DeserializeOwned>(&mut self, buffer: Buffer) -> VmResult<T> {
+        let data = slice(buffer.ptr, buffer.len).to_vec();
+        unsafe { (self.free)(buffer) };
+        if let Some(e) = self.context.error.take() {
+            return Err(e.into());
+        }
+        serde_json::from_slice(&data).map_err(|e| {
+            BackendError::unknown(format!("can not parse result of native contract: {}", e)).into()
+        })
+    }
+
+    fn call(&mut self, entry: CallFn, env: &Env, info: &MessageInfo, msg: &[u8]) -> Buffer {
+        let host = self.host();
+        let env = to_vec(env).unwrap_or_default();
+        let info = to_vec(info).unwrap_or_default();
+        unsafe {
+            entry(
+                &host,
+                env.as_ptr(),
+                env.len(),
+                info.as_ptr(),
+                info.len(),
+                msg.as_ptr(),
+                msg.len(),
+            )
+        }
+    }
+}
+
+impl Executor for NativeExecutor {
+    fn init(
+        &mut self,
+        env: &Env,
+        info: &MessageInfo,
+        msg: &[u8],
+    ) -> VmResult<ContractResult<InitResponse<SpecialMsg>>> {
+        let buffer = self.call(self.init, env, info, msg);
+        self.finish(buffer)
+    }
+
+    fn handle(
+        &mut self,
+        env: &Env,
+        info: &MessageInfo,
+        msg: &[u8],
+    ) -> VmResult<ContractResult<HandleResponse<SpecialMsg>>> {
+        let buffer = self.call(self.handle, env, info, msg);
+        self.finish(buffer)
+    }
+
+    fn migrate(
+        &mut self,
+        env: &Env,
+        info: &MessageInfo,
+        msg: &[u8],
+    ) -> VmResult<ContractResult<MigrateResponse<SpecialMsg>>> {
+        let migrate = self.migrate.ok_or_else(|| {
+            BackendError::unknown("native library does not export cosmwasm_simulate_migrate")
+        })?;
+        let buffer = self.call(migrate, env, info, msg);
+        self.finish(buffer)
+    }
+
+    fn query(&mut self, env: &Env, msg: &[u8]) -> VmResult<ContractResult<QueryResponse>> {
+        let host = self.host();
+        let env = to_vec(env).unwrap_or_default();
+        let buffer =
+            unsafe { (self.query)(&host, env.as_ptr(), env.len(), msg.as_ptr(), msg.len()) };
+        self.finish(buffer)
+    }
+
+    // native code is not metered
+    fn gas_left(&self) -> u64 {
+        u64::MAX
+    }
+
+    fn gas_report(&self) -> GasReport {
+        GasReport {
+            limit: u64::MAX,
+            remaining: u64::MAX,
+            used_externally: 0,
+            used_internally: 0,
+        }
+    }
+
+    fn storage(&mut self, func: &mut dyn FnMut(&mut MockStorage)) -> VmResult<()> {
+        func(&mut self.context.storage);
+        Ok(())
+    }
+
+    fn querier(&mut self, func: &mut dyn FnMut(&mut MockQuerier<SpecialQuery>)) -> VmResult<()> {
+        func(&mut self.context.querier);
+        Ok(())
+    }
+}
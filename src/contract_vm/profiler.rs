@@ -0,0 +1,527 @@
+// opt-in profiler counting the executed wasm operators of every function, cosmwasm-vm can not be instrumented so the
+// call runs a second time in wasmer of the singlepass backend. A middleware ends every straight line segment of code
+// with a breakpoint that adds the operators of the segment to its function, the imports of cosmwasm are served from a
+// copy of the storage. Operators are the instructions of the vm gas, host functions are not counted
+use byteorder::{ByteOrder, LittleEndian};
+use colored::*;
+use cosmwasm_std::{to_vec, Binary, CanonicalAddr, Env, HumanAddr, MessageInfo, Order};
+use cosmwasm_vm::{Api, Backend, Querier, Storage};
+use nix::sys::signal::{
+    sigaction, SaFlags, SigAction, SigHandler, SigSet, Signal, SIGBUS, SIGFPE, SIGILL, SIGINT,
+    SIGSEGV, SIGTRAP,
+};
+use std::collections::HashMap;
+use std::os::raw::c_void;
+use std::sync::{Arc, Mutex};
+use wasmer_runtime_core::{
+    backend::Compiler,
+    codegen::{
+        Event, EventSink, FunctionMiddleware, InternalEvent, MiddlewareChain, StreamingCompiler,
+    },
+    export::Export,
+    fault::ensure_sighandler,
+    func, imports,
+    module::{ExportIndex, Module, ModuleInfo},
+    structures::TypedIndex,
+    types::Value,
+    vm::Ctx,
+    wasmparser::Operator,
+    Instance,
+};
+
+use crate::contract_vm::analyzer::INDENT;
+use crate::contract_vm::mock::{MockApi, MockQuerier, MockStorage, SpecialQuery};
+use crate::contract_vm::singlepass_backend::ModuleCodeGenerator as SinglePassMCG;
+
+// functions listed in the report, the rest is summed up
+const REPORT_FUNCTIONS: usize = 20;
+// signals wasmer 0.17 handles, cosmwasm-vm and the ctrl-c handler use them too
+const SIGNALS: [Signal; 6] = [SIGFPE, SIGILL, SIGSEGV, SIGBUS, SIGTRAP, SIGINT];
+
+// calls and operators by function index
+type Counts = Arc<Mutex<HashMap<u32, (u64, u64)>>>;
+
+lazy_static! {
+    static ref WASMER_SIGNALS: Mutex<Option<Vec<SigAction>>> = Mutex::new(None);
+}
+
+#[derive(Clone, Debug)]
+pub struct FunctionProfile {
+    pub name: String,
+    pub calls: u64,
+    pub instructions: u64,
+}
+
+struct ProfilingMiddleware {
+    counts: Counts,
+    function: u32,
+    segment: u64,
+}
+
+impl ProfilingMiddleware {
+    fn count(&self, calls: u64, instructions: u64) -> Event<'static, 'static> {
+        let (counts, function) = (self.counts.clone(), self.function);
+        Event::Internal(InternalEvent::Breakpoint(Box::new(move |_| {
+            let mut counts = counts.lock().unwrap();
+            let count = counts.entry(function).or_default();
+            count.0 += calls;
+            count.1 += instructions;
+            Ok(())
+        })))
+    }
+}
+
+impl FunctionMiddleware for ProfilingMiddleware {
+    type Error = String;
+    fn feed_event<'a, 'b: 'a>(
+        &mut self,
+        op: Event<'a, 'b>,
+        module_info: &ModuleInfo,
+        sink: &mut EventSink<'a, 'b>,
+        _source_loc: u32,
+    ) -> Result<(), Self::Error> {
+        match op {
+            // the index of functions starts after the imported ones
+            Event::Internal(InternalEvent::FunctionBegin(id)) => {
+                self.function = id + module_info.imported_functions.len() as u32;
+                self.segment = 0;
+                sink.push(op);
+                sink.push(self.count(1, 0));
+                return Ok(());
+            }
+            // segments end where the control flow may leave them, like in the metering middleware
+            Event::Wasm(&ref op) | Event::WasmOwned(ref op) => {
+                self.segment += 1;
+                match *op {
+                    Operator::Loop { .. }
+                    | Operator::Block { .. }
+                    | Operator::End
+                    | Operator::If { .. }
+                    | Operator::Else
+                    | Operator::Unreachable
+                    | Operator::Br { .. }
+                    | Operator::BrTable { .. }
+                    | Operator::BrIf { .. }
+                    | Operator::Call { .. }
+                    | Operator::CallIndirect { .. }
+                    | Operator::Return => {
+                        sink.push(self.count(0, self.segment));
+                        self.segment = 0;
+                    }
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+        sink.push(op);
+        Ok(())
+    }
+}
+
+fn compiler(counts: Counts) -> Box<dyn Compiler> {
+    let c: StreamingCompiler<SinglePassMCG, _, _, _, _> = StreamingCompiler::new(move || {
+        let mut chain = MiddlewareChain::new();
+        chain.push(ProfilingMiddleware {
+            counts: counts.clone(),
+            function: 0,
+            segment: 0,
+        });
+        chain
+    });
+    Box::new(c)
+}
+
+fn leb128(data: &[u8], pos: &mut usize) -> Option<usize> {
+    let mut value = 0;
+    for shift in (0..35).step_by(7) {
+        let byte = *data.get(*pos)?;
+        *pos += 1;
+        value |= ((byte & 0x7f) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+// function_names reads the function names of the name section, release builds usually strip it
+fn function_names(section: &[u8]) -> HashMap<u32, String> {
+    let mut names = HashMap::new();
+    let mut pos = 0;
+    while let (Some(&id), Some(size)) = (section.get(pos), {
+        pos += 1;
+        leb128(section, &mut pos)
+    }) {
+        let end = pos + size;
+        if id == 1 {
+            let count = leb128(section, &mut pos).unwrap_or_default();
+            for _ in 0..count {
+                let entry = leb128(section, &mut pos).and_then(|index| {
+                    let len = leb128(section, &mut pos)?;
+                    let name = section.get(pos..pos + len)?;
+                    pos += len;
+                    Some((index as u32, String::from_utf8_lossy(name).to_string()))
+                });
+                match entry {
+                    Some((index, name)) => {
+                        names.insert(index, rustc_demangle::demangle(&name).to_string())
+                    }
+                    None => break,
+                };
+            }
+        }
+        pos = end;
+    }
+    names
+}
+
+// the imported functions of cosmwasm reach the backend through the data pointer of the context
+struct Host {
+    backend: Backend<MockApi, MockStorage, MockQuerier<SpecialQuery>>,
+    instance: *const Instance,
+}
+
+fn host(ctx: &mut Ctx) -> &mut Host {
+    unsafe { &mut *(ctx.data as *mut Host) }
+}
+
+fn read_u32(ctx: &Ctx, at: u32) -> Result<u32, String> {
+    let view = ctx.memory(0).view::<u8>();
+    let bytes: Vec<u8> = view
+        .get(at as usize..at as usize + 4)
+        .ok_or("region out of bounds")?
+        .iter()
+        .map(|cell| cell.get())
+        .collect();
+    Ok(LittleEndian::read_u32(&bytes))
+}
+
+// regions are offset, capacity and length of a buffer in the memory of the contract
+fn read_region(ctx: &Ctx, ptr: u32) -> Result<Vec<u8>, String> {
+    let (offset, length) = (read_u32(ctx, ptr)?, read_u32(ctx, ptr + 8)?);
+    let view = ctx.memory(0).view::<u8>();
+    let data = view
+        .get(offset as usize..offset as usize + length as usize)
+        .ok_or("region out of bounds")?;
+    Ok(data.iter().map(|cell| cell.get()).collect())
+}
+
+fn write_region(ctx: &Ctx, ptr: u32, data: &[u8]) -> Result<(), String> {
+    let (offset, capacity) = (read_u32(ctx, ptr)?, read_u32(ctx, ptr + 4)?);
+    if data.len() > capacity as usize {
+        return Err("region too small".to_string());
+    }
+    let view = ctx.memory(0).view::<u8>();
+    let cells = view
+        .get(offset as usize..offset as usize + data.len())
+        .ok_or("region out of bounds")?;
+    for (cell, byte) in cells.iter().zip(data) {
+        cell.set(*byte);
+    }
+    let mut length = [0u8; 4];
+    LittleEndian::write_u32(&mut length, data.len() as u32);
+    for (cell, byte) in view[ptr as usize + 8..ptr as usize + 12]
+        .iter()
+        .zip(&length)
+    {
+        cell.set(*byte);
+    }
+    Ok(())
+}
+
+// allocate asks the contract for a region, the instance is called again while it runs an import
+fn allocate(instance: &Instance, size: usize) -> Result<u32, String> {
+    match instance
+        .call("allocate", &[Value::I32(size as i32)])
+        .map_err(|e| e.to_string())?
+        .first()
+    {
+        Some(Value::I32(ptr)) => Ok(*ptr as u32),
+        _ => Err("allocate returned no region".to_string()),
+    }
+}
+
+fn write_to_contract(ctx: &mut Ctx, data: &[u8]) -> Result<u32, String> {
+    let instance = unsafe { &*host(ctx).instance };
+    let ptr = allocate(instance, data.len())?;
+    write_region(ctx, ptr, data)?;
+    Ok(ptr)
+}
+
+fn db_read(ctx: &mut Ctx, key_ptr: u32) -> Result<u32, String> {
+    let key = read_region(ctx, key_ptr)?;
+    match host(ctx).backend.storage.get(&key).0 {
+        Ok(Some(value)) => write_to_contract(ctx, &value),
+        Ok(None) => Ok(0),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn db_write(ctx: &mut Ctx, key_ptr: u32, value_ptr: u32) -> Result<(), String> {
+    let (key, value) = (read_region(ctx, key_ptr)?, read_region(ctx, value_ptr)?);
+    host(ctx)
+        .backend
+        .storage
+        .set(&key, &value)
+        .0
+        .map_err(|e| e.to_string())
+}
+
+fn db_remove(ctx: &mut Ctx, key_ptr: u32) -> Result<(), String> {
+    let key = read_region(ctx, key_ptr)?;
+    host(ctx)
+        .backend
+        .storage
+        .remove(&key)
+        .0
+        .map_err(|e| e.to_string())
+}
+
+fn db_scan(ctx: &mut Ctx, start_ptr: u32, end_ptr: u32, order: i32) -> Result<u32, String> {
+    let bound = |ptr| match ptr {
+        0 => Ok(None),
+        ptr => read_region(ctx, ptr).map(Some),
+    };
+    let (start, end) = (bound(start_ptr)?, bound(end_ptr)?);
+    let order = match order {
+        2 => Order::Descending,
+        _ => Order::Ascending,
+    };
+    host(ctx)
+        .backend
+        .storage
+        .scan(start.as_deref(), end.as_deref(), order)
+        .0
+        .map_err(|e| e.to_string())
+}
+
+// next returns value, key and the big endian key length, an empty key ends the iteration
+fn db_next(ctx: &mut Ctx, iterator_id: u32) -> Result<u32, String> {
+    let (key, value) = host(ctx)
+        .backend
+        .storage
+        .next(iterator_id)
+        .0
+        .map_err(|e| e.to_string())?
+        .unwrap_or_default();
+    let mut data = value;
+    data.extend(&key);
+    data.extend(&(key.len() as u32).to_be_bytes());
+    write_to_contract(ctx, &data)
+}
+
+fn canonicalize_address(
+    ctx: &mut Ctx,
+    source_ptr: u32,
+    destination_ptr: u32,
+) -> Result<u32, String> {
+    let human = String::from_utf8_lossy(&read_region(ctx, source_ptr)?).to_string();
+    match host(ctx).backend.api.canonical_address(&HumanAddr(human)).0 {
+        Ok(canonical) => write_region(ctx, destination_ptr, canonical.as_slice()).map(|_| 0),
+        Err(e) => write_to_contract(ctx, e.to_string().as_bytes()),
+    }
+}
+
+fn humanize_address(ctx: &mut Ctx, source_ptr: u32, destination_ptr: u32) -> Result<u32, String> {
+    let canonical = CanonicalAddr(Binary(read_region(ctx, source_ptr)?));
+    match host(ctx).backend.api.human_address(&canonical).0 {
+        Ok(human) => write_region(ctx, destination_ptr, human.as_str().as_bytes()).map(|_| 0),
+        Err(e) => write_to_contract(ctx, e.to_string().as_bytes()),
+    }
+}
+
+fn query_chain(ctx: &mut Ctx, request_ptr: u32) -> Result<u32, String> {
+    let request = read_region(ctx, request_ptr)?;
+    let result = host(ctx)
+        .backend
+        .querier
+        .query_raw(&request, u64::MAX)
+        .0
+        .map_err(|e| e.to_string())?;
+    write_to_contract(ctx, &to_vec(&result).map_err(|e| e.to_string())?)
+}
+
+// debug messages were printed by the call that is profiled
+fn debug(_ctx: &mut Ctx, _message_ptr: u32) {}
+
+// current returns the handler of a signal without changing it
+fn current(signal: Signal) -> SigAction {
+    let default = SigAction::new(SigHandler::SigDfl, SaFlags::empty(), SigSet::empty());
+    unsafe {
+        let action = sigaction(signal, &default).unwrap();
+        sigaction(signal, &action).unwrap();
+        action
+    }
+}
+
+// with_wasmer_signals runs func with the signal handlers of wasmer 0.17, which catch its breakpoints and traps, then
+// puts back the handlers of cosmwasm-vm and the simulator
+fn with_wasmer_signals<T>(func: impl FnOnce() -> T) -> T {
+    let saved: Vec<SigAction> = SIGNALS.iter().map(|signal| current(*signal)).collect();
+    // wasmer only installs its handlers once, later runs install the ones it left
+    let mut wasmer_signals = WASMER_SIGNALS.lock().unwrap();
+    match wasmer_signals.as_ref() {
+        Some(actions) => {
+            for (signal, action) in SIGNALS.iter().zip(actions) {
+                unsafe { sigaction(*signal, action).unwrap() };
+            }
+        }
+        None => {
+            ensure_sighandler();
+            *wasmer_signals = Some(SIGNALS.iter().map(|signal| current(*signal)).collect());
+        }
+    }
+    drop(wasmer_signals);
+    let result = func();
+    for (signal, action) in SIGNALS.iter().zip(saved.iter()) {
+        unsafe { sigaction(*signal, action).unwrap() };
+    }
+    result
+}
+
+// Profiler keeps the instrumented module of a contract, it is compiled on the first profiled call
+pub struct Profiler {
+    module: Module,
+    counts: Counts,
+    names: HashMap<u32, String>,
+}
+
+impl Profiler {
+    pub fn new(code: &[u8]) -> Result<Self, String> {
+        let counts: Counts = Arc::new(Mutex::new(HashMap::new()));
+        let module = wasmer_runtime_core::compile_with(code, compiler(counts.clone()).as_ref())
+            .map_err(|e| format!("can not compile the profiled contract: {:?}", e))?;
+        let mut names = module
+            .custom_sections("name")
+            .and_then(|sections| sections.first())
+            .map(|section| function_names(section))
+            .unwrap_or_default();
+        // exported names are kept by stripped builds
+        for (name, index) in module.info().exports.iter() {
+            if let ExportIndex::Func(index) = index {
+                names.insert(index.index() as u32, name.to_owned());
+            }
+        }
+        Ok(Profiler {
+            module,
+            counts,
+            names,
+        })
+    }
+
+    // run calls the entry point with the backend and returns the functions by operators executed, most first
+    pub fn run(
+        &mut self,
+        func_type: &str,
+        env: &Env,
+        info: &MessageInfo,
+        msg: &[u8],
+        backend: Backend<MockApi, MockStorage, MockQuerier<SpecialQuery>>,
+    ) -> Result<Vec<FunctionProfile>, String> {
+        self.counts.lock().unwrap().clear();
+        let import_object = imports! {
+            "env" => {
+                "db_read" => func!(db_read),
+                "db_write" => func!(db_write),
+                "db_remove" => func!(db_remove),
+                "db_scan" => func!(db_scan),
+                "db_next" => func!(db_next),
+                "canonicalize_address" => func!(canonicalize_address),
+                "humanize_address" => func!(humanize_address),
+                "query_chain" => func!(query_chain),
+                "debug" => func!(debug),
+            },
+        };
+        let mut instance = self
+            .module
+            .instantiate(&import_object)
+            .map_err(|e| format!("can not instantiate the profiled contract: {:?}", e))?;
+        if !matches!(
+            instance.exports().find(|(name, _)| name.eq(func_type)),
+            Some((_, Export::Function { .. }))
+        ) {
+            return Err(format!("contract does not export {}", func_type));
+        }
+        let mut host = Host {
+            backend,
+            instance: &instance as *const Instance,
+        };
+        instance.context_mut().data = &mut host as *mut Host as *mut c_void;
+
+        // entry points take regions with the json of env, info for all but query, and msg
+        let env = to_vec(env).map_err(|e| e.to_string())?;
+        let info = to_vec(info).map_err(|e| e.to_string())?;
+        let params: Vec<&[u8]> = match func_type {
+            "query" => vec![&env, msg],
+            _ => vec![&env, &info, msg],
+        };
+        let mut args = vec![];
+        for data in params {
+            let ptr = allocate(&instance, data.len())?;
+            write_region(instance.context(), ptr, data)?;
+            args.push(Value::I32(ptr as i32));
+        }
+        with_wasmer_signals(|| instance.call(func_type, &args))
+            .map_err(|e| format!("profiled call failed: {}", e))?;
+
+        let mut functions: Vec<FunctionProfile> = self
+            .counts
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(index, (calls, instructions))| FunctionProfile {
+                name: self
+                    .names
+                    .get(index)
+                    .cloned()
+                    .unwrap_or_else(|| format!("func[{}]", index)),
+                calls: *calls,
+                instructions: *instructions,
+            })
+            .collect();
+        functions.sort_by(|a, b| {
+            b.instructions
+                .cmp(&a.instructions)
+                .then(a.name.cmp(&b.name))
+        });
+        Ok(functions)
+    }
+}
+
+// print_profile shows the functions with the most operators executed and their share of all
+pub fn print_profile(functions: &[FunctionProfile]) {
+    let total: u64 = functions.iter().map(|function| function.instructions).sum();
+    println!(
+        "{} : {} instructions in {} functions",
+        "profile".blue().bold(),
+        total.to_string().yellow(),
+        functions.len().to_string().yellow()
+    );
+    let share = |instructions: u64| match total {
+        0 => 0.0,
+        total => instructions as f64 * 100.0 / total as f64,
+    };
+    for function in functions.iter().take(REPORT_FUNCTIONS) {
+        println!(
+            "{}{:>12} {:>6.2}% {:>8} calls  {}",
+            INDENT,
+            function.instructions.to_string().yellow(),
+            share(function.instructions),
+            function.calls,
+            function.name.green()
+        );
+    }
+    if functions.len() > REPORT_FUNCTIONS {
+        let rest: u64 = functions[REPORT_FUNCTIONS..]
+            .iter()
+            .map(|function| function.instructions)
+            .sum();
+        println!(
+            "{}{:>12} {:>6.2}% in {} other functions",
+            INDENT,
+            rest.to_string().yellow(),
+            share(rest),
+            functions.len() - REPORT_FUNCTIONS
+        );
+    }
+}
@@ -1,3 +1,4 @@
+use schemars::JsonSchema;
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -6,8 +7,8 @@ use cosmwasm_std::testing::MockQuerierCustomHandlerResult;
 use cosmwasm_std::{
     to_binary, AllBalanceResponse, AllDelegationsResponse, BalanceResponse, BankQuery, Binary,
     BondedDenomResponse, Coin, ContractResult, CustomQuery, Empty, FullDelegation, HumanAddr,
-    QuerierResult, QueryRequest, StakingQuery, SystemResult, Validator, ValidatorsResponse,
-    WasmQuery,
+    QuerierResult, QueryRequest, StakingQuery, StdError, StdResult, SystemResult, Validator,
+    ValidatorsResponse, WasmQuery,
 };
 
 /// DelegationResponse is data format returned from StakingRequest::Delegation query
@@ -58,14 +59,26 @@ impl<C: DeserializeOwned> StdMockQuerier<C> {
         self.bank.balances.insert(addr.into(), balance)
     }
 
-    #[cfg(feature = "staking")]
-    pub fn update_staking(
-        &mut self,
-        denom: &str,
-        validators: &[crate::query::Validator],
-        delegations: &[crate::query::FullDelegation],
-    ) {
-        self.staking = StakingQuerier::new(denom, validators, delegations);
+    // bank and set_bank copy the balances out and back, to revert failed calls
+    pub fn bank(&self) -> BankQuerier {
+        self.bank.clone()
+    }
+
+    pub fn set_bank(&mut self, bank: BankQuerier) {
+        self.bank = bank;
+    }
+
+    // subtract coin from the given address and return the new balance
+    pub fn debit(&mut self, addr: &HumanAddr, coin: &Coin) -> Result<Vec<Coin>, String> {
+        self.bank.debit(addr, coin)
+    }
+
+    pub fn credit(&mut self, addr: &HumanAddr, coin: &Coin) -> Vec<Coin> {
+        self.bank.credit(addr, coin)
+    }
+
+    pub fn update_staking(&mut self, staking: StakingQuerier) {
+        self.staking = staking;
     }
 
     pub fn with_custom_handler<CH: 'static>(mut self, handler: CH) -> Self
@@ -102,6 +115,46 @@ impl BankQuerier {
         BankQuerier { balances: map }
     }
 
+    pub fn balance(&self, addr: &HumanAddr) -> Vec<Coin> {
+        self.balances.get(addr).cloned().unwrap_or_default()
+    }
+
+    // merge adds the balances of the addresses this bank does not know yet
+    pub fn merge(&mut self, other: &BankQuerier) {
+        for (addr, coins) in other.balances.iter() {
+            self.balances
+                .entry(addr.to_owned())
+                .or_insert_with(|| coins.to_vec());
+        }
+    }
+
+    pub fn debit(&mut self, addr: &HumanAddr, coin: &Coin) -> Result<Vec<Coin>, String> {
+        let balance = self.balances.entry(addr.to_owned()).or_default();
+        let insufficient = format!(
+            "Insufficient funds: {} needs {}{}",
+            addr, coin.amount, coin.denom
+        );
+        match balance.iter_mut().find(|c| c.denom == coin.denom) {
+            None => Err(insufficient),
+            Some(c) => match c.amount - coin.amount {
+                Err(_) => Err(insufficient),
+                Ok(amount) => {
+                    c.amount = amount;
+                    Ok(balance.clone())
+                }
+            },
+        }
+    }
+
+    pub fn credit(&mut self, addr: &HumanAddr, coin: &Coin) -> Vec<Coin> {
+        let balance = self.balances.entry(addr.to_owned()).or_default();
+        match balance.iter_mut().find(|c| c.denom == coin.denom) {
+            None => balance.push(coin.clone()),
+            Some(c) => c.amount += coin.amount,
+        }
+        balance.clone()
+    }
+
     pub fn query(&self, request: &BankQuery) -> QuerierResult {
         let contract_result: ContractResult<Binary> = match request {
             BankQuery::Balance { address, denom } => {
@@ -191,3 +244,158 @@ impl StakingQuerier {
         SystemResult::Ok(contract_result)
     }
 }
+
+/// GovQuery is the custom query a contract sends as `{"custom":{"gov":{...}}}`,
+/// cosmwasm 0.13 has no native gov query so it goes through SpecialQuery
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum GovQuery {
+    Proposal { proposal_id: u64 },
+    Proposals {},
+    Vote { proposal_id: u64, voter: HumanAddr },
+}
+
+/// GovMsg is the custom message a contract sends as `{"custom":{"gov":{...}}}`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum GovMsg {
+    Vote { proposal_id: u64, vote: VoteOption },
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum VoteOption {
+    Yes,
+    No,
+    Abstain,
+    NoWithVeto,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ProposalStatus {
+    VotingPeriod,
+    Passed,
+    Rejected,
+    Failed,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Proposal {
+    pub proposal_id: u64,
+    pub title: String,
+    pub description: String,
+    pub status: ProposalStatus,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ProposalsResponse {
+    pub proposals: Vec<Proposal>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct VoteResponse {
+    pub vote: Option<VoteOption>,
+}
+
+#[derive(Clone, Default)]
+pub struct GovQuerier {
+    pub proposals: Vec<Proposal>,
+    votes: HashMap<(u64, HumanAddr), VoteOption>,
+}
+
+impl GovQuerier {
+    // add a proposal in voting period and return its id
+    pub fn propose(&mut self, title: &str, description: &str) -> u64 {
+        let proposal_id = self.proposals.len() as u64 + 1;
+        self.proposals.push(Proposal {
+            proposal_id,
+            title: title.to_string(),
+            description: description.to_string(),
+            status: ProposalStatus::VotingPeriod,
+        });
+        proposal_id
+    }
+
+    pub fn set_status(&mut self, proposal_id: u64, status: ProposalStatus) -> bool {
+        match self
+            .proposals
+            .iter_mut()
+            .find(|p| p.proposal_id == proposal_id)
+        {
+            Some(proposal) => {
+                proposal.status = status;
+                true
+            }
+            None => false,
+        }
+    }
+
+    // votes of a proposal, sorted by voter address
+    pub fn votes(&self, proposal_id: u64) -> Vec<(&HumanAddr, &VoteOption)> {
+        let mut votes: Vec<(&HumanAddr, &VoteOption)> = self
+            .votes
+            .iter()
+            .filter(|((id, _), _)| *id == proposal_id)
+            .map(|((_, voter), vote)| (voter, vote))
+            .collect();
+        votes.sort_by(|a, b| a.0.as_str().cmp(b.0.as_str()));
+        votes
+    }
+
+    // voting is only allowed while proposal is in voting period
+    pub fn execute(&mut self, voter: &str, msg: &GovMsg) -> Result<(), String> {
+        match msg {
+            GovMsg::Vote { proposal_id, vote } => {
+                match self
+                    .proposals
+                    .iter()
+                    .find(|p| p.proposal_id == *proposal_id)
+                {
+                    None => Err(format!("No such proposal: {}", proposal_id)),
+                    Some(proposal) if proposal.status != ProposalStatus::VotingPeriod => {
+                        Err(format!("Proposal {} is not in voting period", proposal_id))
+                    }
+                    Some(_) => {
+                        self.votes
+                            .insert((*proposal_id, HumanAddr::from(voter)), *vote);
+                        Ok(())
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn query(&self, request: &GovQuery) -> QuerierResult {
+        let contract_result: ContractResult<Binary> = match request {
+            GovQuery::Proposal { proposal_id } => {
+                match self
+                    .proposals
+                    .iter()
+                    .find(|p| p.proposal_id == *proposal_id)
+                {
+                    Some(proposal) => to_binary(proposal).into(),
+                    None => {
+                        let res: StdResult<Binary> =
+                            Err(StdError::not_found(format!("proposal {}", proposal_id)));
+                        res.into()
+                    }
+                }
+            }
+            GovQuery::Proposals {} => {
+                let res = ProposalsResponse {
+                    proposals: self.proposals.clone(),
+                };
+                to_binary(&res).into()
+            }
+            GovQuery::Vote { proposal_id, voter } => {
+                let res = VoteResponse {
+                    vote: self.votes.get(&(*proposal_id, voter.to_owned())).cloned(),
+                };
+                to_binary(&res).into()
+            }
+        };
+        // system result is always ok in the mock implementation
+        SystemResult::Ok(contract_result)
+    }
+}
@@ -0,0 +1,192 @@
+// receipt of a call with the results of the messages it dispatched, nested calls are folded in so the events and
+// gas of the whole call tree are in one place
+use crate::contract_vm::analyzer::INDENT;
+use colored::*;
+use cosmwasm_std::Attribute;
+use serde::Serializer;
+use serde_json::Value;
+
+// results are json strings, they are embedded as json when they parse
+fn as_json<S: Serializer>(result: &str, serializer: S) -> Result<S::Ok, S::Error> {
+    match serde_json::from_str::<serde_json::Value>(result) {
+        Ok(value) => serializer.serialize_some(&value),
+        Err(_) => serializer.serialize_str(result),
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct Event {
+    pub contract: String,
+    pub key: String,
+    pub value: String,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct MessageReceipt {
+    // wasm_execute, wasm_instantiate, bank, gov or ibc
+    pub kind: String,
+    // called contract, recipient or module
+    pub target: String,
+    #[serde(serialize_with = "as_json")]
+    pub result: String,
+    pub failed: bool,
+    // receipt of the contract call made by the message
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub receipt: Option<Box<Receipt>>,
+}
+
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct Receipt {
+    pub contract: String,
+    #[serde(serialize_with = "as_json")]
+    pub result: String,
+    // dispatched messages in order
+    pub messages: Vec<MessageReceipt>,
+    // attributes of the call followed by the events of its messages, in execution order
+    pub events: Vec<Event>,
+    // gas of the call and of the calls of its messages
+    pub gas_used: u64,
+    // sha256 of the contract storage after the call, empty when the call failed
+    pub state_hash: String,
+    // root hash of the iavl tree of the contract storage after the call, failed calls are not committed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub iavl_root: Option<String>,
+}
+
+impl Receipt {
+    pub fn new(
+        contract: &str,
+        result: String,
+        gas_used: u64,
+        attributes: &[Attribute],
+        messages: Vec<MessageReceipt>,
+    ) -> Self {
+        let mut events: Vec<Event> = attributes
+            .iter()
+            .map(|attr| Event {
+                contract: contract.to_string(),
+                key: attr.key.to_owned(),
+                value: attr.value.to_owned(),
+            })
+            .collect();
+        let mut total_gas = gas_used;
+        for receipt in messages.iter().filter_map(|msg| msg.receipt.as_ref()) {
+            events.extend(receipt.events.iter().cloned());
+            total_gas += receipt.gas_used;
+        }
+        Receipt {
+            contract: contract.to_string(),
+            result,
+            messages,
+            events,
+            gas_used: total_gas,
+            state_hash: String::new(),
+            iavl_root: None,
+        }
+    }
+
+    // wasm_events groups the events by the contract call that emitted them like the wasm events of wasmd:
+    // [{"type": "wasm", "attributes": [{"key": "contract_address", "value": ..}, {"key", "value"}..]}]
+    pub fn wasm_events(&self) -> Vec<Value> {
+        let mut groups: Vec<(&str, Vec<Value>)> = vec![];
+        for event in self.events.iter() {
+            let attribute = serde_json::json!({ "key": event.key, "value": event.value });
+            match groups.last_mut() {
+                Some((contract, attributes)) if event.contract.eq(*contract) => {
+                    attributes.push(attribute)
+                }
+                _ => groups.push((
+                    &event.contract,
+                    vec![
+                        serde_json::json!({ "key": "contract_address", "value": event.contract }),
+                        attribute,
+                    ],
+                )),
+            }
+        }
+        groups
+            .into_iter()
+            .map(|(_, attributes)| serde_json::json!({ "type": "wasm", "attributes": attributes }))
+            .collect()
+    }
+
+    // print_events shows the events as a table with a column per field
+    pub fn print_events(&self) {
+        if self.events.is_empty() {
+            return;
+        }
+        let contract_len = self
+            .events
+            .iter()
+            .map(|event| event.contract.len())
+            .chain(Some("contract".len()))
+            .max()
+            .unwrap_or_default();
+        let key_len = self
+            .events
+            .iter()
+            .map(|event| event.key.len())
+            .chain(Some("key".len()))
+            .max()
+            .unwrap_or_default();
+        println!("{}", "events".blue().bold());
+        println!(
+            "{}{:<contract_len$} | {:<key_len$} | {}",
+            INDENT,
+            "contract".bold(),
+            "key".bold(),
+            "value".bold(),
+            contract_len = contract_len,
+            key_len = key_len
+        );
+        println!(
+            "{}{}-+-{}-+-{}",
+            INDENT,
+            "-".repeat(contract_len),
+            "-".repeat(key_len),
+            "-".repeat("value".len())
+        );
+        for event in self.events.iter() {
+            println!(
+                "{}{:<contract_len$} | {:<key_len$} | {}",
+                INDENT,
+                event.contract.green(),
+                event.key.blue().bold(),
+                event.value.yellow(),
+                contract_len = contract_len,
+                key_len = key_len
+            );
+        }
+    }
+
+    // print shows the message tree with the merged totals
+    pub fn print(&self) {
+        println!("{}", "receipt".blue().bold());
+        self.print_messages(1);
+        println!(
+            "{}total gas : {}",
+            INDENT,
+            self.gas_used.to_string().yellow()
+        );
+        println!(
+            "{}events    : {}",
+            INDENT,
+            self.events.len().to_string().yellow()
+        );
+    }
+
+    fn print_messages(&self, depth: usize) {
+        for msg in self.messages.iter() {
+            println!(
+                "{}{} {} => {}",
+                INDENT.repeat(depth),
+                msg.kind.green(),
+                msg.target.blue().bold(),
+                msg.result.yellow()
+            );
+            if let Some(receipt) = msg.receipt.as_ref() {
+                receipt.print_messages(depth + 1);
+            }
+        }
+    }
+}
@@ -1,6 +1,47 @@
 use colored::*;
 use std::fmt::Write;
 
+// readable_bytes shows utf8 data as it is, otherwise as hex
+pub fn readable_bytes(data: &[u8]) -> String {
+    match std::str::from_utf8(data) {
+        Ok(result) if !result.is_empty() => result.to_string(),
+        _ => {
+            let mut hex_str = String::new();
+            for a in data.iter() {
+                write!(hex_str, "{:02x}", a).expect("Not written");
+            }
+            hex_str
+        }
+    }
+}
+
+// storage_key_name decodes length prefixed namespaces of cw-storage-plus and cosmwasm-storage,
+// like `balance[owner]`, other keys are shown as they are
+pub fn storage_key_name(key: &[u8]) -> String {
+    let mut namespaces: Vec<String> = vec![];
+    let mut rest = key;
+    while rest.len() > 2 {
+        let len = u16::from_be_bytes([rest[0], rest[1]]) as usize;
+        let namespace = match rest.get(2..2 + len).map(std::str::from_utf8) {
+            Some(Ok(namespace)) if len > 0 => namespace,
+            _ => break,
+        };
+        namespaces.push(namespace.to_string());
+        rest = &rest[2 + len..];
+    }
+    if namespaces.is_empty() {
+        return readable_bytes(key);
+    }
+    let mut name = namespaces.remove(0);
+    for segment in namespaces {
+        write!(name, "[{}]", segment).expect("Not written");
+    }
+    if !rest.is_empty() {
+        write!(name, "[{}]", readable_bytes(rest)).expect("Not written");
+    }
+    name
+}
+
 pub fn logger_storage_event_insert(key: &[u8], value: &[u8]) {
     let mut key_str = match std::str::from_utf8(&key) {
         Ok(result) => result.to_string(),
@@ -0,0 +1,74 @@
+// declarative deployment of a whole multi contract system from a yaml manifest
+use crate::{call_contract, error_json, get_engine, is_error_result, sender_info, CointBalance};
+use colored::*;
+use serde_json::Value;
+
+#[derive(Deserialize)]
+pub struct DeployContract {
+    // name of the contract, usable in ${contract:name} placeholders
+    pub name: String,
+    pub wasm: String,
+    // address when it is not the name, the name becomes its alias
+    pub address: Option<String>,
+    // other addresses resolving to the contract, like the bech32 address other contracts have hardcoded
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    pub sender: Option<String>,
+    // contracts without init message are only loaded
+    pub init: Option<Value>,
+}
+
+#[derive(Deserialize)]
+pub struct Manifest {
+    #[serde(default)]
+    pub balances: Vec<CointBalance>,
+    // contracts are instantiated in the listed order
+    pub contracts: Vec<DeployContract>,
+}
+
+impl DeployContract {
+    pub fn address(&self) -> String {
+        self.address.to_owned().unwrap_or(self.name.to_owned())
+    }
+}
+
+pub fn load(file: &str) -> Result<Manifest, String> {
+    let data = std::fs::read_to_string(file).map_err(|e| e.to_string())?;
+    serde_yaml::from_str(&data).map_err(|e| e.to_string())
+}
+
+// instantiate calls init of every contract in order, stops at the first failure
+pub fn instantiate(manifest: &Manifest, default_sender: &str) -> bool {
+    let len = manifest.contracts.len();
+    for (index, contract) in manifest.contracts.iter().enumerate() {
+        let init_msg = match &contract.init {
+            Some(msg) => msg.to_string(),
+            None => continue,
+        };
+        let sender_addr = contract.sender.as_deref().unwrap_or(default_sender);
+        let result = match (get_engine(&contract.name), sender_info(sender_addr)) {
+            (Some(engine), Some(info)) => call_contract(engine, "init", &init_msg, &info),
+            (None, _) => error_json(&format!("No such contract: {}", contract.name)),
+            (_, None) => error_json(&format!("No account found: {}", sender_addr)),
+        };
+        if is_error_result(&result) {
+            println!(
+                "[{}/{}] {} [{}]: {}",
+                index + 1,
+                len,
+                "deploy failed".red().bold(),
+                contract.name.blue().bold(),
+                result.red()
+            );
+            return false;
+        }
+        println!(
+            "[{}/{}] {} [{}]",
+            index + 1,
+            len,
+            "deployed".green().bold(),
+            contract.name.blue().bold()
+        );
+    }
+    true
+}
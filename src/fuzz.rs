@@ -0,0 +1,320 @@
+// fuzzing of contract entry points with messages generated from the analyzed json schema
+use crate::contract_vm::analyzer::Analyzer;
+use crate::contract_vm::engine::ContractInstance;
+use crate::contract_vm::mock::MockStorage;
+use crate::{error_json, get_engine, sender_info};
+use colored::*;
+use itertools::sorted;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use serde_json::{json, Map, Value};
+
+const MAX_DEPTH: usize = 4;
+// every n-th iteration starts again from the state right after loading
+const FRESH_STATE_EVERY: u64 = 10;
+// calls using more gas than mean + GAS_OUTLIER_SIGMA * standard deviation are reported
+const GAS_OUTLIER_SIGMA: f64 = 3.0;
+
+const STRINGS: &[&str] = &[
+    "",
+    "a",
+    "orai1qqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqq",
+    "fake_sender_addr",
+    "ünïcödé ✓",
+    "\"quoted\" \\ back",
+];
+const UINT_STRINGS: &[&str] = &[
+    "0",
+    "1",
+    "18446744073709551615",
+    "340282366920938463463374607431768211455",
+    "340282366920938463463374607431768211456",
+    "-1",
+];
+
+#[derive(Serialize)]
+struct Finding {
+    iteration: u64,
+    kind: String,
+    msg: String,
+    error: String,
+    gas_used: u64,
+}
+
+struct Generator<'a> {
+    analyzer: &'a Analyzer,
+    rng: StdRng,
+}
+
+impl<'a> Generator<'a> {
+    // value_for returns None when an optional member is left out
+    fn value_for(&mut self, type_name: &str, depth: usize) -> Option<Value> {
+        if let Some(inner) = type_name.strip_suffix('?') {
+            if self.rng.gen_ratio(1, 4) {
+                return None;
+            }
+            return self.value_for(inner, depth);
+        }
+        if let Some(item) = type_name
+            .strip_prefix('[')
+            .and_then(|t| t.strip_suffix(']'))
+        {
+            let len = match depth < MAX_DEPTH {
+                true => self.rng.gen_range(0..4),
+                false => 0,
+            };
+            return Some(Value::Array(
+                (0..len)
+                    .filter_map(|_| self.value_for(item, depth + 1))
+                    .collect(),
+            ));
+        }
+        if let Some(members) = self.analyzer.map_of_struct.get(type_name) {
+            let mut object = Map::new();
+            if depth < MAX_DEPTH {
+                // sorted, so the same seed draws the same values in every process
+                for (name, member_type) in sorted(members.iter()) {
+                    if let Some(value) = self.value_for(member_type, depth + 1) {
+                        object.insert(name.to_owned(), value);
+                    }
+                }
+            }
+            return Some(Value::Object(object));
+        }
+
+        let base_type = self
+            .analyzer
+            .map_of_basetype
+            .get(type_name)
+            .map(|t| t.as_str())
+            .unwrap_or(type_name);
+        Some(match base_type {
+            // Uint128 and friends are numbers in strings
+            "string" if type_name.starts_with("Uint") || type_name.starts_with("Decimal") => {
+                json!(UINT_STRINGS.choose(&mut self.rng).unwrap())
+            }
+            "string" if type_name.eq("Binary") => json!(base64::encode(
+                STRINGS.choose(&mut self.rng).unwrap().as_bytes()
+            )),
+            "string" => json!(STRINGS.choose(&mut self.rng).unwrap()),
+            "integer" => json!([0u64, 1, u32::MAX as u64, u64::MAX]
+                .choose(&mut self.rng)
+                .unwrap()),
+            "number" => json!([0.0, 1.5, -1.0, f64::MAX].choose(&mut self.rng).unwrap()),
+            "boolean" => json!(self.rng.gen_bool(0.5)),
+            _ => Value::Null,
+        })
+    }
+
+    // message builds a schema valid message, or a broken one when invalid is set
+    fn message(&mut self, msg_name: &str, invalid: bool) -> Option<Value> {
+        let variants = self.analyzer.map_of_member.get(msg_name)?;
+        let mut names: Vec<&String> = variants.keys().collect();
+        names.sort();
+        let name = names.choose(&mut self.rng)?.to_string();
+
+        let mut body = Map::new();
+        for member in variants[&name].iter() {
+            if let Some(value) = self.value_for(&member.member_def, 0) {
+                body.insert(member.member_name.to_owned(), value);
+            }
+        }
+
+        if invalid {
+            let keys: Vec<String> = body.keys().cloned().collect();
+            match (self.rng.gen_range(0..3), keys.choose(&mut self.rng)) {
+                // drop a member
+                (0, Some(key)) => {
+                    body.remove(key);
+                }
+                // member of the wrong type
+                (1, Some(key)) => {
+                    let wrong = match body[key] {
+                        Value::String(_) => json!(-1),
+                        _ => json!("wrong type"),
+                    };
+                    body.insert(key.to_owned(), wrong);
+                }
+                // unknown member
+                _ => {
+                    body.insert("unknown_field".to_string(), json!(true));
+                }
+            }
+        }
+
+        let is_enum = *self.analyzer.map_of_enum.get(msg_name).unwrap_or(&false);
+        Some(match is_enum {
+            true => {
+                let mut msg = Map::new();
+                msg.insert(name, Value::Object(body));
+                Value::Object(msg)
+            }
+            false => Value::Object(body),
+        })
+    }
+}
+
+// classify tells apart contract errors, which are expected, from panics and vm errors
+fn classify(result: &str) -> Option<&'static str> {
+    let error = serde_json::from_str::<Value>(result)
+        .ok()?
+        .get("error")?
+        .as_str()
+        .unwrap_or_default()
+        .to_lowercase();
+    if error.contains("unreachable") || error.contains("panic") {
+        Some("panic")
+    } else if error.contains("gas")
+        || error.contains("runtime error")
+        || error.contains("error calling")
+        || error.contains("vm error")
+    {
+        Some("vm_error")
+    } else {
+        Some("contract_error")
+    }
+}
+
+fn call(engine: &mut ContractInstance, entry: &str, msg: &[u8], sender_addr: &str) -> String {
+    let info = match sender_info(sender_addr) {
+        Some(info) => info,
+        None => return error_json(&format!("No account found: {}", sender_addr)),
+    };
+    match entry {
+        "init" => engine.init_raw(msg, &info),
+        "handle" => engine.handle_raw(msg, &info),
+        _ => engine.query_raw(msg),
+    }
+}
+
+fn set_storage(engine: &mut ContractInstance, storage: &MockStorage) {
+    engine
+        .with_storage(|s| {
+            *s = storage.clone();
+            Ok(())
+        })
+        .unwrap();
+}
+
+// run fuzzes the entry point then writes the report, returns false when panics or vm errors are found
+pub fn run(
+    contract_addr: &str,
+    sender_addr: &str,
+    entry: &str,
+    iterations: u64,
+    seed: u64,
+    report_file: &str,
+) -> bool {
+    let engine = match get_engine(contract_addr) {
+        Some(engine) => engine,
+        None => {
+            println!("No such contract: {}", contract_addr.red());
+            return false;
+        }
+    };
+    // accept the names of later cosmwasm versions too
+    let entry = match entry {
+        "instantiate" => "init",
+        "execute" => "handle",
+        e => e,
+    };
+    let msg_name = match entry {
+        "init" => "InitMsg",
+        "handle" => "HandleMsg",
+        "query" => "QueryMsg",
+        _ => {
+            println!("wrong entry point {}", entry.red().bold());
+            return false;
+        }
+    };
+
+    let analyzer = std::mem::replace(&mut engine.analyzer, Analyzer::default());
+    if !analyzer.map_of_member.contains_key(msg_name) {
+        println!("no schema found for {}", msg_name.red().bold());
+        engine.analyzer = analyzer;
+        return false;
+    }
+    let fresh = engine.with_storage(|storage| Ok(storage.clone())).unwrap();
+
+    let mut generator = Generator {
+        analyzer: &analyzer,
+        rng: StdRng::seed_from_u64(seed),
+    };
+    let mut findings: Vec<Finding> = vec![];
+    let mut gas_used: Vec<(u64, String, u64)> = vec![];
+    let mut contract_errors = 0;
+
+    for iteration in 0..iterations {
+        if iteration % FRESH_STATE_EVERY == 0 {
+            set_storage(engine, &fresh);
+        }
+        let invalid = generator.rng.gen_ratio(1, 10);
+        let msg = match generator.message(msg_name, invalid) {
+            Some(msg) => msg.to_string(),
+            None => break,
+        };
+
+        engine.refresh_gas();
+        let gas_init = engine.executor.gas_left();
+        let result = call(engine, entry, msg.as_bytes(), sender_addr);
+        let gas = gas_init.saturating_sub(engine.executor.gas_left());
+
+        match classify(&result) {
+            Some("contract_error") => contract_errors += 1,
+            Some(kind) => findings.push(Finding {
+                iteration,
+                kind: kind.to_string(),
+                msg: msg.clone(),
+                error: result,
+                gas_used: gas,
+            }),
+            None => {}
+        }
+        gas_used.push((iteration, msg, gas));
+    }
+    engine.analyzer = analyzer;
+
+    // gas outliers
+    let count = gas_used.len().max(1) as f64;
+    let mean = gas_used.iter().map(|g| g.2 as f64).sum::<f64>() / count;
+    let deviation = (gas_used
+        .iter()
+        .map(|g| (g.2 as f64 - mean).powi(2))
+        .sum::<f64>()
+        / count)
+        .sqrt();
+    for (iteration, msg, gas) in gas_used.iter() {
+        if deviation > 0.0 && *gas as f64 > mean + GAS_OUTLIER_SIGMA * deviation {
+            findings.push(Finding {
+                iteration: *iteration,
+                kind: "gas_outlier".to_string(),
+                msg: msg.to_owned(),
+                error: format!("mean gas {:.0}", mean),
+                gas_used: *gas,
+            });
+        }
+    }
+
+    let failures = findings.iter().filter(|f| f.kind.ne("gas_outlier")).count();
+    let report = json!({
+        "contract": contract_addr,
+        "entry": entry,
+        "iterations": gas_used.len(),
+        "seed": seed,
+        "contract_errors": contract_errors,
+        "findings": findings,
+    });
+    match std::fs::write(report_file, serde_json::to_string_pretty(&report).unwrap()) {
+        Ok(_) => println!("fuzz report is written to {}", report_file.blue().bold()),
+        Err(e) => println!("can not write fuzz report: {}", e.to_string().red()),
+    }
+    println!(
+        "fuzz finished: {} iterations, {} contract errors, {} panics or vm errors, {} gas outliers",
+        gas_used.len().to_string().green().bold(),
+        contract_errors.to_string().yellow(),
+        failures.to_string().red().bold(),
+        (findings.len() - failures).to_string().yellow()
+    );
+    failures == 0
+}
@@ -0,0 +1,144 @@
+// rhai scripts run before and after each call, they can log, assert, query contracts or make follow-up calls
+use crate::contract_vm::mock::{register_custom_query_handler, CustomQueryHandler};
+use crate::{call_contract, error_json, get_engine, sender_info};
+use cosmwasm_std::{to_binary, Binary, ContractResult};
+use rhai::serde::{from_dynamic, to_dynamic};
+use rhai::{Dynamic, Engine, EvalAltResult, Map, Scope, AST};
+use serde_json::Value;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+struct Hooks {
+    engine: Engine,
+    ast: AST,
+}
+
+lazy_static! {
+    // the watcher thread runs calls too, so the script is shared, rhai is built with sync for it
+    static ref HOOKS: Mutex<Option<Hooks>> = Mutex::new(None);
+}
+// set while a hook runs, calls made by a hook do not run the hooks again
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+fn to_json(value: Dynamic) -> Value {
+    from_dynamic::<Value>(&value).unwrap_or(Value::Null)
+}
+
+// from_json parses a json string into a rhai value, other strings are kept as they are
+fn from_json(text: &str) -> Dynamic {
+    match serde_json::from_str::<Value>(text) {
+        Ok(value) => to_dynamic(value).unwrap_or(Dynamic::UNIT),
+        Err(_) => Dynamic::from(text.to_string()),
+    }
+}
+
+fn query(contract_addr: &str, msg: Map) -> Dynamic {
+    let msg = to_json(Dynamic::from(msg)).to_string();
+    match get_engine(contract_addr) {
+        Some(engine) => from_json(&engine.query(&msg)),
+        None => from_json(&error_json(&format!("No such contract: {}", contract_addr))),
+    }
+}
+
+fn call(contract_addr: &str, call_type: &str, msg: Map, sender_addr: &str) -> Dynamic {
+    let msg = to_json(Dynamic::from(msg)).to_string();
+    let result = match (get_engine(contract_addr), sender_info(sender_addr)) {
+        (Some(engine), Some(info)) => call_contract(engine, call_type, &msg, &info),
+        (None, _) => error_json(&format!("No such contract: {}", contract_addr)),
+        (_, None) => error_json(&format!("No account found: {}", sender_addr)),
+    };
+    from_json(&result)
+}
+
+// load compiles the hook script, it defines before_call(call) and after_call(call, result) or only one of them
+pub fn load(file: &str) -> Result<(), String> {
+    let mut engine = Engine::new();
+    engine.register_fn("query", query).register_fn("call", call);
+    let ast = engine
+        .compile_file(file.into())
+        .map_err(|e| e.to_string())?;
+    let custom_query = ast.iter_functions().any(|f| f.name == "custom_query");
+    *HOOKS.lock().unwrap() = Some(Hooks { engine, ast });
+    if custom_query {
+        register_custom_query_handler(Box::new(ScriptQueryHandler));
+    }
+    Ok(())
+}
+
+// ScriptQueryHandler answers custom queries with custom_query(query) of the script, the function returns the
+// response or () when it does not know the query and throws to fail it
+struct ScriptQueryHandler;
+
+impl CustomQueryHandler for ScriptQueryHandler {
+    fn handle(&self, query: &Value) -> Option<ContractResult<Binary>> {
+        match run("custom_query", vec![from_json(&query.to_string())])? {
+            Err(e) => Some(ContractResult::Err(format!("custom_query hook: {}", e))),
+            Ok(value) if value.is::<()>() => None,
+            Ok(value) => Some(to_binary(&to_json(value)).into()),
+        }
+    }
+}
+
+fn call_map(contract_addr: &str, call_type: &str, msg: &str, sender_addr: &str) -> Map {
+    let mut call = Map::new();
+    call.insert("contract".into(), contract_addr.to_string().into());
+    call.insert("type".into(), call_type.to_string().into());
+    call.insert("sender".into(), sender_addr.to_string().into());
+    call.insert("msg".into(), from_json(msg));
+    call
+}
+
+// run calls a hook function when the script defines it, None when there is nothing to run
+fn run(name: &str, args: Vec<Dynamic>) -> Option<Result<Dynamic, String>> {
+    // checked before the lock, the calls of a running hook come back here
+    if RUNNING.load(Ordering::SeqCst) {
+        return None;
+    }
+    let hooks = HOOKS.lock().unwrap();
+    let hooks = hooks.as_ref()?;
+    if !hooks.ast.iter_functions().any(|f| f.name == name) {
+        return None;
+    }
+    RUNNING.store(true, Ordering::SeqCst);
+    let result = hooks
+        .engine
+        .call_fn::<Dynamic>(&mut Scope::new(), &hooks.ast, name, args)
+        .map_err(|e| match *e {
+            EvalAltResult::ErrorRuntime(value, _) => value.to_string(),
+            e => e.to_string(),
+        });
+    RUNNING.store(false, Ordering::SeqCst);
+    Some(result)
+}
+
+// before_call returns the message to call, the hook can return a new message or throw to reject the call
+pub fn before_call(
+    contract_addr: &str,
+    call_type: &str,
+    msg: &str,
+    sender_addr: &str,
+) -> Result<String, String> {
+    let call = call_map(contract_addr, call_type, msg, sender_addr);
+    match run("before_call", vec![call.into()]) {
+        None => Ok(msg.to_string()),
+        Some(Err(e)) => Err(format!("before_call hook: {}", e)),
+        Some(Ok(value)) if value.is::<()>() => Ok(msg.to_string()),
+        Some(Ok(value)) if value.is::<String>() => Ok(value.cast::<String>()),
+        Some(Ok(value)) => Ok(to_json(value).to_string()),
+    }
+}
+
+// after_call fails when the hook throws, so hooks can assert on results
+pub fn after_call(
+    contract_addr: &str,
+    call_type: &str,
+    msg: &str,
+    sender_addr: &str,
+    result: &str,
+) -> Result<(), String> {
+    let call = call_map(contract_addr, call_type, msg, sender_addr);
+    match run("after_call", vec![call.into(), from_json(result)]) {
+        Some(Err(e)) => Err(format!("after_call hook: {}", e)),
+        _ => Ok(()),
+    }
+}
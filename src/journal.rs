@@ -0,0 +1,133 @@
+// append-only journal of the executed calls, one json line per call, replaying it against freshly loaded contracts
+// reports the calls whose result changed
+use crate::contract_vm::engine::set_block;
+use crate::{call_contract, deploy_wasm, get_engine, is_error_result, migrate_contract};
+use colored::*;
+use cosmwasm_std::{Coin, HumanAddr, MessageInfo};
+use serde_json::Value;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::sync::Mutex;
+
+lazy_static! {
+    // file of --journal, every call is appended once it finishes
+    pub static ref JOURNAL_FILE: Mutex<Option<String>> = Mutex::new(None);
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Entry {
+    pub height: u64,
+    pub contract: String,
+    // wasm file that ran the call, replay loads it for init and migrates to it when it changed
+    pub code: String,
+    #[serde(rename = "type")]
+    pub call_type: String,
+    pub sender: String,
+    #[serde(default)]
+    pub funds: Vec<Coin>,
+    pub msg: String,
+    pub result: String,
+    pub gas_used: u64,
+}
+
+pub fn record(entry: &Entry) {
+    let file = match JOURNAL_FILE.lock().unwrap().clone() {
+        Some(file) => file,
+        None => return,
+    };
+    let written = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&file)
+        .and_then(|mut f| writeln!(f, "{}", serde_json::to_string(entry).unwrap_or_default()));
+    if let Err(e) = written {
+        println!("can not write journal {}: {}", file, e.to_string().red());
+    }
+}
+
+pub fn load(file: &str) -> Result<Vec<Entry>, String> {
+    let data = std::fs::read_to_string(file).map_err(|e| e.to_string())?;
+    data.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            serde_json::from_str(line).map_err(|e| format!("line {}: {}", index + 1, e))
+        })
+        .collect()
+}
+
+// results are compared as json, so the formatting of the journal does not matter
+fn same_result(recorded: &str, result: &str) -> bool {
+    match (
+        serde_json::from_str::<Value>(recorded),
+        serde_json::from_str::<Value>(result),
+    ) {
+        (Ok(recorded), Ok(result)) => recorded.eq(&result),
+        _ => recorded.eq(result),
+    }
+}
+
+fn run(entry: &Entry) -> (String, u64) {
+    let info = MessageInfo {
+        sender: HumanAddr::from(entry.sender.as_str()),
+        sent_funds: entry.funds.to_owned(),
+    };
+    // the chain moves to the recorded height when the session advanced it, it can not go back
+    let _ = set_block(Some(entry.height), None);
+    if entry.call_type.eq("init") && get_engine(&entry.contract).is_none() {
+        if let Err(e) = deploy_wasm(&entry.code, &entry.contract) {
+            return (crate::error_json(&e), 0);
+        }
+    }
+    let result = match get_engine(&entry.contract) {
+        None => crate::error_json(&format!("No such contract: {}", entry.contract)),
+        Some(engine) if entry.call_type.eq("migrate") => {
+            let code = Some(entry.code.as_str()).filter(|code| engine.wasm_file.ne(code));
+            migrate_contract(&entry.contract, code, &entry.msg, &info)
+        }
+        Some(engine) => call_contract(engine, &entry.call_type, &entry.msg, &info),
+    };
+    let gas_used = get_engine(&entry.contract).map_or(0, |engine| engine.last_gas_used);
+    (result, gas_used)
+}
+
+// replay runs every call of the journal in order, it fails when a result differs from the recorded one, gas changes
+// are only reported
+pub fn replay(file: &str) -> bool {
+    let entries = match load(file) {
+        Ok(entries) => entries,
+        Err(e) => {
+            println!("invalid journal {}: {}", file, e.red());
+            return false;
+        }
+    };
+    let mut diverged = 0;
+    for (index, entry) in entries.iter().enumerate() {
+        let (result, gas_used) = run(entry);
+        let step = format!(
+            "call {} [{} {}]",
+            (index + 1).to_string().blue().bold(),
+            entry.call_type.green().bold(),
+            entry.contract.green().bold()
+        );
+        if !same_result(&entry.result, &result) {
+            diverged += 1;
+            println!("{} {}", step, "diverged".red().bold());
+            println!("  recorded: {}", entry.result);
+            println!("  replayed: {}", result.red());
+        } else if gas_used != entry.gas_used && !is_error_result(&result) {
+            println!(
+                "{} gas {} -> {}",
+                step,
+                entry.gas_used.to_string().yellow(),
+                gas_used.to_string().yellow().bold()
+            );
+        }
+    }
+    println!(
+        "replay finished: {} calls, {} diverged",
+        entries.len().to_string().green().bold(),
+        diverged.to_string().red().bold()
+    );
+    diverged == 0
+}
@@ -0,0 +1,51 @@
+#![deny(
+    dead_code,
+    nonstandard_style,
+    unused_imports,
+    unused_mut,
+    unused_variables,
+    unused_unsafe,
+    unreachable_patterns
+)]
+
+// the contract vm with an embeddable Simulator, the cosmwasm-simulate binary builds its REPL, REST server and tools
+// on top of it
+#[cfg(not(any(
+    all(target_os = "freebsd", target_arch = "x86_64"),
+    all(target_os = "freebsd", target_arch = "aarch64"),
+    all(target_os = "macos", target_arch = "x86_64"),
+    all(target_os = "linux", target_arch = "x86_64"),
+    all(target_os = "linux", target_arch = "aarch64"),
+    all(target_os = "android", target_arch = "x86_64"),
+    all(target_os = "android", target_arch = "aarch64"),
+)))]
+compile_error!("This crate doesn't yet support compiling on operating systems and architectures other than these:
+       - FreeBSD and x86_64
+       - FreeBSD and AArch64
+       - macOS and x86_64
+       - Linux and x86_64
+       - Linux and AArch64
+       - Android and x86_64
+       - Android and AArch64");
+
+extern crate dynasmrt;
+
+extern crate serde;
+
+#[macro_use]
+extern crate serde_derive;
+
+#[macro_use]
+extern crate dynasm;
+
+#[macro_use]
+extern crate lazy_static;
+
+extern crate byteorder;
+#[macro_use]
+extern crate smallvec;
+
+pub mod contract_vm;
+mod simulator;
+
+pub use simulator::Simulator;
@@ -8,74 +8,124 @@
     unreachable_patterns
 )]
 
-#[cfg(not(any(
-    all(target_os = "freebsd", target_arch = "x86_64"),
-    all(target_os = "freebsd", target_arch = "aarch64"),
-    all(target_os = "macos", target_arch = "x86_64"),
-    all(target_os = "linux", target_arch = "x86_64"),
-    all(target_os = "linux", target_arch = "aarch64"),
-    all(target_os = "android", target_arch = "x86_64"),
-    all(target_os = "android", target_arch = "aarch64"),
-)))]
-compile_error!("This crate doesn't yet support compiling on operating systems and architectures other than these:
-       - FreeBSD and x86_64
-       - FreeBSD and AArch64
-       - macOS and x86_64
-       - Linux and x86_64
-       - Linux and AArch64
-       - Android and x86_64
-       - Android and AArch64");
-
-pub mod contract_vm;
+mod deploy;
+mod fuzz;
+mod hooks;
+mod journal;
+mod openapi;
+mod report;
+mod rest;
+mod rpc;
+mod standard;
+mod stream;
+mod tx;
+mod upload;
 
 extern crate base64;
 extern crate clap;
 
-use crate::contract_vm::analyzer::{Member, INDENT};
-use crate::contract_vm::editor::TerminalEditor;
-use crate::contract_vm::engine::{ContractInstance, BLOCK_HEIGHT, CHAIN_ID, DENOM};
-use crate::contract_vm::mock::MockStorage;
-use crate::contract_vm::querier::WasmHandler;
+use cosmwasm_simulate::contract_vm;
 
-use clap::{App, Arg};
+use crate::contract_vm::analyzer::{load_data_from_file, Member, INDENT};
+use crate::contract_vm::chain::{self, is_error_result, Chain};
+use crate::contract_vm::editor::TerminalEditor;
+use crate::contract_vm::engine::{
+    BlockState, ContractInstance, BLOCK_HEIGHT, CALL_CANCELLED, CANCELLED, CHAIN_ID, DENOM,
+    ROLLBACK, STORAGE_DIFF,
+};
+use crate::contract_vm::ibc::{IbcModule, PacketState};
+use crate::contract_vm::mock::{MockStorage, SpecialMsg, SpecialQuery};
+use crate::contract_vm::querier::{BankQuerier, GovQuerier, ProposalStatus, WasmHandler};
+use crate::contract_vm::receipt::MessageReceipt;
+use crate::contract_vm::watcher::{readable_bytes, storage_key_name};
+
+use bech32::ToBase32;
+use clap::{App, AppSettings, Arg, ArgMatches, Shell, SubCommand};
 use colored::*;
+use cosmwasm_std::testing::MockQuerierCustomHandlerResult;
 use cosmwasm_std::{
-    from_slice, Attribute, Binary, Coin, CosmosMsg, HumanAddr, MessageInfo, QuerierResult,
-    SystemError, SystemResult, Uint128, WasmMsg, WasmQuery,
+    from_slice, to_binary, Binary, Coin, CosmosMsg, HumanAddr, MessageInfo, QuerierResult,
+    SystemError, SystemResult, Uint128, WasmQuery,
 };
+use cosmwasm_vm::Api;
 use itertools::sorted;
+use nix::sys::signal::{sigaction, SaFlags, SigAction, SigHandler, SigSet, Signal};
+use nix::unistd::{dup, dup2};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::io::{Error, ErrorKind};
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::io::{Error, ErrorKind, Write};
 use std::mem::transmute;
+use std::os::unix::io::FromRawFd;
 use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, MutexGuard};
 use std::{fs, sync, thread, time, vec};
 
-extern crate dynasmrt;
-
 extern crate serde;
 
 #[macro_use]
 extern crate serde_derive;
 
-#[macro_use]
-extern crate dynasm;
-
 #[macro_use]
 extern crate lazy_static;
 
-extern crate byteorder;
-#[macro_use]
-extern crate smallvec;
-
 // default const is 'static lifetime
 const DEFAULT_SENDER_ADDR: &str = "fake_sender_addr";
 const DEFAULT_SENDER_BALANCE: u64 = 10_000_000_000_000_000;
+// calls listed by :history without an index
+const HISTORY_CALLS: usize = 20;
+
+lazy_static! {
+    // engines share the global config and call each other synchronously, so the REST workers, the REPL and the
+    // reloads of the watcher take turns
+    static ref ENGINES: Mutex<()> = Mutex::new(());
+}
+
+fn lock_engines() -> MutexGuard<'static, ()> {
+    ENGINES.lock().unwrap_or_else(|e| e.into_inner())
+}
 
 struct Config {
     editor: TerminalEditor,
-    engines: HashMap<String, ContractInstance>,
+    // boxed so engines keep their address when a contract is instantiated during a call
+    engines: HashMap<String, Box<ContractInstance>>,
+    // wasm files by code id, code id is the load order starting from 1
+    codes: Vec<String>,
+    // companion contracts that are compiled on first use, by address
+    pending: HashMap<String, String>,
+    // wasm files of the companion contracts compiled since, a revert of their first call makes them pending again
+    compiled: HashMap<String, String>,
     accounts: Vec<MessageInfo>,
+    gov: GovQuerier,
+    ibc: IbcModule,
+    // second build of the primary contract, every call is repeated on it
+    compare: Option<Box<ContractInstance>>,
+    // human friendly names of contract addresses
+    aliases: HashMap<String, String>,
+    // storage of each contract at the end of the blocks it changed in, only kept with --checkpoints
+    checkpoints: Option<HashMap<String, BTreeMap<u64, MockStorage>>>,
+    // queries of the REPL run against this past height
+    query_height: Option<u64>,
+    // every call of the session for the html report
+    session: Vec<report::CallRecord>,
+    // last successful init message and its sender by contract, run again when a reload resets the state
+    init_msgs: HashMap<String, (String, MessageInfo)>,
+    // reloaded contracts start from empty storage instead of copying the old one
+    reset_on_reload: bool,
+    // migrate message run on the new build after a reload by contract address, the empty address is for all
+    migrate_on_reload: HashMap<String, String>,
+    // contracts dropped with :remove, the watcher does not load them again
+    removed: HashSet<String>,
+    // contract and sender of the REPL prompt
+    current: String,
+    current_sender: String,
+    // folder of --state-dir, the storage of every contract is saved there after each call
+    state_dir: Option<String>,
+    // named snapshots of the storage and balances of every contract, gov and ibc with the block, to rewind
+    // experiments
+    snapshots: BTreeMap<String, (chain::State, BlockState)>,
 }
 // using raw pointer with a life time to store static object
 static mut _DATA: *const Config = 0 as *const Config;
@@ -85,47 +135,783 @@ impl Config {
             _DATA = transmute(Box::new(Config {
                 editor: TerminalEditor::new(),
                 engines: HashMap::new(),
+                codes: Vec::new(),
+                pending: HashMap::new(),
+                compiled: HashMap::new(),
                 accounts: Vec::new(),
+                gov: GovQuerier::default(),
+                ibc: IbcModule::default(),
+                compare: None,
+                aliases: HashMap::new(),
+                checkpoints: None,
+                query_height: None,
+                session: vec![],
+                init_msgs: HashMap::new(),
+                reset_on_reload: false,
+                migrate_on_reload: HashMap::new(),
+                removed: HashSet::new(),
+                state_dir: None,
+                snapshots: BTreeMap::new(),
+                current: String::new(),
+                current_sender: String::new(),
             }));
         }
         return transmute(_DATA);
     }
 }
 
-fn query_wasm(request: &WasmQuery) -> QuerierResult {
+// contracts of the binary run against the config, contracts it does not know are loaded on first use
+impl Chain for Config {
+    fn engine(&mut self, contract_addr: &str) -> Option<&mut ContractInstance> {
+        get_engine(contract_addr)
+    }
+
+    fn engines(&mut self) -> Vec<&mut ContractInstance> {
+        self.engines
+            .values_mut()
+            .map(|engine| engine.as_mut())
+            .collect()
+    }
+
+    fn bank(&mut self) -> BankQuerier {
+        self.engines
+            .values_mut()
+            .next()
+            .map(|engine| engine.with_querier(|querier| Ok(querier.bank())).unwrap())
+            .unwrap_or_default()
+    }
+
+    fn code(&self, code_id: u64) -> Option<String> {
+        self.codes.get((code_id as usize).wrapping_sub(1)).cloned()
+    }
+
+    fn instance_address(&mut self, wasm_file: &str, label: Option<&str>) -> String {
+        match label {
+            Some(label) => free_address(label),
+            None => instance_address(wasm_file),
+        }
+    }
+
+    fn load(&mut self, wasm_file: &str, contract_addr: &str) -> Result<(), String> {
+        match insert_engine(
+            wasm_file,
+            contract_addr,
+            query_wasm,
+            &MockStorage::default(),
+        ) {
+            true => Ok(()),
+            false => Err(format!("can not load {}", wasm_file)),
+        }
+    }
+
+    fn remove(&mut self, contract_addr: &str) {
+        self.engines.remove(contract_addr);
+    }
+
+    fn resolve_address(&self, addr: &str) -> String {
+        resolve_address(addr)
+    }
+
+    fn gov(&mut self) -> &mut GovQuerier {
+        &mut self.gov
+    }
+
+    fn ibc(&mut self) -> &mut IbcModule {
+        &mut self.ibc
+    }
+}
+
+// resolve_address returns the address of an alias, other addresses are returned as they are
+fn resolve_address(addr: &str) -> String {
+    let Config { aliases, .. } = unsafe { Config::get() };
+    match aliases.get(addr) {
+        Some(address) => address.to_owned(),
+        None => addr.to_string(),
+    }
+}
+
+// substitute_placeholders replaces ${sender}, ${contract} and ${contract:name} with the actual addresses
+fn substitute_placeholders(
+    msg: &str,
+    engine: &ContractInstance,
+    info: &MessageInfo,
+) -> Result<String, String> {
+    let mut result = String::new();
+    let mut rest = msg;
+    while let Some(start) = rest.find("${") {
+        let end = match rest[start..].find('}') {
+            Some(end) => start + end,
+            None => break,
+        };
+        result.push_str(&rest[..start]);
+        let name = &rest[start + 2..end];
+        let value = match name.split_once(':') {
+            None if name.eq("sender") => info.sender.to_string(),
+            None if name.eq("contract") => engine.env.contract.address.to_string(),
+            Some(("contract", contract)) => {
+                let address = resolve_address(contract);
+                if !contract_addresses().contains(&address) {
+                    return Err(format!("No such contract: {}", contract));
+                }
+                address
+            }
+            _ => return Err(format!("Unknown placeholder: ${{{}}}", name)),
+        };
+        result.push_str(&value);
+        rest = &rest[end + 1..];
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+// get_engine returns a loaded contract, companion contracts are compiled when they are first used
+fn get_engine<'a>(contract_addr: &str) -> Option<&'a mut ContractInstance> {
+    let contract_addr = resolve_address(contract_addr);
+    let contract_addr = contract_addr.as_str();
     unsafe {
-        let Config { engines, .. } = Config::get();
-        match request {
-            WasmQuery::Smart { contract_addr, msg } => {
-                match engines.get_mut(contract_addr.as_str()) {
-                    None => SystemResult::Err(SystemError::NoSuchContract {
-                        addr: contract_addr.to_owned(),
-                    }),
-                    Some(engine) => {
-                        let result = cosmwasm_vm::call_query(
-                            &mut engine.instance,
-                            &engine.env,
-                            msg.as_slice(),
-                        );
+        let Config {
+            engines,
+            pending,
+            compiled,
+            ..
+        } = Config::get();
+        if let Some(wasm_file) = pending.remove(contract_addr) {
+            println!(
+                "loading contract [{}] on first use",
+                contract_addr.blue().bold()
+            );
+            insert_engine(
+                &wasm_file,
+                contract_addr,
+                query_wasm,
+                &MockStorage::default(),
+            );
+            restore_storage(contract_addr);
+            compiled.insert(contract_addr.to_string(), wasm_file);
+        }
+        engines.get_mut(contract_addr).map(|engine| engine.as_mut())
+    }
+}
 
-                        // response can not unwrap, so it is empty
-                        match result {
-                            Ok(response) => SystemResult::Ok(response),
-                            Err(err) => SystemResult::Err(SystemError::InvalidResponse {
-                                error: err.to_string(),
-                                response: Binary::from([]),
-                            }),
-                        }
+// contract_addresses returns loaded and not yet compiled contracts, sorted
+fn contract_addresses() -> Vec<String> {
+    unsafe {
+        let Config {
+            engines, pending, ..
+        } = Config::get();
+        sorted(engines.keys().chain(pending.keys()).cloned()).collect()
+    }
+}
+
+fn call_contract(
+    engine: &mut ContractInstance,
+    func_type: &str,
+    param: &str,
+    info: &MessageInfo,
+) -> String {
+    let height = unsafe { Config::get() }.query_height;
+    call_contract_at(engine, func_type, param, info, height)
+}
+
+// call_contract_at substitutes placeholders, runs the hooks around the call and records it in the rpc history,
+// queries run against the checkpoint of a past height when it is given
+fn call_contract_at(
+    engine: &mut ContractInstance,
+    func_type: &str,
+    param: &str,
+    info: &MessageInfo,
+    query_height: Option<u64>,
+) -> String {
+    if CANCELLED.load(Ordering::SeqCst) {
+        return error_json(CALL_CANCELLED);
+    }
+    let contract_addr = engine.env.contract.address.to_string();
+    let param = match substitute_placeholders(param, engine, info).and_then(|param| {
+        hooks::before_call(&contract_addr, func_type, &param, info.sender.as_str())
+    }) {
+        Ok(param) => param,
+        Err(e) => {
+            if stream::listening() {
+                stream::publish_error(&contract_addr, func_type, &Value::String(e.clone()), None);
+            }
+            return error_json(&e);
+        }
+    };
+    // calls that are not queries are reverted when they or the after_call hook fail
+    let mut snapshot = match func_type.eq("query") {
+        true => None,
+        false => Some((snapshot_state(), contract_vm::engine::save_block())),
+    };
+    let result = match query_height.filter(|_| func_type.eq("query")) {
+        Some(height) => query_at_height(engine, height, &param, info),
+        None => {
+            // successful calls move the engine to the next block
+            let height = engine.env.block.height;
+            let block_height = unsafe { BLOCK_HEIGHT };
+            let data = engine
+                .with_storage(|storage| Ok(storage.data.clone()))
+                .unwrap();
+            let started = time::Instant::now();
+            // attached funds move to the contract before it runs, a failed call reverts them with its state
+            let result = match func_type.eq("query") {
+                true => compare_call(engine, func_type, &param, info),
+                false => match chain::transfer(
+                    unsafe { Config::get() },
+                    info.sender.as_str(),
+                    &engine.env.contract.address,
+                    &info.sent_funds,
+                ) {
+                    Ok(_) => compare_call(engine, func_type, &param, info),
+                    Err(e) => error_json(&format!("can not attach funds: {}", e)),
+                },
+            };
+            let duration = started.elapsed();
+            // a failed call is a failed transaction, everything it changed before the error is reverted
+            if is_error_result(&result) && unsafe { ROLLBACK } {
+                if let Some((snapshot, _)) = snapshot.take() {
+                    revert_state(snapshot);
+                    println!("{}", "state of the failed call is reverted".yellow());
+                }
+            }
+            let changes = match &snapshot {
+                Some((snapshot, _)) if stream::listening() || unsafe { STORAGE_DIFF } => {
+                    let changes = storage_changes(snapshot);
+                    if unsafe { STORAGE_DIFF } {
+                        print_storage_diff(snapshot, &changes);
                     }
+                    changes
                 }
+                _ => vec![],
+            };
+            if stream::listening() {
+                stream::publish_call(
+                    &engine.last_receipt,
+                    func_type,
+                    info.sender.as_str(),
+                    &param,
+                    height,
+                    &changes,
+                );
             }
-            _ => SystemResult::Err(SystemError::UnsupportedRequest {
-                kind: "Not implemented".to_string(),
-            }),
+            record_session(
+                engine,
+                report::CallRecord {
+                    height,
+                    contract: contract_addr.to_owned(),
+                    call_type: func_type.to_string(),
+                    sender: info.sender.to_string(),
+                    msg: param.to_owned(),
+                    result: result.to_owned(),
+                    duration,
+                    ..Default::default()
+                },
+                &data,
+            );
+            journal::record(&journal::Entry {
+                height,
+                contract: contract_addr.to_owned(),
+                code: engine.wasm_file.to_owned(),
+                call_type: func_type.to_string(),
+                sender: info.sender.to_string(),
+                funds: info.sent_funds.to_owned(),
+                msg: param.to_owned(),
+                result: result.to_owned(),
+                gas_used: engine.last_gas_used,
+            });
+            rpc::record_tx(
+                engine,
+                height,
+                func_type,
+                &param,
+                info.sender.as_str(),
+                &result,
+            );
+            if unsafe { BLOCK_HEIGHT } > block_height {
+                save_checkpoint(block_height);
+            }
+            if func_type.eq("init") && !is_error_result(&result) {
+                unsafe { Config::get() }.init_msgs.insert(
+                    contract_addr.to_owned(),
+                    (param.to_owned(), info.to_owned()),
+                );
+            }
+            if func_type.ne("query") {
+                save_storage();
+            }
+            result
+        }
+    };
+    if let Err(e) = hooks::after_call(
+        &contract_addr,
+        func_type,
+        &param,
+        info.sender.as_str(),
+        &result,
+    ) {
+        println!("{}", e.red());
+        // the hook fails the call, so the call is reverted with its block like any failed call
+        if let Some((snapshot, block)) = snapshot.filter(|_| unsafe { ROLLBACK }) {
+            revert_state(snapshot);
+            contract_vm::engine::restore_block(block);
+            save_storage();
+            println!("{}", "state of the failed call is reverted".yellow());
+        }
+        if stream::listening() {
+            stream::publish_error(&contract_addr, func_type, &Value::String(e.clone()), None);
+        }
+        return error_json(&e);
+    }
+    result
+}
+
+// state_file is the file of --state-dir holding the storage of the contract
+fn state_file(contract_addr: &str) -> Option<std::path::PathBuf> {
+    let dir = unsafe { Config::get() }.state_dir.as_ref()?;
+    let name = contract_addr.replace(
+        |c: char| !c.is_ascii_alphanumeric() && c != '_' && c != '-' && c != '.',
+        "_",
+    );
+    Some(Path::new(dir).join(format!("{}.json", name)))
+}
+
+// restore_storage loads the storage saved by an earlier run into the contract
+fn restore_storage(contract_addr: &str) {
+    let file = match state_file(contract_addr).filter(|file| file.is_file()) {
+        Some(file) => file,
+        None => return,
+    };
+    let storage = load_data_from_file(&file.to_string_lossy())
+        .and_then(|data| MockStorage::from_state(data.as_slice()));
+    match (
+        storage,
+        unsafe { Config::get() }.engines.get_mut(contract_addr),
+    ) {
+        (Ok(storage), Some(engine)) => {
+            println!(
+                "storage of [{}] is restored from {}",
+                contract_addr.blue().bold(),
+                file.to_string_lossy().blue().bold()
+            );
+            engine
+                .with_storage(|s| {
+                    *s = storage;
+                    Ok(())
+                })
+                .unwrap();
+        }
+        (Err(e), _) => println!("invalid state file {}: {}", file.to_string_lossy(), e.red()),
+        _ => {}
+    }
+}
+
+// save_storage writes the storage of every loaded contract to --state-dir, messages may change other contracts too
+fn save_storage() {
+    let Config { engines, .. } = unsafe { Config::get() };
+    for (contract_addr, engine) in engines.iter_mut() {
+        let file = match state_file(contract_addr) {
+            Some(file) => file,
+            None => return,
+        };
+        let state = engine.with_storage(|s| Ok(s.to_state())).unwrap();
+        if let Err(e) = fs::write(&file, state) {
+            println!(
+                "can not save storage to {}: {}",
+                file.to_string_lossy(),
+                e.to_string().red()
+            );
+        }
+    }
+}
+
+// snapshot_state copies the storage of every loaded contract, the balances, gov and ibc
+fn snapshot_state() -> chain::State {
+    chain::snapshot(unsafe { Config::get() })
+}
+
+// storage_changes lists the keys of every contract that changed since a snapshot with their new value,
+// none when the key was removed
+fn storage_changes(snapshot: &chain::State) -> Vec<(String, Vec<u8>, Option<Vec<u8>>)> {
+    let Config { engines, .. } = unsafe { Config::get() };
+    let empty = BTreeMap::new();
+    let mut changes = vec![];
+    for (contract_addr, engine) in engines.iter_mut() {
+        let data = engine
+            .with_storage(|storage| Ok(storage.data.clone()))
+            .unwrap();
+        // contracts instantiated by the call start empty
+        let before = snapshot
+            .contracts
+            .get(contract_addr)
+            .map_or(&empty, |storage| &storage.data);
+        let keys: BTreeSet<&Vec<u8>> = data.keys().chain(before.keys()).collect();
+        changes.extend(
+            keys.into_iter()
+                .filter(|key| data.get(*key).ne(&before.get(*key)))
+                .map(|key| {
+                    (
+                        contract_addr.to_owned(),
+                        key.to_owned(),
+                        data.get(key).cloned(),
+                    )
+                }),
+        );
+    }
+    changes
+}
+
+// revert_state restores a snapshot, contracts instantiated after it are dropped
+fn revert_state(snapshot: chain::State) {
+    let config = unsafe { Config::get() };
+    // companions compiled during the reverted call were not loaded before it, they go back to pending
+    for contract_addr in chain::revert(config, snapshot) {
+        if let Some(wasm_file) = config.compiled.remove(&contract_addr) {
+            config.pending.insert(contract_addr, wasm_file);
+        }
+    }
+}
+
+// save_snapshot keeps the state of every contract and the block under a name, an older snapshot of the name is
+// replaced, returns the number of contracts saved
+fn save_snapshot(name: &str) -> usize {
+    let state = snapshot_state();
+    let count = state.contracts.len();
+    unsafe { Config::get() }
+        .snapshots
+        .insert(name.to_string(), (state, contract_vm::engine::save_block()));
+    count
+}
+
+// restore_snapshot rewinds to a named snapshot, contracts instantiated since are dropped, returns the contracts of
+// the snapshot that were removed since and can not be restored
+fn restore_snapshot(name: &str) -> Result<Vec<String>, String> {
+    let Config {
+        snapshots, engines, ..
+    } = unsafe { Config::get() };
+    let (state, block) = snapshots
+        .get(name)
+        .cloned()
+        .ok_or_else(|| format!("No such snapshot: {}", name))?;
+    let missing = state
+        .contracts
+        .keys()
+        .filter(|contract_addr| !engines.contains_key(*contract_addr))
+        .cloned()
+        .collect();
+    revert_state(state);
+    contract_vm::engine::restore_block(block);
+    save_storage();
+    Ok(missing)
+}
+
+// print_storage_diff shows the changes of a call per contract, new keys in green, removed keys in red and modified
+// keys in yellow with their value before
+fn print_storage_diff(snapshot: &chain::State, changes: &[(String, Vec<u8>, Option<Vec<u8>>)]) {
+    if changes.is_empty() {
+        println!("{}", "storage unchanged".blue().bold());
+        return;
+    }
+    let mut contract = None;
+    for (contract_addr, key, value) in changes {
+        if contract != Some(contract_addr) {
+            println!(
+                "{} [{}]",
+                "storage diff".blue().bold(),
+                contract_addr.blue().bold()
+            );
+            contract = Some(contract_addr);
+        }
+        let before = snapshot
+            .contracts
+            .get(contract_addr)
+            .and_then(|storage| storage.data.get(key));
+        let key = storage_key_name(key);
+        match (before, value) {
+            (None, Some(value)) => println!(
+                "{}+ {} = {}",
+                INDENT,
+                key.green().bold(),
+                readable_bytes(value).green()
+            ),
+            (Some(before), None) => println!(
+                "{}- {} = {}",
+                INDENT,
+                key.red().bold(),
+                readable_bytes(before).red()
+            ),
+            (Some(before), Some(value)) => println!(
+                "{}~ {} = {} -> {}",
+                INDENT,
+                key.yellow().bold(),
+                readable_bytes(before),
+                readable_bytes(value).yellow()
+            ),
+            (None, None) => {}
+        }
+    }
+}
+
+// record_session completes the call record with gas, events and storage changes, then adds it to the session
+fn record_session(
+    engine: &mut ContractInstance,
+    record: report::CallRecord,
+    data_before: &BTreeMap<Vec<u8>, Vec<u8>>,
+) {
+    let data = engine
+        .with_storage(|storage| Ok(storage.data.clone()))
+        .unwrap();
+    let keys: BTreeSet<&Vec<u8>> = data.keys().chain(data_before.keys()).collect();
+    let state_changes = keys
+        .into_iter()
+        .filter(|key| data.get(*key).ne(&data_before.get(*key)))
+        .map(|key| report::StateChange {
+            key: storage_key_name(key),
+            before: data_before.get(key).map(|v| readable_bytes(v)),
+            after: data.get(key).map(|v| readable_bytes(v)),
+        })
+        .collect();
+    let storage_ops = contract_vm::mock::count_ops(&engine.last_storage_ops);
+    unsafe { Config::get() }.session.push(report::CallRecord {
+        gas_used: engine.last_gas_used,
+        instructions: engine.last_instructions,
+        storage_ops,
+        attributes: engine
+            .last_attributes
+            .iter()
+            .map(|attr| (attr.key.to_owned(), attr.value.to_owned()))
+            .collect(),
+        messages: engine.last_messages.to_owned(),
+        state_changes,
+        ..record
+    });
+}
+
+// save_checkpoint keeps the storage of every contract that changed since its last checkpoint
+fn save_checkpoint(height: u64) {
+    let Config {
+        engines,
+        checkpoints,
+        ..
+    } = unsafe { Config::get() };
+    let checkpoints = match checkpoints {
+        Some(checkpoints) => checkpoints,
+        None => return,
+    };
+    for (contract_addr, engine) in engines.iter_mut() {
+        let storage = engine.with_storage(|storage| Ok(storage.clone())).unwrap();
+        let history = checkpoints
+            .entry(contract_addr.to_owned())
+            .or_insert_with(BTreeMap::new);
+        if history
+            .values()
+            .next_back()
+            .map_or(false, |last| last.to_state().eq(&storage.to_state()))
+        {
+            continue;
+        }
+        history.insert(height, storage);
+    }
+}
+
+// query_at_height runs a query against the storage of the contract as of a past block,
+// contracts queried by it answer with their current state
+fn query_at_height(
+    engine: &mut ContractInstance,
+    height: u64,
+    param: &str,
+    info: &MessageInfo,
+) -> String {
+    // the latest block is the current state
+    if height + 1 >= unsafe { BLOCK_HEIGHT } {
+        return compare_call(engine, "query", param, info);
+    }
+    let contract_addr = engine.env.contract.address.to_string();
+    let checkpoint = match unsafe { Config::get() }.checkpoints.as_ref() {
+        None => return error_json("Checkpoints are not kept, start with --checkpoints"),
+        Some(checkpoints) => checkpoints
+            .get(&contract_addr)
+            .and_then(|history| history.range(..=height).next_back())
+            .map(|(_, storage)| storage.clone()),
+    };
+    let checkpoint = match checkpoint {
+        Some(checkpoint) => checkpoint,
+        None => {
+            return error_json(&format!(
+                "No state of {} at height {}",
+                contract_addr, height
+            ))
+        }
+    };
+    println!("query at height {}", height.to_string().blue().bold());
+    let current = engine
+        .with_storage(|storage| Ok(std::mem::replace(storage, checkpoint)))
+        .unwrap();
+    let result = engine.call("query", param, info).result;
+    engine
+        .with_storage(|storage| {
+            *storage = current;
+            Ok(())
+        })
+        .unwrap();
+    result
+}
+
+// compare_call calls the engine, then repeats the call on the compared build and reports divergences
+fn compare_call(
+    engine: &mut ContractInstance,
+    func_type: &str,
+    param: &str,
+    info: &MessageInfo,
+) -> String {
+    let compare = match unsafe { Config::get() }.compare.as_mut() {
+        Some(compare)
+            if compare
+                .env
+                .contract
+                .address
+                .eq(&engine.env.contract.address) =>
+        {
+            compare
+        }
+        _ => return engine.call(func_type, param, info).result,
+    };
+
+    // both builds see the same block
+    let (block, height) = (engine.env.block.clone(), unsafe { BLOCK_HEIGHT });
+    let result = engine.call(func_type, param, info).result;
+    let next_height = unsafe { BLOCK_HEIGHT };
+    unsafe { BLOCK_HEIGHT = height };
+    compare.env.block = block;
+    println!("{}", "repeating call on the compared build".blue().bold());
+    let compare_result = compare.call(func_type, param, info).result;
+    unsafe { BLOCK_HEIGHT = next_height };
+
+    let mut divergences: Vec<String> = vec![];
+    if result.ne(&compare_result) {
+        divergences.push(format!("result {} != {}", result, compare_result));
+    }
+    if engine.last_attributes.ne(&compare.last_attributes) {
+        divergences.push(format!(
+            "attributes {:?} != {:?}",
+            engine.last_attributes, compare.last_attributes
+        ));
+    }
+    if engine.last_messages.ne(&compare.last_messages) {
+        divergences.push(format!(
+            "messages {} != {}",
+            engine.last_messages, compare.last_messages
+        ));
+    }
+    let data = engine
+        .with_storage(|storage| Ok(storage.data.clone()))
+        .unwrap();
+    let compare_data = compare
+        .with_storage(|storage| Ok(storage.data.clone()))
+        .unwrap();
+    let keys: BTreeSet<&Vec<u8>> = data.keys().chain(compare_data.keys()).collect();
+    for key in keys {
+        if data.get(key).ne(&compare_data.get(key)) {
+            divergences.push(format!(
+                "state {} {} != {}",
+                storage_key_name(key),
+                data.get(key).map_or("-".to_string(), |v| readable_bytes(v)),
+                compare_data
+                    .get(key)
+                    .map_or("-".to_string(), |v| readable_bytes(v))
+            ));
+        }
+    }
+
+    match divergences.is_empty() {
+        true => println!("{}", "compared build matches".green().bold()),
+        false => {
+            println!("{}", "compared build diverges".red().bold());
+            for divergence in divergences {
+                println!("{}{}", INDENT, divergence.yellow());
+            }
+        }
+    }
+    result
+}
+
+// ignore_messages keeps the compared build from dispatching messages twice
+fn ignore_messages(_: &str, _: Vec<CosmosMsg<SpecialMsg>>) -> Vec<MessageReceipt> {
+    vec![]
+}
+
+// smart queries to this address are answered by the simulator itself
+const SIMULATOR_ADDRESS: &str = "simulator";
+
+// introspection queries of contracts and scripts that adapt to the simulation
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum SimulatorQuery {
+    Block {},
+    Contracts {},
+    Accounts {},
+    // cosmwasm 0.13 has no WasmQuery::ContractInfo, the simulator answers it instead
+    ContractInfo { address: HumanAddr },
+}
+
+fn query_simulator(msg: &Binary) -> QuerierResult {
+    let query: SimulatorQuery = match from_slice(msg) {
+        Ok(query) => query,
+        Err(e) => {
+            return SystemResult::Err(SystemError::InvalidRequest {
+                error: format!("unknown simulator query: {}", e),
+                request: msg.to_owned(),
+            })
+        }
+    };
+    let response = match query {
+        SimulatorQuery::Block {} => to_binary(&contract_vm::engine::block_info()),
+        SimulatorQuery::Contracts {} => {
+            to_binary(&serde_json::json!({ "contracts": contract_addresses() }))
+        }
+        SimulatorQuery::Accounts {} => {
+            let accounts: Vec<Value> = unsafe { Config::get() }
+                .accounts
+                .iter()
+                .map(|info| serde_json::json!({ "address": info.sender, "balance": info.sent_funds }))
+                .collect();
+            to_binary(&serde_json::json!({ "accounts": accounts }))
+        }
+        SimulatorQuery::ContractInfo { address } => match get_engine(address.as_str()) {
+            None => {
+                return SystemResult::Err(SystemError::NoSuchContract { addr: address });
+            }
+            Some(engine) => {
+                let Config { codes, .. } = unsafe { Config::get() };
+                let code_id = codes
+                    .iter()
+                    .position(|file| file.eq(&engine.wasm_file))
+                    .map(|index| index + 1);
+                to_binary(&serde_json::json!({
+                    "address": address,
+                    "code_id": code_id,
+                    "creator": null,
+                    "admin": null,
+                    "label": address,
+                    "wasm_file": engine.wasm_file,
+                }))
+            }
+        },
+    };
+    SystemResult::Ok(response.into())
+}
+
+fn query_wasm(request: &WasmQuery) -> QuerierResult {
+    match request {
+        WasmQuery::Smart { contract_addr, msg } if contract_addr.as_str().eq(SIMULATOR_ADDRESS) => {
+            query_simulator(msg)
         }
+        _ => chain::query_wasm(unsafe { Config::get() }, request),
     }
 }
 
+fn query_special(request: &SpecialQuery) -> MockQuerierCustomHandlerResult {
+    chain::query_special(unsafe { Config::get() }, request)
+}
+
 fn check_is_need_slash(name: &str) -> bool {
     // Binary is base64 string input
     if name.eq("string") {
@@ -134,89 +920,258 @@ fn check_is_need_slash(name: &str) -> bool {
     return false;
 }
 
-fn to_json_item(name: &String, type_name: &str, engine: &ContractInstance) -> String {
+// parse_input_value reads user input as json, plain text that is not json is taken as a string
+fn parse_input_value(data: &str) -> Value {
+    match serde_json::from_str(data) {
+        Ok(value) => value,
+        Err(_) => Value::String(data.to_string()),
+    }
+}
+
+// address_book lists the accounts, contracts and aliases suggested at address prompts, sorted
+fn address_book() -> Vec<String> {
+    let Config {
+        accounts, aliases, ..
+    } = unsafe { Config::get() };
+    let mut addresses: Vec<String> = accounts
+        .iter()
+        .map(|info| info.sender.to_string())
+        .chain(contract_addresses())
+        .chain(aliases.keys().cloned())
+        .collect();
+    // a contract can be an account too, dedup only drops neighbours
+    addresses.sort();
+    addresses.dedup();
+    addresses
+}
+
+// is_bech32 tells if the address is a bech32 address with the chain prefix, like --strict-address requires
+fn is_bech32(address: &str) -> bool {
+    contract_vm::mock::MockApi::new(true)
+        .canonical_address(&HumanAddr::from(address))
+        .0
+        .is_ok()
+}
+
+// strict_address gives a name that is not a bech32 address a bech32 address derived from it with --strict-address,
+// the name and the aliases of the name point to the derived address. Without the flag the name is the address
+fn strict_address(name: &str) -> String {
+    if !unsafe { contract_vm::mock::STRICT_ADDRESS } || is_bech32(name) {
+        return name.to_string();
+    }
+    // the same name gets another address under another seed
+    let seed =
+        unsafe { contract_vm::mock::SEED }.map_or(vec![], |seed| seed.to_be_bytes().to_vec());
+    let hash = Sha256::digest(&[seed.as_slice(), name.as_bytes()].concat());
+    let address = bech32::encode(
+        contract_vm::engine::BECH32_PREFIX,
+        hash[..20].to_vec().to_base32(),
+        bech32::Variant::Bech32,
+    )
+    .unwrap_or_default();
+    let Config { aliases, .. } = unsafe { Config::get() };
+    for target in aliases
+        .values_mut()
+        .filter(|target| target.as_str().eq(name))
+    {
+        *target = address.to_owned();
+    }
+    aliases.insert(name.to_string(), address.to_owned());
+    address
+}
+
+// add_account registers a named account with funds of the chain denom, with --strict-address a name that is not an
+// address becomes the alias of a bech32 address derived from it
+fn add_account(name: &str, amount: Uint128) -> Result<String, String> {
+    let Config {
+        accounts, engines, ..
+    } = unsafe { Config::get() };
+    let address = match check_address(name) {
+        Ok(address) => address,
+        Err(_) => strict_address(name),
+    };
+    let sender = HumanAddr::from(address.as_str());
+    if accounts.iter().any(|info| info.sender.eq(&sender)) {
+        return Err(format!("account {} already exists", name));
+    }
+    let funds = match amount.is_zero() {
+        true => vec![],
+        false => vec![Coin {
+            denom: DENOM.to_string(),
+            amount,
+        }],
+    };
+    accounts.push(MessageInfo {
+        sender: sender.to_owned(),
+        sent_funds: funds.to_owned(),
+    });
+    accounts.sort_by(|a, b| a.sender.cmp(&b.sender));
+    // contracts loaded later get the balance from the accounts
+    if !engines.is_empty() {
+        chain::update_bank(unsafe { Config::get() }, |bank| {
+            for coin in funds.iter() {
+                bank.credit(&sender, coin);
+            }
+            Ok(())
+        })?;
+    }
+    Ok(address)
+}
+
+// check_address resolves an alias and validates the address like the contract api will, known addresses always pass
+fn check_address(input: &str) -> Result<String, String> {
+    let address = resolve_address(input);
+    if address_book().contains(&address) {
+        return Ok(address);
+    }
+    let api = contract_vm::mock::MockApi::new(unsafe { contract_vm::mock::STRICT_ADDRESS });
+    match api.canonical_address(&HumanAddr::from(address.as_str())).0 {
+        Ok(_) => Ok(address),
+        Err(e) => Err(format!("{} is not a valid address: {}", input, e)),
+    }
+}
+
+// to_json_item reads a member, owner is the struct or message it belongs to
+fn to_json_item(
+    name: &String,
+    type_name: &str,
+    engine: &ContractInstance,
+    owner: &str,
+) -> Option<(String, Value)> {
     let (strip_type_name, optional) = match type_name.strip_suffix('?') {
         Some(s) => (s, true),
         None => (type_name, false),
     };
+    let constraints = &engine.analyzer.map_of_constraint;
+    let constraint = constraints
+        .get(&format!("{}.{}", owner, name))
+        .or_else(|| constraints.get(strip_type_name));
     let mut data: String = String::new();
+    let is_address = ["HumanAddr", "Addr"].contains(&strip_type_name);
 
     unsafe {
         let Config { editor, .. } = Config::get();
-        editor.readline(&mut data, true);
+        // values of a string enum are suggested in the history
+        if let Some(constraint) = constraint.filter(|c| !c.values.is_empty()) {
+            println!(
+                "one of [ {} ]",
+                constraint.values.join(" | ").green().bold()
+            );
+            editor.update_history_entries(constraint.values.to_owned());
+        }
+        // so are known addresses
+        if is_address {
+            editor.update_history_entries(address_book());
+        }
+        if strip_type_name.eq("Binary") {
+            println!(
+                "text is base64 encoded, start with {} to give encoded bytes",
+                "base64:".green().bold()
+            );
+        }
+        loop {
+            data.clear();
+            if !editor.readline(&mut data, true) {
+                return None;
+            }
+            // addresses the contract api would reject are asked again, aliases are replaced by their address
+            if is_address && (!data.is_empty() || !optional) {
+                match check_address(&data) {
+                    Ok(address) => data = address,
+                    Err(e) => {
+                        println!("{}, input again:", e.red());
+                        continue;
+                    }
+                }
+            }
+            if let Some(Err(e)) = data.strip_prefix("base64:").map(base64::decode) {
+                println!(
+                    "{} is not base64: {}, input again:",
+                    data,
+                    e.to_string().red()
+                );
+                continue;
+            }
+            // numbers out of the schema bounds and unknown enum values are asked again
+            let value = match constraint.map_or(true, |c| c.values.is_empty()) {
+                true => parse_input_value(&data),
+                false => Value::String(data.to_owned()),
+            };
+            match constraint.filter(|_| !data.is_empty() || !optional) {
+                Some(constraint) => match constraint.check(&value) {
+                    Ok(_) => break,
+                    Err(e) => println!("{}, input again:", e.red()),
+                },
+                None => break,
+            }
+        }
     }
 
     // do not append optional when empty
     if data.is_empty() && optional {
-        return String::new();
+        return None;
     }
 
-    // if Binary then first char is not the base64 then encode it to base64
-    if strip_type_name.eq("Binary") && !data.chars().next().unwrap().is_alphabetic() {
-        data = base64::encode(data.as_bytes());
+    // Binary input is the text to encode, bytes that are already encoded are given with the base64: prefix
+    if strip_type_name.eq("Binary") {
+        data = match data.strip_prefix("base64:") {
+            Some(encoded) => encoded.to_string(),
+            None => base64::encode(data.as_bytes()),
+        };
     }
 
     let mapped_type_name = match engine.analyzer.map_of_basetype.get(strip_type_name) {
         None => strip_type_name,
         Some(v) => v,
     };
-    let mut params = "\"".to_string();
-    params.push_str(name);
-    params.push_str("\":");
-    if check_is_need_slash(mapped_type_name) {
-        params.push('"');
-        // clear enter and add slash to double quote
-        params.push_str(data.replace('\n', "").replace('"', "\\\"").as_str());
-        params.push('"');
-    } else {
-        params.push_str(data.as_str());
-    }
+    let value = match check_is_need_slash(mapped_type_name) {
+        // clear enter, quotes are escaped when serialized
+        true => Value::String(data.replace('\n', "")),
+        false => parse_input_value(&data),
+    };
 
-    params.push(',');
-    return params;
+    Some((name.to_string(), value))
 }
 
-fn input_type(mem_name: &String, type_name: &String, engine: &ContractInstance) -> String {
+fn input_type(
+    mem_name: &String,
+    type_name: &String,
+    engine: &ContractInstance,
+    owner: &str,
+) -> Option<(String, Value)> {
     println!("input [{}]:", mem_name.blue().bold());
     let st = match engine.analyzer.map_of_struct.get_key_value(type_name) {
         Some(h) => h,
         _ => {
             // return to function, not return to st
-            return to_json_item(&mem_name, &type_name, engine);
+            return to_json_item(&mem_name, &type_name, engine, owner);
         }
     };
     //todo:need show all members by recursive invocation
-    let mut params = "\"".to_string();
-    params.push_str(mem_name);
-    params.push_str("\":");
-
     if st.1.len() == 0 {
+        let mut data = String::new();
         unsafe {
             let Config { editor, .. } = Config::get();
-            editor.readline(&mut params, true);
-        }
-    } else {
-        params.push('{');
-        // member is default sorted
-        for members in st.1 {
-            println!(
-                "input {}[{} : {}]:",
-                INDENT,
-                members.0.blue().bold(),
-                members.1.yellow()
-            );
-            params.push_str(to_json_item(&members.0, members.1, engine).as_str());
+            editor.readline(&mut data, true);
         }
-        // remove last , character
-        if st.1.len() > 0 {
-            params.pop();
-        }
-        params.push('}');
+        return Some((mem_name.to_string(), parse_input_value(&data)));
     }
 
-    params.push(',');
+    let mut params = Map::new();
+    // member is default sorted
+    for members in st.1 {
+        println!(
+            "input {}[{} : {}]:",
+            INDENT,
+            members.0.blue().bold(),
+            members.1.yellow()
+        );
+        if let Some((key, value)) = to_json_item(&members.0, members.1, engine, type_name) {
+            params.insert(key, value);
+        }
+    }
 
-    return params;
+    Some((mem_name.to_string(), Value::Object(params)))
 }
 
 fn input_message(
@@ -225,32 +1180,589 @@ fn input_message(
     engine: &ContractInstance,
     is_enum: &bool,
 ) -> String {
-    let mut final_msg: String = "{".to_string();
+    let mut option_values = Map::new();
+    for vcm in members {
+        // if there is option value then push to msg
+        if let Some((key, value)) =
+            input_type(&vcm.member_name, &vcm.member_def.to_string(), engine, name)
+        {
+            option_values.insert(key, value);
+        }
+    }
 
+    let mut final_msg = Value::Object(option_values);
     if *is_enum {
-        final_msg.push('"');
-        final_msg.push_str(name);
-        final_msg.push_str("\":{");
+        let mut msg = Map::new();
+        msg.insert(name.to_string(), final_msg);
+        final_msg = Value::Object(msg);
     }
-    let mut option_values: String = String::new();
-    for vcm in members {
-        option_values
-            .push_str(input_type(&vcm.member_name, &vcm.member_def.to_string(), engine).as_str());
+
+    final_msg.to_string()
+}
+
+// run_command runs a colon command typed at the call type prompt
+fn run_command(command: &str) {
+    let mut args = command.split_whitespace();
+    let name = args.next().unwrap_or_default();
+    let args: Vec<&str> = args.collect();
+    let session = &unsafe { Config::get() }.session;
+    match name {
+        // :metrics out.csv exports per call metrics
+        ":metrics" => {
+            let file = args.first().cloned().unwrap_or("metrics.csv");
+            match report::write_metrics(file, session) {
+                Ok(_) => println!("metrics are written to {}", file.blue().bold()),
+                Err(e) => println!("can not write metrics: {}", e.red()),
+            }
+        }
+        // :fixtures out.json exports the calls with their responses for frontend tests
+        ":fixtures" => {
+            let file = args.first().cloned().unwrap_or("fixtures.json");
+            match report::write_fixtures(file, session) {
+                Ok(_) => println!("fixtures are written to {}", file.blue().bold()),
+                Err(e) => println!("can not write fixtures: {}", e.red()),
+            }
+        }
+        // :report out.html writes the calls of the session
+        ":report" => {
+            let file = args.first().cloned().unwrap_or("report.html");
+            match report::write_html(file, session) {
+                Ok(_) => println!("session report is written to {}", file.blue().bold()),
+                Err(e) => println!("can not write session report: {}", e.red()),
+            }
+        }
+        // :load-standard cw20-base loads a prebuilt cw-plus contract
+        ":load-standard" => match standard::load(args.first().cloned().unwrap_or_default()) {
+            Ok((contract_addr, code_id)) => println!(
+                "loaded standard contract [{}] with code id {}",
+                contract_addr.blue().bold(),
+                code_id.to_string().green().bold()
+            ),
+            Err(e) => println!("{}", e.red()),
+        },
+        // :deploy path/to/contract.wasm [address] loads another contract during the session
+        ":deploy" => match args.first() {
+            None => println!("usage: {}", ":deploy <wasm_file> [address]".blue().bold()),
+            Some(wasm_file) => {
+                let contract_addr = match args.get(1) {
+                    Some(address) => strict_address(address),
+                    None => free_address(
+                        Path::new(wasm_file)
+                            .file_stem()
+                            .and_then(|stem| stem.to_str())
+                            .unwrap_or("contract"),
+                    ),
+                };
+                match deploy_wasm(wasm_file, &contract_addr) {
+                    Ok(code_id) => println!(
+                        "deployed [{}] with code id {}",
+                        contract_addr.blue().bold(),
+                        code_id.to_string().green().bold()
+                    ),
+                    Err(e) => println!("{}", e.red()),
+                }
+            }
+        },
+        // :instantiate <code id> [label] creates another contract of a loaded code, the init message is asked for
+        ":instantiate" => match args.first().map(|code_id| code_id.parse::<usize>()) {
+            Some(Ok(code_id)) => {
+                let Config {
+                    editor,
+                    current_sender,
+                    ..
+                } = unsafe { Config::get() };
+                println!("Input init json:");
+                let mut msg = String::new();
+                editor.readline(&mut msg, true);
+                let instantiated = match (
+                    read_message(&msg, Path::new("")),
+                    sender_info(current_sender),
+                ) {
+                    (Err(e), _) => Err(e),
+                    (_, None) => Err(format!("No account found: {}", current_sender)),
+                    (Ok(msg), Some(info)) => {
+                        instantiate_contract(code_id, args.get(1).cloned(), &msg, &info)
+                    }
+                };
+                match instantiated {
+                    Ok((contract_addr, result)) if !is_error_result(&result) => {
+                        println!(
+                            "instantiated [{}] from code id {}, switch to it with call type {}",
+                            contract_addr.blue().bold(),
+                            code_id.to_string().green().bold(),
+                            "contract".blue().bold()
+                        );
+                        editor.add_input_history_entry(contract_addr);
+                    }
+                    Ok((_, result)) => println!("{}", result.red()),
+                    Err(e) => println!("{}", e.red()),
+                }
+            }
+            _ => {
+                println!("usage: {}", ":instantiate <code_id> [label]".blue().bold());
+                let Config { codes, .. } = unsafe { Config::get() };
+                for (index, file) in codes.iter().enumerate() {
+                    println!("{} {}", (index + 1).to_string().green().bold(), file);
+                }
+            }
+        },
+        // :remove <address> drops a contract with its compiled module and storage
+        ":remove" => match args.first() {
+            None => println!("usage: {}", ":remove <address>".blue().bold()),
+            Some(address) => match remove_contract(&resolve_address(address)) {
+                true => println!("removed [{}]", address.blue().bold()),
+                false => println!("No such contract: {}", address.red().bold()),
+            },
+        },
+        // :reset [address] [--init] wipes the storage of one contract, the current one by default
+        ":reset" => {
+            let init = args.contains(&"--init");
+            let contract_addr = match args.iter().find(|arg| arg.ne(&&"--init")) {
+                Some(address) => resolve_address(address),
+                None => unsafe { Config::get() }.current.to_owned(),
+            };
+            match reset_storage(&contract_addr) {
+                true => {
+                    println!("storage of [{}] is reset", contract_addr.blue().bold());
+                    if init {
+                        init_again(&contract_addr);
+                    }
+                }
+                false => println!("No such contract: {}", contract_addr.red().bold()),
+            }
+        }
+        // :account lists the accounts, :account <name> [amount] adds one with funds of the chain denom
+        ":account" => match args.first() {
+            None => print_accounts(),
+            Some(name) => {
+                let amount = match args.get(1).map(|amount| amount.parse::<u128>()) {
+                    None => Ok(Uint128::from(DEFAULT_SENDER_BALANCE)),
+                    Some(Ok(amount)) => Ok(Uint128::from(amount)),
+                    Some(Err(_)) => Err(format!("wrong amount {}", args[1])),
+                };
+                match amount.and_then(|amount| add_account(name, amount)) {
+                    Ok(address) => {
+                        println!(
+                            "account {} is added at {}, switch to it with {}",
+                            name.green().bold(),
+                            address.blue().bold(),
+                            format!(":sender {}", name).blue().bold()
+                        );
+                        unsafe { Config::get() }
+                            .editor
+                            .add_input_history_entry(name.to_string());
+                    }
+                    Err(e) => println!("{}", e.red()),
+                }
+            }
+        },
+        // :env shows the block, :env set height|time|chain-id <value> and :env advance height|time <n> change it
+        ":env" => match (args.first(), args.get(1), args.get(2)) {
+            (None, _, _) => print_block(),
+            (Some(&"set"), Some(&"chain-id"), Some(chain_id)) => {
+                unsafe {
+                    contract_vm::engine::CHAIN_ID = Box::leak(chain_id.to_string().into_boxed_str())
+                };
+                print_block();
+            }
+            (Some(action), Some(field), Some(value))
+                if ["set", "advance"].contains(action) && ["height", "time"].contains(field) =>
+            {
+                let block = contract_vm::engine::block_info();
+                let now = block.time * 1_000_000_000 + block.time_nanos;
+                // time is unix seconds for set and a duration like 3600 or 1h for advance
+                let value = match (*action, *field) {
+                    ("advance", "time") => parse_duration(value),
+                    (_, "time") => value.parse::<u64>().ok().map(|secs| secs * 1_000_000_000),
+                    _ => value.parse::<u64>().ok(),
+                };
+                let changed = match (value, *action, *field) {
+                    (None, _, _) => Err(format!("wrong {} {}", field, args[2])),
+                    (Some(height), "set", "height") => {
+                        contract_vm::engine::set_block(Some(height), None)
+                    }
+                    (Some(blocks), _, "height") => {
+                        contract_vm::engine::set_block(Some(block.height + blocks), None)
+                    }
+                    (Some(time), "set", _) => contract_vm::engine::set_block(None, Some(time)),
+                    (Some(duration), _, _) => {
+                        contract_vm::engine::set_block(None, Some(now + duration))
+                    }
+                };
+                match changed {
+                    Ok(_) => print_block(),
+                    Err(e) => println!("{}", e.red()),
+                }
+            }
+            _ => println!(
+                "usage: {} | {} | {}",
+                ":env".blue().bold(),
+                ":env set height|time|chain-id <value>".blue().bold(),
+                ":env advance height|time <blocks|duration>".blue().bold()
+            ),
+        },
+        // :history lists the last calls of the session, :history <n> shows the message and result of call n
+        ":history" => match args.first().map(|index| index.parse::<usize>()) {
+            None => print_history(),
+            Some(Ok(index)) if index > 0 && index <= session.len() => {
+                let record = &session[index - 1];
+                println!(
+                    "{} {} [{}] from {} at height {}",
+                    index.to_string().blue().bold(),
+                    record.call_type.green().bold(),
+                    record.contract.blue().bold(),
+                    record.sender,
+                    record.height
+                );
+                println!("{}   : {}", "msg".blue().bold(), record.msg);
+                println!("{}: {}", "result".blue().bold(), record.result);
+            }
+            _ => println!("usage: {}", ":history [n]".blue().bold()),
+        },
+        // :snapshot lists the snapshots, :snapshot save|restore <name> keeps or rewinds to the state of every contract
+        ":snapshot" => match (args.first(), args.get(1)) {
+            (None, _) => {
+                for name in unsafe { Config::get() }.snapshots.keys() {
+                    println!("{}", name.green().bold());
+                }
+            }
+            (Some(&"save"), Some(name)) => println!(
+                "snapshot {} is saved with {} contracts",
+                name.green().bold(),
+                save_snapshot(name)
+            ),
+            (Some(&"restore"), Some(name)) => match restore_snapshot(name) {
+                Ok(missing) => {
+                    println!("snapshot {} is restored", name.green().bold());
+                    for contract_addr in missing {
+                        println!(
+                            "[{}] was removed since the snapshot and is not restored",
+                            contract_addr.yellow().bold()
+                        );
+                    }
+                    print_block();
+                }
+                Err(e) => println!("{}", e.red()),
+            },
+            _ => println!(
+                "usage: {} | {}",
+                ":snapshot".blue().bold(),
+                ":snapshot save|restore <name>".blue().bold()
+            ),
+        },
+        // :proof <hex key> [address] proves a storage key, or its absence, against the iavl root
+        ":proof" => match args.first() {
+            None => println!("usage: {}", ":proof <hex_key> [address]".blue().bold()),
+            Some(key) => {
+                let contract_addr = match args.get(1) {
+                    Some(address) => resolve_address(address),
+                    None => unsafe { Config::get() }.current.to_owned(),
+                };
+                match contract_proof(&contract_addr, key) {
+                    Ok(proof) => println!(
+                        "{}\nproof is verified against the root",
+                        serde_json::to_string_pretty(&proof).unwrap_or_default()
+                    ),
+                    Err(e) => println!("{}", e.red()),
+                }
+            }
+        },
+        _ => println!("unknown command {}", command.red().bold()),
     }
+}
 
-    // if there is option value then push to msg
-    if option_values.len() > 0 {
-        option_values.pop();
-        final_msg.push_str(option_values.as_str());
+// print_history lists the last calls of the session with their gas and status
+fn print_history() {
+    let Config { session, .. } = unsafe { Config::get() };
+    let skipped = session.len().saturating_sub(HISTORY_CALLS);
+    for (index, record) in session.iter().enumerate().skip(skipped) {
+        let status = match is_error_result(&record.result) {
+            true => "failed".red(),
+            false => "ok".green(),
+        };
+        println!(
+            "{} {} {} [{}] {} gas {} {}",
+            (index + 1).to_string().blue().bold(),
+            record.height,
+            record.call_type.green().bold(),
+            record.contract.blue().bold(),
+            record.sender,
+            record.gas_used.to_string().yellow(),
+            status
+        );
     }
+}
 
-    final_msg.push('}');
+// print_accounts lists the accounts with their aliases and bank balances
+fn print_accounts() {
+    let Config {
+        accounts,
+        aliases,
+        current,
+        ..
+    } = unsafe { Config::get() };
+    let bank = get_engine(current)
+        .map(|engine| engine.with_querier(|querier| Ok(querier.bank())).unwrap());
+    for info in accounts.iter() {
+        let names: Vec<&str> = aliases
+            .iter()
+            .filter(|(_, address)| address.as_str().eq(info.sender.as_str()))
+            .map(|(name, _)| name.as_str())
+            .collect();
+        println!(
+            "{} {} {:?}",
+            info.sender.as_str().green().bold(),
+            names.join(", ").blue(),
+            bank.as_ref().map_or(info.sent_funds.to_owned(), |bank| bank
+                .balance(&info.sender))
+        );
+    }
+}
 
-    if *is_enum {
-        final_msg.push('}');
+fn print_block() {
+    let block = contract_vm::engine::block_info();
+    println!(
+        "chain {}, height {}, time {}.{:09}",
+        block.chain_id.green().bold(),
+        block.height.to_string().green().bold(),
+        block.time.to_string().green().bold(),
+        block.time_nanos
+    );
+}
+
+// remove_contract forgets everything kept about the contract, aliases of it included
+fn remove_contract(contract_addr: &str) -> bool {
+    let Config {
+        engines,
+        pending,
+        compiled,
+        compare,
+        aliases,
+        checkpoints,
+        init_msgs,
+        removed,
+        ..
+    } = unsafe { Config::get() };
+    if engines.remove(contract_addr).is_none() && pending.remove(contract_addr).is_none() {
+        return false;
+    }
+    if compare.as_ref().map_or(false, |engine| {
+        engine.env.contract.address.eq(contract_addr)
+    }) {
+        *compare = None;
+    }
+    compiled.remove(contract_addr);
+    aliases.retain(|_, address| address.as_str().ne(contract_addr));
+    if let Some(checkpoints) = checkpoints {
+        checkpoints.remove(contract_addr);
+    }
+    init_msgs.remove(contract_addr);
+    removed.insert(contract_addr.to_string());
+    if let Some(file) = state_file(contract_addr) {
+        let _ = fs::remove_file(file);
+    }
+    true
+}
+
+// contract_proof proves a hex storage key against the iavl root of the contract, storage changed outside of calls is
+// saved as a new version first
+fn contract_proof(contract_addr: &str, key: &str) -> Result<Value, String> {
+    if unsafe { !contract_vm::mock::IAVL_STORAGE } {
+        return Err("proofs need the iavl tree, start with --iavl".to_string());
+    }
+    let key = hex::decode(key.trim_start_matches("0x"))
+        .map_err(|e| format!("wrong key {}, must be hex: {}", key, e))?;
+    let engine =
+        get_engine(contract_addr).ok_or_else(|| format!("No such contract: {}", contract_addr))?;
+    let (root, version, proof) = engine
+        .with_storage(|storage| {
+            storage.commit(unsafe { BLOCK_HEIGHT });
+            let tree = storage.tree.as_ref().unwrap();
+            Ok((tree.root_hash(), tree.version, tree.prove(&key)))
+        })
+        .unwrap();
+    proof.verify(&root)?;
+    Ok(serde_json::json!({
+        "root": hex::encode(root),
+        "version": version,
+        "proof": proof,
+    }))
+}
+
+// reset_storage replaces the storage of the contract and of its compared build with empty storage
+fn reset_storage(contract_addr: &str) -> bool {
+    let Config { compare, .. } = unsafe { Config::get() };
+    let engine = match get_engine(contract_addr) {
+        Some(engine) => engine,
+        None => return false,
+    };
+    let compared = compare
+        .as_mut()
+        .filter(|compare| compare.env.contract.address.eq(contract_addr));
+    for engine in std::iter::once(engine).chain(compared.map(|compare| compare.as_mut())) {
+        engine
+            .with_storage(|storage| {
+                *storage = MockStorage::default();
+                Ok(())
+            })
+            .unwrap();
+    }
+    true
+}
+
+// free_address returns the name when no contract or alias uses it, otherwise name_1, name_2..., with
+// --strict-address the free name becomes the alias of a derived bech32 address
+fn free_address(name: &str) -> String {
+    let contracts = contract_addresses();
+    let Config { aliases, .. } = unsafe { Config::get() };
+    let mut contract_addr = name.to_string();
+    let mut index = 1;
+    while contracts.contains(&contract_addr) || aliases.contains_key(&contract_addr) {
+        contract_addr = format!("{}_{}", name, index);
+        index += 1;
+    }
+    strict_address(&contract_addr)
+}
+
+// deploy_wasm compiles a wasm file as a new code and loads it at the address, returns the code id
+fn deploy_wasm(wasm_file: &str, contract_addr: &str) -> Result<usize, String> {
+    if !wasm_file.ends_with(".wasm") || !Path::new(wasm_file).is_file() {
+        return Err(format!("wasm file {} not found", wasm_file));
+    }
+    if contract_addresses()
+        .iter()
+        .any(|addr| addr.eq(contract_addr))
+    {
+        return Err(format!("contract {} already loaded", contract_addr));
+    }
+    if !insert_engine(
+        wasm_file,
+        contract_addr,
+        query_wasm,
+        &MockStorage::default(),
+    ) {
+        return Err(format!("can not load {}", wasm_file));
+    }
+    let Config { codes, removed, .. } = unsafe { Config::get() };
+    removed.remove(contract_addr);
+    codes.push(wasm_file.to_string());
+    Ok(codes.len())
+}
+
+// code_file returns the wasm file of a code id or the given wasm file
+fn code_file(code: &str) -> Result<String, String> {
+    let Config { codes, .. } = unsafe { Config::get() };
+    if let Ok(code_id) = code.parse::<usize>() {
+        return codes
+            .get(code_id.wrapping_sub(1))
+            .cloned()
+            .ok_or_else(|| format!("No such code: {}", code_id));
+    }
+    match code.ends_with(".wasm") && Path::new(code).is_file() {
+        true => Ok(code.to_string()),
+        false => Err(format!("wasm file {} not found", code)),
+    }
+}
+
+// migrate_contract calls migrate of the contract, with new_code (a code id or a wasm file) it first loads the new
+// code with the storage and balances of the contract, the old code is put back when migrate fails
+fn migrate_contract(
+    contract_addr: &str,
+    new_code: Option<&str>,
+    msg: &str,
+    info: &MessageInfo,
+) -> String {
+    let wasm_file = match new_code.filter(|code| !code.is_empty()).map(code_file) {
+        None => {
+            return match get_engine(contract_addr) {
+                Some(engine) => call_contract(engine, "migrate", msg, info),
+                None => error_json(&format!("No such contract: {}", contract_addr)),
+            }
+        }
+        Some(Err(e)) => return error_json(&e),
+        Some(Ok(wasm_file)) => wasm_file,
+    };
+    let Config { engines, codes, .. } = unsafe { Config::get() };
+    let mut old = match engines.remove(contract_addr) {
+        Some(engine) => engine,
+        None => return error_json(&format!("No such contract: {}", contract_addr)),
+    };
+    let storage = old.with_storage(|storage| Ok(storage.clone())).unwrap();
+    let bank = old.with_querier(|querier| Ok(querier.bank())).unwrap();
+    if !insert_engine(&wasm_file, contract_addr, query_wasm, &storage) {
+        engines.insert(contract_addr.to_owned(), old);
+        return error_json(&format!("can not load {}", wasm_file));
     }
+    let engine = get_engine(contract_addr).unwrap();
+    engine
+        .with_querier(|querier| {
+            querier.set_bank(bank);
+            Ok(())
+        })
+        .unwrap();
+    println!(
+        "migrating [{}] to [{}]",
+        contract_addr.blue().bold(),
+        wasm_file.blue().bold()
+    );
+    let result = call_contract(engine, "migrate", msg, info);
+    if is_error_result(&result) {
+        engines.insert(contract_addr.to_owned(), old);
+        println!("{}", "migrate failed, the old code is kept".yellow());
+    } else if !codes.contains(&wasm_file) {
+        codes.push(wasm_file);
+    }
+    result
+}
+
+// read_funds asks for the coins attached to a call, empty input attaches none
+fn read_funds() -> Result<Vec<Coin>, String> {
+    let Config { editor, .. } = unsafe { Config::get() };
+    println!(
+        "Input funds like {}, empty for none:",
+        format!("100{}", DENOM).blue().bold()
+    );
+    let mut funds = String::new();
+    editor.readline(&mut funds, false);
+    parse_coins(&funds)
+}
+
+// parse_coins reads coins like 100orai,5uatom
+fn parse_coins(text: &str) -> Result<Vec<Coin>, String> {
+    text.split(',')
+        .map(str::trim)
+        .filter(|coin| !coin.is_empty())
+        .map(|coin| {
+            let split = coin
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(coin.len());
+            let (amount, denom) = coin.split_at(split);
+            match (amount.parse::<u128>(), denom.is_empty()) {
+                (Ok(amount), false) => Ok(Coin {
+                    denom: denom.to_string(),
+                    amount: Uint128::from(amount),
+                }),
+                _ => Err(format!("wrong coin {}, must be like 100{}", coin, DENOM)),
+            }
+        })
+        .collect()
+}
 
-    final_msg
+// read_new_code asks for the code a migrate call moves the contract to
+fn read_new_code() -> String {
+    let Config { editor, codes, .. } = unsafe { Config::get() };
+    println!(
+        "Input new code id or wasm file, empty keeps the code of the contract [ {} ]",
+        codes
+            .iter()
+            .enumerate()
+            .map(|(index, file)| format!("{}: {}", index + 1, file))
+            .collect::<Vec<String>>()
+            .join(" | ")
+    );
+    let mut new_code = String::new();
+    editor.readline(&mut new_code, false);
+    new_code.trim().to_string()
 }
 
 // get_call_type return value and indicate it is contract switch or account switch
@@ -260,26 +1772,43 @@ fn get_call_type() -> Option<(String, bool, bool)> {
         "init".to_string(),
         "handle".to_string(),
         "query".to_string(),
+        "migrate".to_string(),
+        "gov".to_string(),
+        "alias".to_string(),
+        "height".to_string(),
     ];
     let mut contract_switch = false;
     let mut account_switch = false;
 
     print!(
-        "Input call type ({} | {} | {}",
+        "Input call type ({} | {} | {} | {} | {} | {} | {}",
         "init".green().bold(),
         "handle".green().bold(),
         "query".green().bold(),
+        "migrate".green().bold(),
+        "gov".blue().bold(),
+        "alias".blue().bold(),
+        "height".blue().bold(),
     );
     unsafe {
         let Config {
-            engines,
             accounts,
             editor,
+            ibc,
+            aliases,
+            ..
         } = Config::get();
-        if engines.len() > 1 {
+        let contracts = contract_addresses();
+        // packets can be delivered once a transfer is sent
+        if !ibc.packets.is_empty() {
+            print!(" | {}", "ibc".blue().bold());
+            params.push("ibc".to_string());
+        }
+        if contracts.len() > 1 {
             contract_switch = true;
         }
-        if accounts.len() > 1 {
+        // loaded contracts can be chosen as sender too
+        if accounts.len() + contracts.len() > 1 {
             account_switch = true;
         }
         if contract_switch {
@@ -297,14 +1826,32 @@ fn get_call_type() -> Option<(String, bool, bool)> {
         println!(")");
 
         editor.readline(&mut call_type, false);
+        // commands and the gov, ibc and alias prompts use the engines too, the watcher waits until they are done
+        let _engines = lock_engines();
+
+        // :sender <name> switches the sender like call type account
+        if let Some(name) = call_type.strip_prefix(":sender ") {
+            let name = name.trim();
+            if sender_info(name).is_none() {
+                println!("Account {} not existed", name.red().bold());
+                return None;
+            }
+            return Some((name.to_string(), false, true));
+        }
+        if call_type.starts_with(':') {
+            run_command(&call_type);
+            return None;
+        }
 
         if !params.contains(&call_type) {
             print!(
-                "Wrong call type [{}], must one of ({} | {} | {}",
+                "Wrong call type [{}], must one of ({} | {} | {} | {} | {}",
                 call_type.red().bold(),
                 "init".green().bold(),
                 "handle".green().bold(),
                 "query".green().bold(),
+                "migrate".green().bold(),
+                "gov".green().bold(),
             );
             if contract_switch {
                 print!(" | {}", "contract".green().bold());
@@ -324,7 +1871,7 @@ fn get_call_type() -> Option<(String, bool, bool)> {
 
             editor.clear_history();
 
-            for k in sorted(engines.keys()) {
+            for k in contracts.iter() {
                 if first {
                     first = false;
                 } else {
@@ -333,13 +1880,18 @@ fn get_call_type() -> Option<(String, bool, bool)> {
                 print!("{}", k.green().bold());
                 editor.add_history_entry(k);
             }
+            for k in sorted(aliases.keys()) {
+                print!(" | {}", k.blue().bold());
+                editor.add_history_entry(k);
+            }
 
             print!(" ]\n");
 
             editor.readline(&mut call_param, false);
+            let call_param = resolve_address(&call_param);
 
             // check contract existed
-            if engines.get(&call_param).is_none() {
+            if !contracts.contains(&call_param) {
                 println!("Smart contract {} not existed", call_param.red().bold());
                 return None;
             }
@@ -362,34 +1914,268 @@ fn get_call_type() -> Option<(String, bool, bool)> {
                 print!("{}", info.sender.as_str().green().bold());
                 editor.add_history_entry(info.sender.as_str());
             }
+            for k in contracts.iter() {
+                print!(" | {}", k.blue().bold());
+                editor.add_history_entry(k);
+            }
 
             print!(" ]\n");
 
             editor.readline(&mut call_param, false);
 
             // check account existed
-            if !accounts
-                .iter()
-                .map(|x| x.sender.to_string())
-                .collect::<Vec<String>>()
-                .contains(&call_param)
-            {
+            if sender_info(&call_param).is_none() {
                 println!("Account {} not existed", call_param.red().bold());
                 return None;
             }
 
             // return contract as switch param
             return Some((call_param, false, true));
+        } else if call_type.eq("gov") {
+            simulate_gov();
+            return None;
+        } else if call_type.eq("ibc") {
+            simulate_ibc();
+            return None;
+        } else if call_type.eq("alias") {
+            simulate_alias();
+            return None;
+        } else if call_type.eq("height") {
+            simulate_height();
+            return None;
         }
     }
 
     return Some((call_type, false, false));
 }
 
+// sender_info returns the message info of an account, or of a loaded contract acting as sender
+fn sender_info(sender_addr: &str) -> Option<MessageInfo> {
+    let sender_addr = resolve_address(sender_addr);
+    let sender_addr = sender_addr.as_str();
+    unsafe {
+        let Config { accounts, .. } = Config::get();
+        // sent funds of an account are its starting balance, calls attach no funds unless they are given
+        match accounts.iter().find(|x| x.sender.as_str().eq(sender_addr)) {
+            Some(info) => Some(MessageInfo {
+                sender: info.sender.to_owned(),
+                sent_funds: vec![],
+            }),
+            None if contract_addresses().iter().any(|k| k.eq(sender_addr)) => Some(MessageInfo {
+                sender: HumanAddr::from(sender_addr),
+                sent_funds: vec![],
+            }),
+            None => None,
+        }
+    }
+}
+
+// simulate_gov manages proposals of the mock gov module that contracts can query and vote on
+fn simulate_gov() {
+    unsafe {
+        let Config { editor, gov, .. } = Config::get();
+        let mut action = String::new();
+        println!(
+            "Choose gov action [ {} | {} | {} ]",
+            "propose".green().bold(),
+            "list".green().bold(),
+            "status".green().bold()
+        );
+        editor.update_history_entries(vec![
+            "propose".to_string(),
+            "list".to_string(),
+            "status".to_string(),
+        ]);
+        editor.readline(&mut action, false);
+
+        match action.as_str() {
+            "propose" => {
+                let mut title = String::new();
+                let mut description = String::new();
+                println!("input [{}]:", "title".blue().bold());
+                editor.readline(&mut title, true);
+                println!("input [{}]:", "description".blue().bold());
+                editor.readline(&mut description, true);
+                let proposal_id = gov.propose(&title, &description);
+                println!(
+                    "Proposal {} is in voting period",
+                    proposal_id.to_string().green().bold()
+                );
+            }
+            "list" => {
+                for proposal in gov.proposals.iter() {
+                    println!(
+                        "{} {} [{}] {}",
+                        proposal.proposal_id.to_string().blue().bold(),
+                        proposal.title.green().bold(),
+                        format!("{:?}", proposal.status).yellow(),
+                        proposal.description
+                    );
+                    for (voter, vote) in gov.votes(proposal.proposal_id) {
+                        println!("{}{} : {:?}", INDENT, voter.as_str().blue(), vote);
+                    }
+                }
+            }
+            "status" => {
+                let mut proposal_id = String::new();
+                let mut status = String::new();
+                println!("input [{}]:", "proposal_id".blue().bold());
+                editor.readline(&mut proposal_id, false);
+                println!(
+                    "input [{}]: ({} | {} | {} | {})",
+                    "status".blue().bold(),
+                    "voting_period".green().bold(),
+                    "passed".green().bold(),
+                    "rejected".green().bold(),
+                    "failed".green().bold()
+                );
+                editor.readline(&mut status, false);
+                let status: ProposalStatus = match from_slice(format!("\"{}\"", status).as_bytes())
+                {
+                    Ok(s) => s,
+                    Err(_) => {
+                        println!("Wrong proposal status [{}]", status.red().bold());
+                        return;
+                    }
+                };
+                if !gov.set_status(proposal_id.parse().unwrap_or_default(), status) {
+                    println!("Proposal {} not existed", proposal_id.red().bold());
+                }
+            }
+            _ => println!("Wrong gov action [{}]", action.red().bold()),
+        }
+    }
+}
+
+// simulate_height sets the past height queries run at, empty input goes back to the latest state
+fn simulate_height() {
+    unsafe {
+        let Config {
+            editor,
+            query_height,
+            ..
+        } = Config::get();
+        let mut height = String::new();
+        println!(
+            "input [{}], empty for the latest block:",
+            "height".blue().bold()
+        );
+        editor.readline(&mut height, false);
+        if height.is_empty() {
+            *query_height = None;
+            println!("queries run at the latest block");
+            return;
+        }
+        match height.parse::<u64>() {
+            Ok(h) => {
+                *query_height = Some(h);
+                println!("queries run at height {}", h.to_string().blue().bold());
+            }
+            Err(_) => println!("wrong height {}", height.red()),
+        }
+    }
+}
+
+// simulate_alias names a contract address, an empty address lists the aliases
+fn simulate_alias() {
+    unsafe {
+        let Config {
+            editor, aliases, ..
+        } = Config::get();
+        let mut alias = String::new();
+        let mut address = String::new();
+        println!("input [{}]:", "alias".blue().bold());
+        editor.readline(&mut alias, false);
+        println!("input [{}]:", "address".blue().bold());
+        editor.update_history_entries(contract_addresses());
+        editor.readline(&mut address, false);
+
+        if !alias.is_empty() && !address.is_empty() {
+            if !contract_addresses().contains(&address) {
+                println!("Smart contract {} not existed", address.red().bold());
+                return;
+            }
+            aliases.insert(alias, address);
+        }
+        for k in sorted(aliases.keys()) {
+            println!("{} => {}", k.blue().bold(), aliases[k].green().bold());
+        }
+    }
+}
+
+// simulate_ibc delivers acknowledgements or timeouts for packets that are not relayed yet
+fn simulate_ibc() {
+    unsafe {
+        let Config { editor, ibc, .. } = Config::get();
+        let mut action = String::new();
+        println!(
+            "Choose ibc action [ {} | {} | {} | {} ]",
+            "list".green().bold(),
+            "ack".green().bold(),
+            "error".green().bold(),
+            "timeout".green().bold()
+        );
+        editor.update_history_entries(vec![
+            "list".to_string(),
+            "ack".to_string(),
+            "error".to_string(),
+            "timeout".to_string(),
+        ]);
+        editor.readline(&mut action, false);
+
+        let state = match action.as_str() {
+            "list" => {
+                for packet in ibc.packets.iter() {
+                    println!(
+                        "{} {} {}{} from {} to {} [{}]",
+                        packet.sequence.to_string().blue().bold(),
+                        packet.channel_id.green().bold(),
+                        packet.amount.amount,
+                        packet.amount.denom,
+                        packet.sender,
+                        packet.to_address,
+                        format!("{:?}", packet.state).yellow()
+                    );
+                }
+                return;
+            }
+            "ack" => PacketState::Acknowledged,
+            "error" => {
+                let mut error = String::new();
+                println!("input [{}]:", "error".blue().bold());
+                editor.readline(&mut error, true);
+                PacketState::Failed(error)
+            }
+            "timeout" => PacketState::TimedOut,
+            _ => {
+                println!("Wrong ibc action [{}]", action.red().bold());
+                return;
+            }
+        };
+
+        let mut sequence = String::new();
+        println!("input [{}]:", "sequence".blue().bold());
+        editor.readline(&mut sequence, false);
+        // failed transfers are refunded to the sending contract
+        match chain::deliver_packet(Config::get(), sequence.parse().unwrap_or_default(), state) {
+            Ok(None) => {}
+            Ok(Some((packet, balance))) => println!(
+                "refunded {}{} to {}, balance {:?}",
+                packet.amount.amount,
+                packet.amount.denom,
+                packet.sender.as_str().green().bold(),
+                balance
+            ),
+            Err(e) => println!("{}", e.red()),
+        }
+    }
+}
+
 fn simulate_by_auto_analyze(
-    engine: &mut ContractInstance,
+    mut engine: &mut ContractInstance,
     sender_addr: &str,
 ) -> Result<(bool, String, String), String> {
+    let contract_addr = engine.env.contract.address.to_string();
     // enable debug, show info
     if cfg!(debug_assertions) {
         engine.analyzer.dump_all_members();
@@ -397,26 +2183,32 @@ fn simulate_by_auto_analyze(
     }
 
     unsafe {
-        let Config {
-            accounts, editor, ..
-        } = Config::get();
-        let info = match accounts.iter().find(|x| x.sender.as_str().eq(sender_addr)) {
+        let Config { editor, .. } = Config::get();
+        let info = match sender_info(sender_addr) {
             Some(i) => i,
             None => return Err(format!("No account found: {}", sender_addr)),
         };
 
         loop {
+            // back at the main prompt, a cancelled operation is over
+            CANCELLED.store(false, Ordering::SeqCst);
+            if let Some(next) = next_contract(&contract_addr, sender_addr) {
+                return next;
+            }
             println!(
                 "Start_simulate with sender: {}, contract: {}, chain: {}, denom: {}, block height: {}",
                 sender_addr.green().bold(),
-                engine.env.contract.address.green().bold(),
-                CHAIN_ID.green().bold(), DENOM.green().bold(), BLOCK_HEIGHT.to_string().green().bold()
+                contract_addr.green().bold(),
+                CHAIN_ID.green().bold(), DENOM.green().bold(), { BLOCK_HEIGHT }.to_string().green().bold()
             );
 
             let (call_type, contract_switch, account_switch) = match get_call_type() {
                 None => continue,
                 Some(s) => s,
             };
+            // the watcher reloads contracts between calls, never during one
+            let _engines = lock_engines();
+            engine = refresh_engine(engine, &contract_addr);
 
             let mut call_param = String::new();
             let mut first = true;
@@ -444,6 +2236,10 @@ fn simulate_by_auto_analyze(
                 && engine.analyzer.map_of_member.contains_key("QueryMsg")
             {
                 call_param = "QueryMsg".to_string();
+            } else if call_type.eq("migrate")
+                && engine.analyzer.map_of_member.contains_key("MigrateMsg")
+            {
+                call_param = "MigrateMsg".to_string();
             } else {
                 print!("Input Call param from [ ");
 
@@ -517,35 +2313,62 @@ fn simulate_by_auto_analyze(
             // update previous history entries
             editor.update_input_history_entry();
 
-            engine.call(call_type.as_str(), json_msg.as_str(), info);
+            let info = match call_type.eq("query") {
+                true => info.to_owned(),
+                false => match read_funds() {
+                    Ok(sent_funds) => MessageInfo {
+                        sender: info.sender.to_owned(),
+                        sent_funds,
+                    },
+                    Err(e) => {
+                        println!("{}", e.red());
+                        continue;
+                    }
+                },
+            };
+            engine = refresh_engine(engine, &contract_addr);
+            if call_type.eq("migrate") {
+                // the engine is replaced by the new code, the REPL starts again with it
+                let new_code = read_new_code();
+                migrate_contract(&contract_addr, Some(&new_code), &json_msg, &info);
+                return Ok((true, contract_addr, sender_addr.to_string()));
+            }
+            call_contract(engine, call_type.as_str(), json_msg.as_str(), &info);
         }
     }
 }
 
 fn simulate_by_json(
-    engine: &mut ContractInstance,
+    mut engine: &mut ContractInstance,
     sender_addr: &str,
 ) -> Result<(bool, String, String), String> {
+    let contract_addr = engine.env.contract.address.to_string();
     unsafe {
-        let Config {
-            accounts, editor, ..
-        } = Config::get();
-        let info = match accounts.iter().find(|x| x.sender.as_str().eq(sender_addr)) {
+        let Config { editor, .. } = Config::get();
+        let info = match sender_info(sender_addr) {
             Some(i) => i,
             None => return Err(format!("No account found: {}", sender_addr)),
         };
 
         loop {
+            // back at the main prompt, a cancelled operation is over
+            CANCELLED.store(false, Ordering::SeqCst);
+            if let Some(next) = next_contract(&contract_addr, sender_addr) {
+                return next;
+            }
             println!(
                 "Start_simulate with sender: {}, contract: {}, chain: {}, denom: {}, block height: {}",
                 sender_addr.green().bold(),
-                engine.env.contract.address.green().bold(),
-                CHAIN_ID.green().bold(), DENOM.green().bold(), BLOCK_HEIGHT.to_string().green().bold()
+                contract_addr.green().bold(),
+                CHAIN_ID.green().bold(), DENOM.green().bold(), { BLOCK_HEIGHT }.to_string().green().bold()
             );
             let (call_type, contract_switch, account_switch) = match get_call_type() {
                 None => continue,
                 Some(s) => s,
             };
+            // the watcher reloads contracts between calls, never during one
+            let _engines = lock_engines();
+            engine = refresh_engine(engine, &contract_addr);
 
             // default messages
             if contract_switch {
@@ -567,10 +2390,78 @@ fn simulate_by_json(
 
             editor.update_input_history_entry();
             editor.readline(&mut json_msg, true);
+            let json_msg = match read_message(&json_msg, Path::new("")) {
+                Ok(msg) => msg,
+                Err(e) => {
+                    println!("{}", e.red());
+                    continue;
+                }
+            };
+
+            let info = match call_type.eq("query") {
+                true => info.to_owned(),
+                false => match read_funds() {
+                    Ok(sent_funds) => MessageInfo {
+                        sender: info.sender.to_owned(),
+                        sent_funds,
+                    },
+                    Err(e) => {
+                        println!("{}", e.red());
+                        continue;
+                    }
+                },
+            };
+            engine = refresh_engine(engine, &contract_addr);
+            if call_type.eq("migrate") {
+                // the engine is replaced by the new code, the REPL starts again with it
+                let new_code = read_new_code();
+                migrate_contract(&contract_addr, Some(&new_code), &json_msg, &info);
+                return Ok((true, contract_addr, sender_addr.to_string()));
+            }
+            call_contract(engine, call_type.as_str(), json_msg.as_str(), &info);
+        }
+    }
+}
+
+// set by the watcher when it swaps in a rebuilt contract, the REPL then looks its engine up again
+static RELOADED: AtomicBool = AtomicBool::new(false);
 
-            engine.call(call_type.as_str(), json_msg.as_str(), info);
+// refresh_engine returns the engine the watcher swapped in while a prompt was open, the old one is gone
+fn refresh_engine<'a>(
+    engine: &'a mut ContractInstance,
+    contract_addr: &str,
+) -> &'a mut ContractInstance {
+    if !RELOADED.swap(false, Ordering::SeqCst) {
+        return engine;
+    }
+    match get_engine(contract_addr) {
+        Some(engine) => {
+            println!(
+                "using the reloaded build of [{}], suggestions are refreshed",
+                contract_addr.blue().bold()
+            );
+            engine
         }
+        None => engine,
+    }
+}
+
+// next_contract returns where the REPL continues when its contract was removed with :remove
+fn next_contract(
+    contract_addr: &str,
+    sender_addr: &str,
+) -> Option<Result<(bool, String, String), String>> {
+    if unsafe { Config::get() }.engines.contains_key(contract_addr) {
+        return None;
     }
+    Some(match contract_addresses().into_iter().next() {
+        Some(next) => Ok((true, next, sender_addr.to_string())),
+        None => Err("no contract left".to_string()),
+    })
+}
+
+extern "C" fn cancel_call(_: i32) {
+    CANCELLED.store(true, Ordering::SeqCst);
 }
 
 // start_simulate will return next contract and account to run
@@ -578,22 +2469,22 @@ fn start_simulate(
     contract_addr: &str,
     sender_addr: &str,
 ) -> Result<(bool, String, String), String> {
-    unsafe {
-        let Config { engines, .. } = Config::get();
-        match engines.get_mut(contract_addr) {
-            Some(engine) => {
-                // enable debug
-                if cfg!(debug_assertions) {
-                    engine.show_module_info();
-                }
-                if engine.analyzer.map_of_member.is_empty() {
-                    simulate_by_json(engine, sender_addr)
-                } else {
-                    simulate_by_auto_analyze(engine, sender_addr)
-                }
+    match get_engine(contract_addr) {
+        Some(engine) => {
+            let config = unsafe { Config::get() };
+            config.current = engine.env.contract.address.to_string();
+            config.current_sender = sender_addr.to_string();
+            // enable debug
+            if cfg!(debug_assertions) {
+                engine.show_module_info();
+            }
+            if engine.analyzer.map_of_member.is_empty() {
+                simulate_by_json(engine, sender_addr)
+            } else {
+                simulate_by_auto_analyze(engine, sender_addr)
             }
-            None => Err(format!("No engine found: {}", contract_addr)),
         }
+        None => Err(format!("No engine found: {}", contract_addr)),
     }
 }
 
@@ -668,41 +2559,102 @@ fn load_artifacts(
     Ok(file_paths)
 }
 
-// handle_contract_response currently support execute only, with new message info from send fund param
-fn handle_contract_response(sender_addr: &str, messages: Vec<CosmosMsg>) -> Vec<Attribute> {
-    let mut attributes: Vec<Attribute> = vec![];
-    unsafe {
-        let Config { engines, .. } = Config::get();
-        for msg in messages {
-            // only clone required properties
-            if let CosmosMsg::Wasm(WasmMsg::Execute {
-                contract_addr,
-                msg,
-                send,
-            }) = &msg
-            {
-                let result = match engines.get_mut(contract_addr.as_str()) {
-                    None => format!("No such contract: {}", contract_addr),
-                    Some(engine) => {
-                        engine.handle_raw(
-                            msg.as_slice(),
-                            &MessageInfo {
-                                sender: HumanAddr::from(sender_addr),
-                                // there is default account with balance
-                                sent_funds: send.clone(),
-                            },
-                        )
-                    }
-                };
-                attributes.push(Attribute {
-                    key: contract_addr.to_string(),
-                    value: result,
-                })
-            }
-        }
+// share_bank gives a new contract the balances of the loaded ones and tells them its own balance, a reloaded
+// contract keeps the balance it had. Accounts start with their configured funds
+fn share_bank(contract_addr: &str) {
+    let Config {
+        engines, accounts, ..
+    } = unsafe { Config::get() };
+    let own = match engines.get_mut(contract_addr) {
+        None => return,
+        Some(engine) => engine.with_querier(|querier| Ok(querier.bank())).unwrap(),
+    };
+    let mut shared = engines
+        .iter_mut()
+        .find(|(addr, _)| addr.as_str().ne(contract_addr))
+        .map(|(_, engine)| engine.with_querier(|querier| Ok(querier.bank())).unwrap())
+        .unwrap_or_default();
+    let funds: Vec<(&HumanAddr, &[Coin])> = accounts
+        .iter()
+        .map(|info| (&info.sender, info.sent_funds.as_slice()))
+        .collect();
+    shared.merge(&BankQuerier::new(&funds));
+    shared.merge(&own);
+    unsafe { Config::get() }.set_bank(shared);
+}
+
+// handle_contract_response dispatches the messages of a response in order, with new message info from send fund
+// param, and returns their receipts
+fn handle_contract_response(
+    sender_addr: &str,
+    messages: Vec<CosmosMsg<SpecialMsg>>,
+) -> Vec<MessageReceipt> {
+    chain::dispatch(unsafe { Config::get() }, sender_addr, messages)
+}
+
+// instance_address generates the address of a new instance of a code, the file stem with the first free index
+fn instance_address(wasm_file: &str) -> String {
+    let stem = Path::new(wasm_file)
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("contract");
+    let mut index = 1;
+    let contracts = contract_addresses();
+    let Config { aliases, .. } = unsafe { Config::get() };
+    while contracts.contains(&format!("{}_{}", stem, index))
+        || aliases.contains_key(&format!("{}_{}", stem, index))
+    {
+        index += 1;
     }
+    strict_address(&format!("{}_{}", stem, index))
+}
+
+// instantiate_contract creates another instance of a loaded code, at the label when it is free, and runs its init like
+// any call, a failed init leaves no contract behind. Returns the address and the init result
+fn instantiate_contract(
+    code_id: usize,
+    label: Option<&str>,
+    msg: &str,
+    info: &MessageInfo,
+) -> Result<(String, String), String> {
+    chain::instantiate(
+        unsafe { Config::get() },
+        code_id as u64,
+        label,
+        |_, contract_addr| call_contract(get_engine(contract_addr).unwrap(), "init", msg, info),
+    )
+}
 
-    attributes
+// instantiate_engine creates a new contract from a loaded code for a message, the funds are sent before init. Returns
+// the address and the init result
+fn instantiate_engine(
+    code_id: u64,
+    sender_addr: &str,
+    msg: &[u8],
+    send: &[Coin],
+) -> (String, String) {
+    let info = MessageInfo {
+        sender: HumanAddr::from(sender_addr),
+        sent_funds: send.to_vec(),
+    };
+    let instantiated = chain::instantiate(
+        unsafe { Config::get() },
+        code_id,
+        None,
+        |config, contract_addr| match chain::transfer(
+            config,
+            sender_addr,
+            &HumanAddr::from(contract_addr),
+            send,
+        ) {
+            Err(e) => error_json(&e),
+            Ok(_) => get_engine(contract_addr).unwrap().init_raw(msg, &info),
+        },
+    );
+    match instantiated {
+        Ok(instantiated) => instantiated,
+        Err(e) => (code_id.to_string(), e),
+    }
 }
 
 fn insert_engine(
@@ -710,24 +2662,130 @@ fn insert_engine(
     contract_addr: &str,
     wasm_handler: WasmHandler,
     storage: &MockStorage,
-) {
+) -> bool {
     match ContractInstance::new_instance(
         wasm_file,
         contract_addr,
         wasm_handler,
+        query_special,
         storage,
         handle_contract_response,
     ) {
         Err(e) => {
             println!("error occurred during install contract: {}", e.red());
+            false
         }
         Ok(engine) => {
             unsafe {
                 let Config { engines, .. } = Config::get();
-                engines.insert(contract_addr.to_owned(), engine)
+                engines.insert(contract_addr.to_owned(), Box::new(engine));
             };
+            share_bank(contract_addr);
+            true
         }
-    };
+    }
+}
+
+// notify_reload tells the user that the next call of the contract runs the new build
+fn notify_reload(wasm_file: &str, contract_addr: &str, state_preserved: bool) {
+    let checksum = fs::read(wasm_file)
+        .map(|code| hex::encode(Sha256::digest(&code)))
+        .unwrap_or_default();
+    println!();
+    println!(
+        "{}",
+        "===========================contract reloaded==========================="
+            .yellow()
+            .bold()
+    );
+    println!(
+        "contract [{}] is rebuilt, checksum {}, {}",
+        contract_addr.blue().bold(),
+        checksum.yellow(),
+        match state_preserved {
+            true => "state preserved".green().bold(),
+            false => "state reset".red().bold(),
+        }
+    );
+    RELOADED.store(true, Ordering::SeqCst);
+}
+
+// reset_engine loads the new build with empty storage and runs the last init message of the contract again
+fn reset_engine(wasm_file: &str, contract_addr: &str) {
+    if !insert_engine(
+        wasm_file,
+        contract_addr,
+        query_wasm,
+        &MockStorage::default(),
+    ) {
+        return;
+    }
+    notify_reload(wasm_file, contract_addr, false);
+    init_again(contract_addr);
+}
+
+// migrate_after_reload runs the --migrate-on-reload message on the new build so the copied storage moves to its
+// layout, as the sender of the last init like an admin. Files are read again on every reload
+fn migrate_after_reload(contract_addr: &str) {
+    let Config {
+        migrate_on_reload,
+        init_msgs,
+        current_sender,
+        ..
+    } = unsafe { Config::get() };
+    let input = match migrate_on_reload
+        .get(contract_addr)
+        .or_else(|| migrate_on_reload.get(""))
+    {
+        Some(input) => input.to_owned(),
+        None => return,
+    };
+    let info = match init_msgs.get(contract_addr) {
+        Some((_, info)) => Some(MessageInfo {
+            sender: info.sender.to_owned(),
+            sent_funds: vec![],
+        }),
+        None => sender_info(current_sender),
+    };
+    let result = match (
+        read_message(&input, Path::new("")),
+        info,
+        get_engine(contract_addr),
+    ) {
+        (Err(e), _, _) => Err(e),
+        (_, None, _) => Err(format!("No account found: {}", current_sender)),
+        (_, _, None) => Err(format!("No such contract: {}", contract_addr)),
+        (Ok(msg), Some(info), Some(engine)) => {
+            println!("running migrate with {}", msg.yellow());
+            Ok(call_contract(engine, "migrate", &msg, &info))
+        }
+    };
+    match result {
+        Ok(result) if !is_error_result(&result) => {}
+        Ok(_) => println!(
+            "{}",
+            "migrate of the reloaded build failed, the storage is kept as it was copied".yellow()
+        ),
+        Err(e) => println!("can not migrate reloaded contract: {}", e.red()),
+    }
+}
+
+// init_again runs the last successful init message of the contract with the same sender and funds
+fn init_again(contract_addr: &str) {
+    let init = unsafe { Config::get() }
+        .init_msgs
+        .get(contract_addr)
+        .cloned();
+    match (init, get_engine(contract_addr)) {
+        (Some((msg, info)), Some(engine)) => {
+            println!("running init again with {}", msg.yellow());
+            call_contract(engine, "init", &msg, &info);
+        }
+        _ => println!(
+            "contract [{}] was not initialized yet, it starts uninitialized",
+            contract_addr.blue().bold()
+        ),
+    }
 }
 
 fn watch_and_update(
@@ -737,8 +2795,84 @@ fn watch_and_update(
     // do not copy, use reference when loop
     let len = wasm_files.len();
     let mut modified_files: Vec<time::SystemTime> = vec![time::SystemTime::now(); len];
+    let mut first_load = true;
+    let started = time::Instant::now();
+
+    // compile all contracts in parallel the first time, then install them in order
+    let loading: Vec<_> = wasm_files
+        .iter()
+        .map(|(wasm_file, contract_addr)| {
+            let (wasm_file, contract_addr) = (wasm_file.to_owned(), contract_addr.to_owned());
+            thread::spawn(move || {
+                // companion contracts are compiled on first use
+                if unsafe { Config::get() }
+                    .pending
+                    .contains_key(&contract_addr)
+                {
+                    return None;
+                }
+                let loading = time::Instant::now();
+                let engine = ContractInstance::new_instance(
+                    &wasm_file,
+                    &contract_addr,
+                    query_wasm,
+                    query_special,
+                    &MockStorage::default(),
+                    handle_contract_response,
+                );
+                Some((engine, loading.elapsed()))
+            })
+        })
+        .collect();
+
     unsafe {
-        let Config { engines, .. } = Config::get();
+        let Config {
+            engines, pending, ..
+        } = Config::get();
+        for (index, handle) in loading.into_iter().enumerate() {
+            let (wasm_file, contract_addr) = &wasm_files[index];
+            let _engines = lock_engines();
+            if let Ok(modified_time) = fs::metadata(wasm_file)?.modified() {
+                modified_files[index] = modified_time;
+            }
+            let loaded = match handle.join() {
+                Ok(None) => {
+                    println!(
+                        "[{}/{}] {} [{}]",
+                        index + 1,
+                        len,
+                        "deferred".yellow().bold(),
+                        contract_addr.blue().bold()
+                    );
+                    continue;
+                }
+                Ok(Some((Ok(engine), elapsed))) => {
+                    engines.insert(contract_addr.to_owned(), Box::new(engine));
+                    share_bank(contract_addr);
+                    Some(elapsed)
+                }
+                Ok(Some((Err(e), _))) => {
+                    println!("error occurred during install contract: {}", e.red());
+                    None
+                }
+                Err(_) => None,
+            };
+            println!(
+                "[{}/{}] {} [{}]{}",
+                index + 1,
+                len,
+                match loaded {
+                    Some(_) => "loaded".green().bold(),
+                    None => "failed".red().bold(),
+                },
+                contract_addr.blue().bold(),
+                match loaded {
+                    Some(elapsed) => format!(" in {} ms", elapsed.as_millis().to_string().yellow()),
+                    None => String::new(),
+                }
+            );
+        }
+
         loop {
             for index in 0..len {
                 let (wasm_file, contract_addr) = &wasm_files[index];
@@ -750,18 +2884,28 @@ fn watch_and_update(
                     modified_files[index] = modified_time;
                 }
 
+                // sleep 100 miliseconds incase it notifies modification before build version is completed
+                thread::sleep(time::Duration::from_millis(100));
+                // reloads and the migrations after them wait for the call in progress
+                let _engines = lock_engines();
                 match engines.get_mut(contract_addr) {
                     Some(eng) => {
-                        // sleep 100 miliseconds incase it notifies modification before build version is completed
-                        thread::sleep(time::Duration::from_millis(100));
-                        // callback query directly from storage to copy it
-                        eng.instance
-                            .with_storage(|storage| {
-                                insert_engine(wasm_file, contract_addr, query_wasm, storage);
-                                Ok(())
-                            })
-                            .unwrap();
+                        if Config::get().reset_on_reload {
+                            reset_engine(wasm_file, contract_addr);
+                            continue;
+                        }
+                        // copy the storage first, inserting the new build drops the old engine
+                        let storage = eng.with_storage(|storage| Ok(storage.clone())).unwrap();
+                        let reloaded =
+                            insert_engine(wasm_file, contract_addr, query_wasm, &storage);
+                        if reloaded {
+                            notify_reload(wasm_file, contract_addr, true);
+                            migrate_after_reload(contract_addr);
+                        }
                     }
+                    // not compiled yet, it will pick up the new file on first use
+                    None if pending.contains_key(contract_addr) => {}
+                    None if Config::get().removed.contains(contract_addr) => {}
                     None => {
                         insert_engine(
                             wasm_file,
@@ -774,7 +2918,13 @@ fn watch_and_update(
             }
 
             // init all contracts first time, send the first contract to notify
-            if len > 0 {
+            if first_load && len > 0 {
+                first_load = false;
+                println!(
+                    "{} contracts loaded in {} ms",
+                    len.to_string().green().bold(),
+                    started.elapsed().as_millis().to_string().yellow()
+                );
                 sender.send(wasm_files[0].1.to_owned()).unwrap();
             }
 
@@ -790,29 +2940,265 @@ pub struct CointBalance {
     pub amount: Uint128,
 }
 
-fn prepare_command_line() -> bool {
-    let matches = App::new("cosmwasm-simulate")
-        .version("0.1.0")
-        .author("github : https://github.com/oraichain/cosmwasm-simulate.git")
-        .about("A simulation of cosmwasm smart contract system")
-        .arg(
-            Arg::with_name("run")
-                .help("contract file that built by https://github.com/oraichain/smart-studio.git")
-                .empty_values(false),
+// arguments shared by every subcommand that loads contracts
+fn contract_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name("wasm")
+            .help("contract files that built by https://github.com/oraichain/smart-studio.git")
+            .required_unless("deploy")
+            .multiple(true)
+            .empty_values(false),
+        Arg::from_usage("-c, --contract=[CONTRACT_FOLDER] 'Other contract folder'"),
+        Arg::from_usage("-b, --balance=[COIN_BALANCE] 'Other coin balance, multiple'")
+            .multiple(true),
+        Arg::from_usage("--sender=[SENDER_ADDR] 'Default sender address'"),
+        Arg::from_usage("--account=[NAME=AMOUNT] 'Named account with a balance of the chain denom, multiple'")
+            .multiple(true)
+            .number_of_values(1),
+        Arg::from_usage("--address=[CONTRACT_ADDR] 'Address of the contract instead of its file stem'"),
+        Arg::from_usage("--alias=[ALIAS=ADDRESS] 'Name a contract address, multiple'")
+            .multiple(true)
+            .number_of_values(1),
+        Arg::from_usage(
+            "--deploy=[MANIFEST] 'Yaml manifest of contracts, init messages and balances to deploy on startup'",
+        ),
+        Arg::from_usage("--hooks=[SCRIPT] 'Rhai script with before_call and after_call functions run around each call'"),
+        Arg::from_usage(
+            "--journal=[FILE] 'Append every executed call as a json line to this file, for replay'",
+        ),
+        Arg::from_usage("--checkpoints 'Keep the state of every block for queries at past heights'"),
+        Arg::from_usage(
+            "--block-time=[DURATION] 'Time between blocks like 5s or 500ms, block time moves with the height'",
+        ),
+        Arg::from_usage("--chain-id=[CHAIN_ID] 'Chain id of the simulated chain, Oraichain by default'"),
+        Arg::from_usage("--height=[HEIGHT] 'Block height to start at, 12345 by default'"),
+        Arg::from_usage("--time=[UNIX_SECONDS] 'Block time to start at'"),
+        Arg::from_usage(
+            "--query-depth=[DEPTH] 'Maximum depth of nested contract queries, 10 by default'",
+        ),
+        Arg::from_usage(
+            "--reset-on-reload 'Start reloaded contracts from empty storage and run their last init message again'",
+        ),
+        Arg::from_usage(
+            "--migrate-on-reload=[MSG] 'Migrate message or @file run on a reloaded build with the copied storage, address=MSG for one contract, multiple'",
+        )
+        .multiple(true)
+        .number_of_values(1),
+        Arg::from_usage("--explain 'Annotate each step of a call'"),
+        Arg::from_usage(
+            "--output=[FORMAT] 'json writes every call result, error and storage change as a json line to stdout, the rest goes to stderr'",
+        )
+        .possible_values(&["text", "json"]),
+        Arg::from_usage(
+            "--compare=[OTHER_WASM] 'Repeat every call of the primary contract on another build and report divergences'",
+        ),
+        Arg::from_usage("--storage-report 'List storage keys read and written after each call'"),
+        Arg::from_usage(
+            "--storage-diff 'Print the storage changed by each call with the values before and after it'",
+        ),
+        Arg::from_usage(
+            "--no-rollback 'Keep the storage written by failed calls before their error, for debugging'",
+        ),
+        Arg::from_usage(
+            "--count-instructions 'Print the wasm instructions executed by each call, independent of host gas costs'",
+        ),
+        Arg::from_usage(
+            "--profile 'Print the wasm instructions of every function after each call, the call is run again to count them'",
+        ),
+        Arg::from_usage(
+            "--native-debug=[LIBRARY] 'Run the contract from its native cdylib build instead of its wasm, for debuggers, multiple'",
         )
-        .arg(Arg::from_usage(
-            "-c, --contract=[CONTRACT_FOLDER] 'Other contract folder'",
-        ))
-        .arg(
-            Arg::from_usage("-b, --balance=[COIN_BALANCE] 'Other coin balance, multiple'")
-                .multiple(true),
+        .multiple(true)
+        .number_of_values(1),
+        Arg::from_usage(
+            "--staking=[FILE] 'Yaml or json file of the bonded denom, validators and delegations answered to staking queries'",
+        ),
+        Arg::from_usage("--validator=[ADDRESS] 'Validator of the staking queries, multiple'")
+            .multiple(true)
+            .number_of_values(1),
+        Arg::from_usage(
+            "--delegation=[DELEGATOR:VALIDATOR:AMOUNT] 'Delegation of the staking queries with optional :REWARDS, multiple'",
         )
-        .get_matches();
+        .multiple(true)
+        .number_of_values(1),
+        Arg::from_usage(
+            "--ibc-auto-ack 'Acknowledge ICS20 transfer packets as soon as they are sent'",
+        ),
+        Arg::from_usage(
+            "--strict-address 'Validate addresses as bech32 with the chain prefix instead of accepting any string'",
+        ),
+        Arg::from_usage(
+            "--seed=[SEED] 'Seed of derived addresses and generated messages, runs with the same seed are reproducible'",
+        ),
+        Arg::from_usage(
+            "--iavl 'Keep an iavl tree of every contract storage for root hashes and proofs'",
+        ),
+        Arg::from_usage(
+            "--gas-limit=[GAS] 'Gas limit of init and handle calls with the calls of their messages'",
+        ),
+        Arg::from_usage(
+            "--query-gas-limit=[GAS] 'Gas limit of queries, usually smaller like on rpc nodes'",
+        ),
+        Arg::from_usage(
+            "--state-dir=[DIR] 'Save the storage of every contract to this folder after each call and restore it on startup'",
+        ),
+    ]
+}
 
-    let default_addr;
+// parse_duration reads durations like 5s, 500ms or 1m as nanoseconds, plain numbers are seconds
+fn parse_duration(text: &str) -> Option<u64> {
+    let split = text
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(text.len());
+    let (value, unit) = text.split_at(split);
+    let value: u64 = value.parse().ok()?;
+    let nanos = match unit {
+        "ns" => 1,
+        "us" => 1_000,
+        "ms" => 1_000_000,
+        "" | "s" => 1_000_000_000,
+        "m" => 60_000_000_000,
+        "h" => 3_600_000_000_000,
+        _ => return None,
+    };
+    value.checked_mul(nanos)
+}
 
+// prepare_accounts registers accounts from balance args and returns the default sender
+fn prepare_accounts(matches: &ArgMatches, manifest: Option<&deploy::Manifest>) -> String {
     unsafe {
-        let Config { accounts, .. } = Config::get();
+        let Config { accounts, ibc, .. } = Config::get();
+
+        ibc.auto_ack = matches.is_present("ibc-auto-ack");
+        contract_vm::mock::STRICT_ADDRESS = matches.is_present("strict-address");
+        if let Some(seed) = matches.value_of("seed") {
+            match seed.parse() {
+                Ok(seed) => contract_vm::mock::SEED = Some(seed),
+                Err(_) => println!("wrong seed {}, must be a number", seed.red()),
+            }
+        }
+        contract_vm::mock::IAVL_STORAGE = matches.is_present("iavl");
+        contract_vm::engine::EXPLAIN = matches.is_present("explain");
+        if matches.value_of("output") == Some("json") {
+            if let Err(e) = stream::json_output() {
+                println!("can not write json output: {}", e.red());
+            }
+        }
+        if let Some(values) = matches.values_of("alias") {
+            let Config { aliases, .. } = Config::get();
+            for value in values {
+                match value.split_once('=') {
+                    Some((alias, address)) => {
+                        aliases.insert(alias.to_string(), address.to_string());
+                    }
+                    None => println!("wrong alias {}, must be alias=address", value.red()),
+                }
+            }
+        }
+        contract_vm::engine::STORAGE_REPORT = matches.is_present("storage-report");
+        contract_vm::engine::STORAGE_DIFF = matches.is_present("storage-diff");
+        contract_vm::engine::ROLLBACK = !matches.is_present("no-rollback");
+        Config::get().reset_on_reload = matches.is_present("reset-on-reload");
+        // token={"migrate":{}} is for one contract, a message without address for every contract
+        for value in matches.values_of("migrate-on-reload").into_iter().flatten() {
+            let (contract_addr, msg) = match value.split_once('=') {
+                Some((address, msg)) if !value.starts_with(|c| c == '{' || c == '@') => {
+                    (resolve_address(address), msg)
+                }
+                _ => (String::new(), value),
+            };
+            Config::get()
+                .migrate_on_reload
+                .insert(contract_addr, msg.to_string());
+        }
+        contract_vm::engine::COUNT_INSTRUCTIONS = matches.is_present("count-instructions");
+        contract_vm::engine::PROFILE = matches.is_present("profile");
+        if let Some(libraries) = matches.values_of("native-debug") {
+            *contract_vm::engine::NATIVE_DEBUG.lock().unwrap() =
+                libraries.map(|l| l.to_string()).collect();
+        }
+        if let Some(block_time) = matches.value_of("block-time") {
+            match parse_duration(block_time) {
+                Some(nanos) => contract_vm::engine::BLOCK_TIME = nanos,
+                None => println!(
+                    "wrong block time {}, must be like 5s or 500ms",
+                    block_time.red()
+                ),
+            }
+        }
+        if let Some(chain_id) = matches.value_of("chain-id") {
+            contract_vm::engine::CHAIN_ID = Box::leak(chain_id.to_string().into_boxed_str());
+        }
+        let height = matches
+            .value_of("height")
+            .and_then(|height| match height.parse() {
+                Ok(height) => Some(height),
+                Err(_) => {
+                    println!("wrong height {}", height.red());
+                    None
+                }
+            });
+        let time = matches
+            .value_of("time")
+            .and_then(|time| match time.parse::<u64>() {
+                Ok(secs) => Some(secs * 1_000_000_000),
+                Err(_) => {
+                    println!("wrong time {}, must be unix seconds", time.red());
+                    None
+                }
+            });
+        contract_vm::engine::genesis(height, time);
+        if let Some(depth) = matches.value_of("query-depth") {
+            match depth.parse() {
+                Ok(depth) => contract_vm::engine::MAX_QUERY_DEPTH = depth,
+                Err(_) => println!("wrong query depth {}", depth.red()),
+            }
+        }
+        let gas_limit = |arg: &str| {
+            let gas = matches.value_of(arg)?;
+            match gas.parse::<u64>() {
+                Ok(gas) => Some(gas),
+                Err(_) => {
+                    println!("wrong {} {}", arg, gas.red());
+                    None
+                }
+            }
+        };
+        contract_vm::engine::GAS_LIMIT = gas_limit("gas-limit");
+        contract_vm::engine::QUERY_GAS_LIMIT = gas_limit("query-gas-limit");
+        if matches.is_present("checkpoints") {
+            Config::get().checkpoints = Some(HashMap::new());
+        }
+        if let Some(dir) = matches.value_of("state-dir") {
+            match fs::create_dir_all(dir) {
+                Ok(_) => Config::get().state_dir = Some(dir.to_string()),
+                Err(e) => println!("can not create state dir {}: {}", dir, e.to_string().red()),
+            }
+        }
+        *journal::JOURNAL_FILE.lock().unwrap() = matches.value_of("journal").map(String::from);
+        if let Some(file) = matches.value_of("hooks") {
+            if let Err(e) = hooks::load(file) {
+                println!("can not load hook script: {}", e.red());
+            }
+        }
+
+        // validators and delegations of the staking queries
+        let mut staking = match matches.value_of("staking") {
+            Some(file) => contract_vm::mock::StakingConfig::load(file).unwrap_or_else(|e| {
+                println!("can not load staking file {}: {}", file, e.red());
+                Default::default()
+            }),
+            None => Default::default(),
+        };
+        for validator in matches.values_of("validator").into_iter().flatten() {
+            staking.add_validator(validator);
+        }
+        for delegation in matches.values_of("delegation").into_iter().flatten() {
+            if let Err(e) = staking.add_delegation(delegation) {
+                println!("{}", e.red());
+            }
+        }
+        *contract_vm::mock::STAKING.lock().unwrap() = Some(staking.querier(DENOM));
 
         // add more balances
         if let Some(coin_balances) = matches.values_of("balance") {
@@ -833,10 +3219,44 @@ fn prepare_command_line() -> bool {
             }
         }
 
-        // default account
-        if accounts.is_empty() {
+        // named accounts, alice=1000 or alice with the default balance
+        for account in matches.values_of("account").into_iter().flatten() {
+            let (name, amount) = match account.split_once('=') {
+                Some((name, amount)) => (name, amount.parse::<u128>().ok()),
+                None => (account, Some(DEFAULT_SENDER_BALANCE as u128)),
+            };
+            let added = match amount {
+                Some(amount) => add_account(name, Uint128::from(amount)),
+                None => Err(format!("wrong account {}, must be NAME=AMOUNT", account)),
+            };
+            if let Err(e) = added {
+                println!("{}", e.red());
+            }
+        }
+
+        // balances of the deployment manifest
+        for coin_balance in manifest.map_or(&vec![], |m| &m.balances) {
+            accounts.push(MessageInfo {
+                sender: coin_balance.address.to_owned(),
+                sent_funds: vec![Coin {
+                    denom: DENOM.to_string(),
+                    amount: coin_balance.amount,
+                }],
+            });
+        }
+
+        // default account, can be overridden by the sender arg
+        let sender = matches.value_of("sender");
+        if accounts.is_empty() || sender.map_or(false, |s| sender_info(s).is_none()) {
             accounts.push(MessageInfo {
-                sender: HumanAddr::from(format!("{}{}", DENOM, DEFAULT_SENDER_ADDR)),
+                // with --strict-address the default names become aliases of bech32 addresses
+                sender: match sender {
+                    Some(s) => HumanAddr::from(strict_address(s)),
+                    None => HumanAddr::from(strict_address(&format!(
+                        "{}{}",
+                        DENOM, DEFAULT_SENDER_ADDR
+                    ))),
+                },
                 // there is default account with balance
                 sent_funds: vec![Coin {
                     denom: DENOM.to_string(),
@@ -848,53 +3268,742 @@ fn prepare_command_line() -> bool {
         // Sort by sender address
         accounts.sort_by(|a, b| a.sender.cmp(&b.sender));
         // set default addr
-        default_addr = accounts[0].sender.as_str();
+        match sender {
+            Some(s) => s.to_string(),
+            None => accounts[0].sender.to_string(),
+        }
     }
+}
 
-    if let Some(file) = matches.value_of("run") {
-        // start load, check other file as well
-        let wasm_files = match load_artifacts(file, matches.value_of("contract")) {
-            Err(_) => vec![],
-            Ok(s) => s,
-        };
+// expand_wasm_path resolves a glob pattern or scans a directory recursively for wasm files
+fn expand_wasm_path(path: &str) -> Vec<String> {
+    let pattern = match Path::new(path).is_dir() {
+        true => Path::new(path).join("**").join("*.wasm"),
+        false => Path::new(path).to_path_buf(),
+    };
+    let files: Vec<String> = match glob::glob(pattern.to_str().unwrap_or(path)) {
+        Ok(paths) => sorted(
+            paths
+                .filter_map(|p| p.ok())
+                .filter_map(|p| p.to_str().map(|s| s.to_string())),
+        )
+        .collect(),
+        Err(e) => {
+            println!(
+                "wrong pattern {}: {}",
+                path.red().bold(),
+                e.to_string().red()
+            );
+            return vec![];
+        }
+    };
+    // keep the path as it is so loading reports the missing file
+    match files.is_empty() {
+        true => vec![path.to_string()],
+        false => files,
+    }
+}
 
-        let (sender, receiver) = sync::mpsc::channel();
-        // Spawn off an expensive computation
-        thread::spawn(move || {
-            if let Ok(ret) = watch_and_update(&sender, &wasm_files) {
-                return ret;
+// load_contracts starts watching the wasm files and returns the first contract once all are loaded
+fn load_contracts(matches: &ArgMatches, manifest: Option<&deploy::Manifest>) -> Option<String> {
+    let expanded: Vec<String> = matches
+        .values_of("wasm")
+        .map_or(vec![], |values| values.flat_map(expand_wasm_path).collect());
+    let mut files = expanded.iter().map(|f| f.as_str());
+    // start load, check other file as well
+    let mut wasm_files = match files
+        .next()
+        .map(|file| load_artifacts(file, matches.value_of("contract")))
+    {
+        Some(Ok(s)) => s,
+        _ => vec![],
+    };
+    // contracts from the contract folder are only compiled when they are first used
+    let companions = wasm_files.len().saturating_sub(1);
+    // other wasm files given on the command line are registered by their file stem
+    for file in files {
+        if let Ok(artifacts) = load_artifacts(file, None) {
+            for (wasm_file, contract_addr) in artifacts {
+                if wasm_files.iter().any(|(_, addr)| addr.eq(&contract_addr)) {
+                    println!("contract {} already loaded", contract_addr.red().bold());
+                    continue;
+                }
+                wasm_files.push((wasm_file, contract_addr));
             }
-            return true;
-        });
+        }
+    }
+    // contracts of the deployment manifest, named contracts get an alias
+    for contract in manifest.map_or(&vec![], |m| &m.contracts) {
+        let contract_addr = contract.address();
+        if wasm_files.iter().any(|(_, addr)| addr.eq(&contract_addr)) {
+            println!("contract {} already loaded", contract_addr.red().bold());
+            continue;
+        }
+        if !Path::new(&contract.wasm).is_file() {
+            println!("wasm file {} not found", contract.wasm.red().bold());
+            return None;
+        }
+        let Config { aliases, .. } = unsafe { Config::get() };
+        if contract_addr.ne(&contract.name) {
+            aliases.insert(contract.name.to_owned(), contract_addr.to_owned());
+        }
+        for alias in contract.aliases.iter() {
+            aliases.insert(alias.to_owned(), contract_addr.to_owned());
+        }
+        wasm_files.push((contract.wasm.to_owned(), contract_addr));
+    }
+    // primary contract address defaults to the file stem
+    if let (Some(address), Some(primary)) = (matches.value_of("address"), wasm_files.first_mut()) {
+        primary.1 = address.to_string();
+    }
+    if wasm_files.is_empty() {
+        return None;
+    }
+    // file stems are not valid bech32 addresses, contracts are loaded at derived ones
+    for (_, contract_addr) in wasm_files.iter_mut() {
+        *contract_addr = strict_address(contract_addr);
+    }
 
-        // simulate until break, start with first contract
-        match receiver.recv() {
-            Ok(contract_addr) => {
-                unsafe {
-                    let Config {
-                        accounts,
-                        engines,
-                        editor,
-                    } = Config::get();
-                    // init the first suggested items
-                    for k in accounts.iter() {
-                        editor.add_input_history_entry(k.sender.to_string());
-                    }
-                    for k in engines.keys() {
-                        editor.add_input_history_entry(k.to_owned());
+    unsafe {
+        let Config { codes, pending, .. } = Config::get();
+        for (wasm_file, contract_addr) in wasm_files.iter().skip(1).take(companions) {
+            pending.insert(contract_addr.to_owned(), wasm_file.to_owned());
+        }
+        for (wasm_file, _) in wasm_files.iter() {
+            codes.push(wasm_file.to_owned());
+            println!(
+                "Code id {} => [{}]",
+                codes.len().to_string().green().bold(),
+                wasm_file.blue().bold()
+            );
+        }
+    }
+
+    let (sender, receiver) = sync::mpsc::channel();
+    // Spawn off an expensive computation
+    thread::spawn(move || {
+        if let Ok(ret) = watch_and_update(&sender, &wasm_files) {
+            return ret;
+        }
+        return true;
+    });
+
+    match receiver.recv() {
+        Ok(contract_addr) => {
+            if let Some(file) = matches.value_of("compare") {
+                match ContractInstance::new_instance(
+                    file,
+                    &contract_addr,
+                    query_wasm,
+                    query_special,
+                    &MockStorage::default(),
+                    ignore_messages,
+                ) {
+                    Ok(engine) => unsafe { Config::get() }.compare = Some(Box::new(engine)),
+                    Err(e) => println!("can not load compared build: {}", e.red()),
+                }
+            }
+            Some(contract_addr)
+        }
+        Err(e) => {
+            println!("watch error: {}", e.to_string().red());
+            None
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct ScenarioStep {
+    // name of the step in the gas baseline
+    name: Option<String>,
+    #[serde(rename = "type")]
+    call_type: String,
+    contract: Option<String>,
+    sender: Option<String>,
+    msg: serde_json::Value,
+    // coins attached to the step, debited from the sender
+    funds: Option<Vec<Coin>>,
+    // code id or wasm file of migrate steps
+    code: Option<String>,
+    #[serde(default)]
+    expect_error: bool,
+}
+
+// read_message loads the json message of @path/to/msg.json, any other input is the message itself
+fn read_message(input: &str, dir: &Path) -> Result<String, String> {
+    let file = match input.trim().strip_prefix('@') {
+        Some(file) => dir.join(file),
+        None => return Ok(input.to_string()),
+    };
+    let data = load_data_from_file(&file.to_string_lossy())?;
+    let msg: Value = serde_json::from_slice(&data)
+        .map_err(|e| format!("{} is not a json message: {}", file.display(), e))?;
+    Ok(msg.to_string())
+}
+
+// run_scenario executes every step of a json scenario file, returns false if any step does not behave as expected
+fn run_scenario(
+    file: &str,
+    contract_addr: &str,
+    sender_addr: &str,
+    gas_used: &mut BTreeMap<String, u64>,
+    cases: &mut Vec<report::TestCase>,
+    stop_on_failure: bool,
+) -> bool {
+    // scenarios are json, or yaml when the file ends with .yaml or .yml
+    let steps: Result<Vec<ScenarioStep>, String> = load_data_from_file(file).and_then(|data| {
+        match file.ends_with(".yaml") || file.ends_with(".yml") {
+            true => serde_yaml::from_slice(data.as_slice()).map_err(|e| e.to_string()),
+            false => serde_json::from_slice(data.as_slice()).map_err(|e| e.to_string()),
+        }
+    });
+    let steps = match steps {
+        Ok(steps) => steps,
+        Err(e) => {
+            println!("invalid scenario file: {}", e.red());
+            return false;
+        }
+    };
+
+    let scenario_dir = Path::new(file).parent().unwrap_or_else(|| Path::new(""));
+    let mut failed = 0;
+    for (index, step) in steps.iter().enumerate() {
+        let contract = step.contract.as_deref().unwrap_or(contract_addr);
+        let sender = step.sender.as_deref().unwrap_or(sender_addr);
+        let name = step.name.to_owned().unwrap_or(format!(
+            "{}. {} {}",
+            index + 1,
+            step.call_type,
+            contract
+        ));
+        let start = time::Instant::now();
+        let result = match sender_info(sender) {
+            None => error_json(&format!("No account found: {}", sender)),
+            Some(mut info) => match get_engine(contract) {
+                None => error_json(&format!("No such contract: {}", contract)),
+                Some(engine) => {
+                    // "@msg.json" messages are read relative to the scenario file
+                    let msg = match step.msg.as_str() {
+                        Some(msg) if msg.starts_with('@') => read_message(msg, scenario_dir),
+                        _ => Ok(step.msg.to_string()),
+                    };
+                    if let Some(funds) = step.funds.as_ref() {
+                        info.sent_funds = funds.to_owned();
                     }
+                    let result = match msg {
+                        Ok(msg) if step.call_type.eq("migrate") => {
+                            migrate_contract(contract, step.code.as_deref(), &msg, &info)
+                        }
+                        Ok(msg) => call_contract(engine, &step.call_type, &msg, &info),
+                        Err(e) => error_json(&e),
+                    };
+                    // migrate may have replaced the engine
+                    let engine_gas = get_engine(contract).map_or(0, |engine| engine.last_gas_used);
+                    gas_used.insert(name.to_owned(), engine_gas);
+                    result
                 }
-                return start_simulate_forever(contract_addr.as_str(), default_addr);
+            },
+        };
+
+        let failure = match (is_error_result(&result), step.expect_error) {
+            (true, false) => Some("step failed"),
+            (false, true) => Some("step was expected to fail"),
+            _ => None,
+        };
+        cases.push(report::TestCase {
+            name,
+            contract: contract.to_string(),
+            duration: start.elapsed(),
+            failure: failure.map(|message| (message.to_string(), result.to_owned())),
+        });
+        if failure.is_some() {
+            failed += 1;
+            println!(
+                "step {} [{} {}] {}: {}",
+                (index + 1).to_string().blue().bold(),
+                step.call_type.green().bold(),
+                contract.green().bold(),
+                "failed".red().bold(),
+                result.red()
+            );
+            if stop_on_failure {
+                println!("script stopped at its first failure");
+                return false;
+            }
+        } else if stop_on_failure {
+            println!(
+                "step {} [{} {}] {}",
+                (index + 1).to_string().blue().bold(),
+                step.call_type.green().bold(),
+                contract.green().bold(),
+                result
+            );
+        }
+    }
+
+    println!(
+        "scenario finished: {} passed, {} failed",
+        (steps.len() - failed).to_string().green().bold(),
+        failed.to_string().red().bold()
+    );
+    failed == 0
+}
+
+// check_gas_baseline compares gas per step with the baseline file, the baseline is written when it does not exist
+fn check_gas_baseline(matches: &ArgMatches, gas_used: &BTreeMap<String, u64>) -> bool {
+    let file = match matches.value_of("gas-baseline") {
+        Some(file) => file,
+        None => return true,
+    };
+    if matches.is_present("update-gas-baseline") || !Path::new(file).is_file() {
+        return match fs::write(file, serde_json::to_string_pretty(gas_used).unwrap()) {
+            Ok(_) => {
+                println!("gas baseline is written to {}", file.blue().bold());
+                true
             }
             Err(e) => {
-                println!("watch error: {}", e.to_string().red());
+                println!("can not write gas baseline: {}", e.to_string().red());
+                false
+            }
+        };
+    }
+
+    let baseline: BTreeMap<String, u64> = match load_data_from_file(file)
+        .and_then(|data| serde_json::from_slice(data.as_slice()).map_err(|e| e.to_string()))
+    {
+        Ok(baseline) => baseline,
+        Err(e) => {
+            println!("invalid gas baseline: {}", e.red());
+            return false;
+        }
+    };
+    // checked by the validator of the argument
+    let threshold: f64 = matches.value_of("gas-threshold").unwrap().parse().unwrap();
+
+    let mut regressions = 0;
+    for (name, gas) in gas_used.iter() {
+        let base = match baseline.get(name) {
+            Some(base) => *base,
+            None => {
+                println!("step [{}] is not in the gas baseline", name.yellow());
+                continue;
+            }
+        };
+        let change = (*gas as f64 - base as f64) * 100.0 / (base.max(1) as f64);
+        if change > threshold {
+            regressions += 1;
+            println!(
+                "step [{}] gas {} -> {} ({})",
+                name.blue().bold(),
+                base.to_string().yellow(),
+                gas.to_string().red().bold(),
+                format!("+{:.1}%", change).red().bold()
+            );
+        }
+    }
+
+    println!(
+        "gas baseline: {} regressions over {}%",
+        regressions.to_string().red().bold(),
+        threshold
+    );
+    regressions == 0 || matches.is_present("gas-warn-only")
+}
+
+// exec_once runs a single call against the first contract, storage is restored from and saved to the state file
+fn exec_once(
+    matches: &ArgMatches,
+    contract_addr: &str,
+    sender_addr: &str,
+    output: &mut dyn Write,
+) -> bool {
+    let info = match sender_info(sender_addr) {
+        Some(i) => i,
+        None => {
+            let _ = writeln!(
+                output,
+                "{}",
+                error_json(&format!("No account found: {}", sender_addr))
+            );
+            return false;
+        }
+    };
+    let state_file = matches.value_of("state");
+
+    let engine = match get_engine(contract_addr) {
+        Some(engine) => engine,
+        None => {
+            let _ = writeln!(
+                output,
+                "{}",
+                error_json(&format!("No such contract: {}", contract_addr))
+            );
+            return false;
+        }
+    };
+
+    if let Some(file) = state_file.filter(|f| Path::new(f).is_file()) {
+        let storage = match load_data_from_file(file)
+            .and_then(|data| MockStorage::from_state(data.as_slice()))
+        {
+            Ok(storage) => storage,
+            Err(e) => {
+                let _ = writeln!(
+                    output,
+                    "{}",
+                    error_json(&format!("invalid state file: {}", e))
+                );
                 return false;
             }
+        };
+        engine
+            .with_storage(|s| {
+                *s = storage;
+                Ok(())
+            })
+            .unwrap();
+    }
+
+    let messages = matches
+        .value_of("init")
+        .map(|init_msg| read_message(init_msg, Path::new("")))
+        .transpose()
+        .and_then(|init_msg| {
+            read_message(matches.value_of("msg").unwrap(), Path::new("")).map(|msg| (init_msg, msg))
+        });
+    let (init_msg, msg) = match messages {
+        Ok(messages) => messages,
+        Err(e) => {
+            let _ = writeln!(output, "{}", error_json(&e));
+            return false;
+        }
+    };
+
+    if let Some(init_msg) = init_msg {
+        let result = call_contract(engine, "init", &init_msg, &info);
+        if is_error_result(&result) {
+            let _ = writeln!(output, "{}", result);
+            return false;
         }
     }
-    return false;
+
+    let result = match matches.value_of("type").unwrap() {
+        "migrate" => migrate_contract(contract_addr, matches.value_of("code"), &msg, &info),
+        call_type => call_contract(engine, call_type, &msg, &info),
+    };
+
+    // migrate may have replaced the engine
+    if let (Some(file), Some(engine)) = (state_file, get_engine(contract_addr)) {
+        let state = engine.with_storage(|s| Ok(s.to_state())).unwrap();
+        if let Err(e) = fs::write(file, state) {
+            let _ = writeln!(
+                output,
+                "{}",
+                error_json(&format!("can not write state file: {}", e))
+            );
+            return false;
+        }
+    }
+
+    let _ = writeln!(output, "{}", result);
+    !is_error_result(&result)
+}
+
+fn error_json(error: &str) -> String {
+    serde_json::json!({ "error": error }).to_string()
+}
+
+fn rpc_port_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::from_usage(
+        "--rpc-port=[RPC_PORT] 'Serve tendermint rpc tx_search and websocket events of the calls'",
+    )
+}
+
+fn command_line<'a, 'b>() -> App<'a, 'b> {
+    App::new("cosmwasm-simulate")
+        .version("0.1.0")
+        .author("github : https://github.com/oraichain/cosmwasm-simulate.git")
+        .about("A simulation of cosmwasm smart contract system")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(
+            SubCommand::with_name("run")
+                .about("Simulate contracts interactively")
+                .args(&contract_args())
+                .arg(rpc_port_arg())
+                .arg(
+                    Arg::from_usage("--keybindings=[MODE] 'Line editing keybindings'")
+                        .possible_values(&["vi", "emacs"])
+                        .default_value("emacs"),
+                )
+                .arg(Arg::from_usage(
+                    "--script=[SCRIPT_FILE] 'Run the calls of a json or yaml scenario file without the REPL and stop at the first failure'",
+                )),
+        )
+        .subcommand(
+            SubCommand::with_name("serve")
+                .about("Serve contracts through the REST api only")
+                .args(&contract_args())
+                .arg(Arg::from_usage("-p, --port=[PORT] 'REST server port'"))
+                .arg(rpc_port_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("exec")
+                .about("Execute a single call, print its json result and exit with its status")
+                .args(&contract_args())
+                .arg(
+                    Arg::from_usage("-t, --type=[CALL_TYPE] 'Call type'")
+                        .possible_values(&["init", "handle", "query", "migrate"])
+                        .default_value("handle"),
+                )
+                .arg(Arg::from_usage(
+                    "--code=[CODE] 'Code id or wasm file a migrate call moves the contract to'",
+                ))
+                .arg(Arg::from_usage("-m, --msg=<MSG> 'Json message'").required(true))
+                .arg(Arg::from_usage(
+                    "-i, --init=[INIT_MSG] 'Json init message to run before the call'",
+                ))
+                .arg(Arg::from_usage(
+                    "-s, --state=[STATE_FILE] 'Storage file restored before and saved after the call'",
+                )),
+        )
+        .subcommand(
+            SubCommand::with_name("test")
+                .about("Run a json scenario file and exit with its status")
+                .args(&contract_args())
+                .arg(
+                    Arg::from_usage("-s, --scenario=<SCENARIO_FILE> 'Scenario file'")
+                        .required(true),
+                )
+                .arg(Arg::from_usage(
+                    "--gas-baseline=[BASELINE_FILE] 'Compare gas per step with this file, it is written when missing'",
+                ))
+                .arg(
+                    Arg::from_usage("--gas-threshold=[PERCENT] 'Allowed gas increase per step'")
+                        .default_value("10")
+                        .validator(|percent| match percent.parse::<f64>() {
+                            Ok(percent) if percent.is_finite() && percent >= 0.0 => Ok(()),
+                            _ => Err(format!("{} is not a percentage", percent)),
+                        }),
+                )
+                .arg(Arg::from_usage("--gas-warn-only 'Do not fail on gas regressions'"))
+                .arg(Arg::from_usage("--update-gas-baseline 'Overwrite the gas baseline'"))
+                .arg(Arg::from_usage(
+                    "--metrics=[METRICS_FILE] 'Export per call metrics as csv, or json when the file ends with .json'",
+                ))
+                .arg(Arg::from_usage(
+                    "--junit=[JUNIT_FILE] 'Write the steps as a JUnit xml report for ci'",
+                ))
+                .arg(Arg::from_usage(
+                    "--fixtures=[FIXTURES_FILE] 'Export the calls with their responses as CosmJS mock fixtures'",
+                )),
+        )
+        .subcommand(
+            SubCommand::with_name("replay")
+                .about("Run the calls of a journal again and report the results that changed")
+                .args(&contract_args())
+                .arg(Arg::from_usage("-f, --file=<JOURNAL> 'Journal written with --journal'").required(true)),
+        )
+        .subcommand(
+            SubCommand::with_name("fuzz")
+                .about("Call an entry point with generated messages and report panics, vm errors and gas outliers")
+                .args(&contract_args())
+                .arg(
+                    Arg::from_usage("-e, --entry=[ENTRY] 'Entry point'")
+                        .possible_values(&["init", "instantiate", "handle", "execute", "query"])
+                        .default_value("handle"),
+                )
+                .arg(Arg::from_usage("-n, --iterations=[ITERATIONS] 'Number of calls'").default_value("1000"))
+                .arg(Arg::from_usage("--fuzz-seed=[SEED] 'Seed of generated messages, --seed or 0 by default'"))
+                .arg(Arg::from_usage("-i, --init=[INIT_MSG] 'Json init message to run before fuzzing'"))
+                .arg(Arg::from_usage("-r, --report=[REPORT_FILE] 'Report file'").default_value("fuzz-report.json")),
+        )
+        .subcommand(
+            SubCommand::with_name("completions")
+                .about("Print the shell completion script")
+                .arg(
+                    Arg::with_name("shell")
+                        .possible_values(&Shell::variants())
+                        .required(true),
+                ),
+        )
+}
+
+fn prepare_command_line() -> bool {
+    let matches = command_line().get_matches();
+
+    let (name, matches) = match matches.subcommand() {
+        (name, Some(m)) => (name, m),
+        _ => return false,
+    };
+
+    // completions do not load any contract
+    if name.eq("completions") {
+        let shell: Shell = matches.value_of("shell").unwrap().parse().unwrap();
+        command_line().gen_completions_to("cosmwasm-simulate", shell, &mut std::io::stdout());
+        return true;
+    }
+
+    // exec keeps stdout for the result json, everything printed while loading and calling goes to stderr
+    let mut output: Box<dyn Write> = Box::new(std::io::stdout());
+    if name.eq("exec") {
+        let _ = std::io::stdout().flush();
+        match dup(1).and_then(|stdout| dup2(2, 1).map(|_| stdout)) {
+            Ok(stdout) => output = Box::new(unsafe { fs::File::from_raw_fd(stdout) }),
+            Err(e) => eprintln!(
+                "can not keep stdout for the result: {}",
+                e.to_string().red()
+            ),
+        }
+    }
+
+    let manifest = match matches.value_of("deploy").map(deploy::load) {
+        Some(Err(e)) => {
+            println!("invalid deployment manifest: {}", e.red());
+            return false;
+        }
+        Some(Ok(manifest)) => Some(manifest),
+        None => None,
+    };
+    let default_addr = prepare_accounts(matches, manifest.as_ref());
+    // simulate until break, start with first contract
+    let contract_addr = match load_contracts(matches, manifest.as_ref()) {
+        Some(addr) => addr,
+        None => return false,
+    };
+    for loaded in unsafe { Config::get() }
+        .engines
+        .keys()
+        .cloned()
+        .collect::<Vec<String>>()
+    {
+        restore_storage(&loaded);
+    }
+    if let Some(manifest) = manifest.as_ref() {
+        if !deploy::instantiate(manifest, &default_addr) {
+            return false;
+        }
+    }
+
+    if let Some(port) = matches.value_of("rpc-port") {
+        let port = port.to_string();
+        thread::spawn(move || rpc::serve(&port));
+    }
+
+    match name {
+        "run" => {
+            unsafe {
+                let Config {
+                    accounts, editor, ..
+                } = Config::get();
+                editor.set_keybindings(matches.value_of("keybindings").unwrap());
+                // Ctrl-C during a call cancels it instead of quitting
+                let interrupt = SigAction::new(
+                    SigHandler::Handler(cancel_call),
+                    SaFlags::empty(),
+                    SigSet::empty(),
+                );
+                if let Err(e) = sigaction(Signal::SIGINT, &interrupt) {
+                    println!("can not handle Ctrl-C: {}", e.to_string().red());
+                }
+                // init the first suggested items
+                for k in accounts.iter() {
+                    editor.add_input_history_entry(k.sender.to_string());
+                }
+                for k in contract_addresses() {
+                    editor.add_input_history_entry(k);
+                }
+            }
+            // a script runs its calls instead of the REPL
+            if let Some(script) = matches.value_of("script") {
+                return run_scenario(
+                    script,
+                    &contract_addr,
+                    &default_addr,
+                    &mut BTreeMap::new(),
+                    &mut vec![],
+                    true,
+                );
+            }
+            start_simulate_forever(contract_addr.as_str(), &default_addr)
+        }
+        "serve" => rest::serve(
+            matches.value_of("port").unwrap_or(rest::DEFAULT_PORT),
+            &default_addr,
+        ),
+        "exec" => exec_once(matches, &contract_addr, &default_addr, &mut output),
+        "replay" => journal::replay(matches.value_of("file").unwrap()),
+        "fuzz" => {
+            if let Some(init_msg) = matches.value_of("init") {
+                let result = match (
+                    get_engine(&contract_addr),
+                    sender_info(&default_addr),
+                    read_message(init_msg, Path::new("")),
+                ) {
+                    (_, _, Err(e)) => error_json(&e),
+                    (Some(engine), Some(info), Ok(init_msg)) => {
+                        call_contract(engine, "init", &init_msg, &info)
+                    }
+                    _ => error_json("Can not init contract"),
+                };
+                if is_error_result(&result) {
+                    return false;
+                }
+            }
+            fuzz::run(
+                &contract_addr,
+                &default_addr,
+                matches.value_of("entry").unwrap(),
+                matches
+                    .value_of("iterations")
+                    .unwrap()
+                    .parse()
+                    .unwrap_or_default(),
+                matches
+                    .value_of("fuzz-seed")
+                    .and_then(|seed| seed.parse().ok())
+                    .or(unsafe { contract_vm::mock::SEED })
+                    .unwrap_or_default(),
+                matches.value_of("report").unwrap(),
+            )
+        }
+        "test" => {
+            let mut gas_used = BTreeMap::new();
+            let mut cases = vec![];
+            let scenario = matches.value_of("scenario").unwrap();
+            let passed = run_scenario(
+                scenario,
+                &contract_addr,
+                &default_addr,
+                &mut gas_used,
+                &mut cases,
+                false,
+            );
+            if let Some(file) = matches.value_of("junit") {
+                match report::write_junit(file, scenario, &cases) {
+                    Ok(_) => println!("junit report is written to {}", file.blue().bold()),
+                    Err(e) => println!("can not write junit report: {}", e.red()),
+                }
+            }
+            if let Some(file) = matches.value_of("metrics") {
+                match report::write_metrics(file, &unsafe { Config::get() }.session) {
+                    Ok(_) => println!("metrics are written to {}", file.blue().bold()),
+                    Err(e) => println!("can not write metrics: {}", e.red()),
+                }
+            }
+            if let Some(file) = matches.value_of("fixtures") {
+                match report::write_fixtures(file, &unsafe { Config::get() }.session) {
+                    Ok(_) => println!("fixtures are written to {}", file.blue().bold()),
+                    Err(e) => println!("can not write fixtures: {}", e.red()),
+                }
+            }
+            check_gas_baseline(matches, &gas_used) && passed
+        }
+        _ => false,
+    }
 }
 
 fn main() {
-    prepare_command_line();
+    if !prepare_command_line() {
+        std::process::exit(1);
+    }
 }
@@ -0,0 +1,531 @@
+// OpenAPI 3 document of the REST server served at /wasm/openapi.json, the messages of every loaded contract are
+// described from the json schemas its analyzer read, so clients and swagger ui can be generated against it
+use crate::contract_vm::analyzer::{Analyzer, Member};
+use crate::{contract_addresses, get_engine};
+use itertools::sorted;
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+
+// message of each entry point in the json schemas of cosmwasm contracts
+const ENTRY_MESSAGES: [(&str, &str); 4] = [
+    ("init", "InitMsg"),
+    ("handle", "HandleMsg"),
+    ("query", "QueryMsg"),
+    ("migrate", "MigrateMsg"),
+];
+
+// component names may only hold letters, digits, '.', '-' and '_', so the contract address is cleaned up
+fn component_name(contract_addr: &str, name: &str) -> String {
+    let clean = |s: &str| -> String {
+        s.chars()
+            .map(
+                |c| match c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                    true => c,
+                    false => '_',
+                },
+            )
+            .collect()
+    };
+    format!("{}.{}", clean(contract_addr), clean(name))
+}
+
+fn schema_ref(name: &str) -> Value {
+    json!({ "$ref": format!("#/components/schemas/{}", name) })
+}
+
+// type_schema maps a member definition of the analyzer, "[T]" is an array of T and a trailing '?' marks it optional
+fn type_schema(contract_addr: &str, analyzer: &Analyzer, def: &str) -> Value {
+    let def = def.trim_end_matches('?');
+    if let Some(item) = def.strip_prefix('[').and_then(|def| def.strip_suffix(']')) {
+        return json!({ "type": "array", "items": type_schema(contract_addr, analyzer, item) });
+    }
+    match def {
+        "string" | "integer" | "number" | "boolean" => json!({ "type": def }),
+        _ if analyzer.map_of_struct.contains_key(def)
+            || analyzer.map_of_basetype.contains_key(def) =>
+        {
+            schema_ref(&component_name(contract_addr, def))
+        }
+        // definitions the analyzer could not resolve take any value
+        _ => json!({}),
+    }
+}
+
+fn object_schema<'a>(
+    contract_addr: &str,
+    analyzer: &Analyzer,
+    members: impl Iterator<Item = (&'a String, &'a String)>,
+) -> Value {
+    let mut properties = Map::new();
+    let mut required = vec![];
+    for (name, def) in sorted(members) {
+        properties.insert(name.to_owned(), type_schema(contract_addr, analyzer, def));
+        if !def.ends_with('?') {
+            required.push(name.to_owned());
+        }
+    }
+    let mut schema = json!({ "type": "object", "properties": properties });
+    if !required.is_empty() {
+        schema["required"] = json!(required);
+    }
+    schema
+}
+
+// message_schema describes an enum message as one of its variants wrapped in an object, a struct message has its
+// members at the top level like the REPL builds them
+fn message_schema(
+    contract_addr: &str,
+    analyzer: &Analyzer,
+    msg_name: &str,
+    variants: &HashMap<String, Vec<Member>>,
+) -> Value {
+    let object = |members: &Vec<Member>| {
+        object_schema(
+            contract_addr,
+            analyzer,
+            members.iter().map(|m| (&m.member_name, &m.member_def)),
+        )
+    };
+    if !analyzer.map_of_enum.get(msg_name).copied().unwrap_or(false) {
+        return variants
+            .values()
+            .next()
+            .map_or(json!({ "type": "object" }), object);
+    }
+    let schemas: Vec<Value> = sorted(variants.keys())
+        .map(|variant| {
+            json!({
+                "type": "object",
+                "title": variant,
+                "required": [variant],
+                "properties": { variant.as_str(): object(&variants[variant]) },
+                "additionalProperties": false,
+            })
+        })
+        .collect();
+    json!({ "oneOf": schemas })
+}
+
+fn contract_schemas(contract_addr: &str, analyzer: &Analyzer, schemas: &mut Map<String, Value>) {
+    for (name, base_type) in analyzer.map_of_basetype.iter() {
+        schemas.insert(
+            component_name(contract_addr, name),
+            type_schema(contract_addr, analyzer, base_type),
+        );
+    }
+    for (name, members) in analyzer.map_of_struct.iter() {
+        schemas.insert(
+            component_name(contract_addr, name),
+            object_schema(contract_addr, analyzer, members.iter()),
+        );
+    }
+    for (msg_name, variants) in analyzer.map_of_member.iter() {
+        schemas.insert(
+            component_name(contract_addr, msg_name),
+            message_schema(contract_addr, analyzer, msg_name, variants),
+        );
+    }
+}
+
+fn path_param(name: &str, description: &str) -> Value {
+    json!({
+        "name": name,
+        "in": "path",
+        "required": true,
+        "description": description,
+        "schema": { "type": "string" },
+    })
+}
+
+fn query_param(name: &str, description: &str) -> Value {
+    json!({
+        "name": name,
+        "in": "query",
+        "required": false,
+        "description": description,
+        "schema": { "type": "string" },
+    })
+}
+
+// call_params are the query parameters every call of an entry point takes
+fn call_params() -> Vec<Value> {
+    vec![
+        query_param(
+            "sender",
+            "account calling the contract, the default sender otherwise",
+        ),
+        query_param("funds", "coins attached to the call, like 100orai,5uatom"),
+        query_param("height", "block height the call runs at"),
+        query_param("code", "wasm file a migration switches the contract to"),
+    ]
+}
+
+fn json_body(schema: Value) -> Value {
+    json!({ "required": true, "content": { "application/json": { "schema": schema } } })
+}
+
+// operation documents one route, errors are the {"error"} object of error_json
+fn operation(summary: &str, parameters: Vec<Value>, body: Option<Value>, response: Value) -> Value {
+    let mut operation = json!({
+        "summary": summary,
+        "parameters": parameters,
+        "responses": {
+            "200": {
+                "description": "success",
+                "content": { "application/json": { "schema": response } },
+            },
+            "default": {
+                "description": "error",
+                "content": { "application/json": { "schema": schema_ref("Error") } },
+            },
+        },
+    });
+    if let Some(body) = body {
+        operation["requestBody"] = body;
+    }
+    operation
+}
+
+fn routes() -> Map<String, Value> {
+    let address = || path_param("address", "contract address or label");
+    let entry_point = || {
+        let mut param = path_param("entry_point", "entry point of the contract");
+        param["schema"]["enum"] = json!(["init", "handle", "query", "migrate"]);
+        param
+    };
+    let any = || json!({});
+    let strings = || json!({ "type": "array", "items": { "type": "string" } });
+    let mut paths = Map::new();
+    let mut add = |path: &str, method: &str, operation: Value| {
+        let entry = paths.entry(path.to_string()).or_insert_with(|| json!({}));
+        entry[method] = operation;
+    };
+
+    add(
+        "/wasm/openapi.json",
+        "get",
+        operation("this document", vec![], None, json!({ "type": "object" })),
+    );
+    add(
+        "/wasm/contracts",
+        "get",
+        operation("addresses of the loaded contracts", vec![], None, strings()),
+    );
+    add(
+        "/wasm/codes",
+        "get",
+        operation(
+            "loaded codes with their wasm files, code ids start at 1",
+            vec![],
+            None,
+            json!({ "type": "array", "items": {
+                "type": "object",
+                "properties": { "code_id": { "type": "integer" }, "wasm_file": { "type": "string" } },
+            }}),
+        ),
+    );
+    add(
+        "/wasm/code",
+        "post",
+        operation(
+            "upload a wasm file, a zip of the wasm and its schemas, a multipart form or base64 code in json and load it \
+             as a contract",
+            vec![query_param("address", "address of the new contract")],
+            Some(json!({ "required": true, "content": {
+                "application/wasm": { "schema": { "type": "string", "format": "binary" } },
+                "application/zip": { "schema": { "type": "string", "format": "binary" } },
+                "multipart/form-data": { "schema": { "type": "object" } },
+                "application/json": { "schema": { "type": "object", "required": ["wasm_byte_code"], "properties": {
+                    "wasm_byte_code": { "type": "string", "format": "byte" },
+                    "address": { "type": "string" },
+                }}},
+            }})),
+            json!({ "type": "object", "properties": {
+                "contract_address": { "type": "string" },
+                "code_id": { "type": "integer" },
+                "schema_files": { "type": "integer" },
+            }}),
+        ),
+    );
+    add(
+        "/wasm/code/{code_id}/instantiate",
+        "post",
+        operation(
+            "instantiate a loaded code with the init message as body",
+            vec![
+                path_param("code_id", "id of the code"),
+                query_param("label", "address of the new contract when it is free"),
+                query_param("sender", "account instantiating the contract"),
+                query_param("funds", "coins attached to the call, like 100orai,5uatom"),
+            ],
+            Some(json_body(json!({ "type": "object" }))),
+            schema_ref("Instantiated"),
+        ),
+    );
+    add(
+        "/wasm/instantiate",
+        "post",
+        operation(
+            "instantiate a loaded code like the instantiate message of wasmd",
+            vec![],
+            Some(json_body(
+                json!({ "type": "object", "required": ["code_id", "msg"], "properties": {
+                    "code_id": { "type": "integer" },
+                    "msg": { "type": "object" },
+                    "label": { "type": "string" },
+                    "sender": { "type": "string" },
+                    "funds": { "type": "array", "items": {
+                        "type": "object",
+                        "properties": { "denom": { "type": "string" }, "amount": { "type": "string" } },
+                    }},
+                }}),
+            )),
+            schema_ref("Instantiated"),
+        ),
+    );
+    add(
+        "/wasm/events",
+        "get",
+        operation(
+            "search the events of the executed calls",
+            vec![
+                query_param("contract", "only events of this contract"),
+                query_param(
+                    "attribute",
+                    "key:value the event must have, may be repeated",
+                ),
+                query_param("limit", "most events returned"),
+            ],
+            None,
+            json!({ "type": "array", "items": { "type": "object" } }),
+        ),
+    );
+    add(
+        "/wasm/contract/{address}",
+        "delete",
+        operation(
+            "remove a contract",
+            vec![address()],
+            None,
+            json!({ "type": "object", "properties": { "removed": { "type": "string" } } }),
+        ),
+    );
+    add(
+        "/wasm/contract/{address}/schema",
+        "get",
+        operation(
+            "messages, structs and base types analyzed from the json schemas of the contract",
+            vec![address()],
+            None,
+            json!({ "type": "object" }),
+        ),
+    );
+    add(
+        "/wasm/contract/{address}/state",
+        "get",
+        operation(
+            "storage of the contract as base64 key to base64 value",
+            vec![
+                address(),
+                query_param("decode", "true lists decoded entries instead"),
+            ],
+            None,
+            any(),
+        ),
+    );
+    add(
+        "/wasm/contract/{address}/state/{key}",
+        "get",
+        operation(
+            "decoded entry of one storage key",
+            vec![address(), path_param("key", "base64 storage key")],
+            None,
+            schema_ref("StateEntry"),
+        ),
+    );
+    add(
+        "/wasm/contract/{address}/raw/{key}",
+        "get",
+        operation(
+            "value of one storage key like the raw query of wasmd",
+            vec![address(), path_param("key", "base64 storage key")],
+            None,
+            json!({ "type": "object", "properties": { "data": { "type": "string", "nullable": true } } }),
+        ),
+    );
+    add(
+        "/wasm/contract/{address}/proof",
+        "get",
+        operation(
+            "proof of a storage key against the iavl root of the contract",
+            vec![address(), query_param("key", "hex storage key")],
+            None,
+            json!({ "type": "object" }),
+        ),
+    );
+    let mut params = vec![
+        address(),
+        entry_point(),
+        path_param("msg", "json message, raw or base64 encoded"),
+    ];
+    params.extend(call_params());
+    add(
+        "/wasm/contract/{address}/{entry_point}/{msg}",
+        "get",
+        operation("call an entry point of the contract", params, None, any()),
+    );
+    let mut params = vec![address(), entry_point()];
+    params.extend(call_params());
+    add(
+        "/wasm/contract/{address}/{entry_point}",
+        "post",
+        operation(
+            "call an entry point of the contract with the message as body",
+            params,
+            Some(json_body(json!({ "type": "object" }))),
+            any(),
+        ),
+    );
+    add(
+        "/cosmos/tx/v1beta1/txs",
+        "post",
+        operation(
+            "execute a protobuf encoded cosmos tx",
+            vec![],
+            Some(json_body(
+                json!({ "type": "object", "required": ["tx_bytes"], "properties": {
+                    "tx_bytes": { "type": "string", "format": "byte" },
+                    "verify": { "type": "boolean" },
+                    "account_number": { "type": "integer" },
+                }}),
+            )),
+            json!({ "type": "object" }),
+        ),
+    );
+    add(
+        "/snapshots",
+        "get",
+        operation("names of the saved snapshots", vec![], None, strings()),
+    );
+    add(
+        "/snapshot/{name}",
+        "post",
+        operation(
+            "save the state of every contract",
+            vec![path_param("name", "name of the snapshot")],
+            None,
+            json!({ "type": "object", "properties": {
+                "saved": { "type": "string" },
+                "contracts": { "type": "integer" },
+            }}),
+        ),
+    );
+    add(
+        "/snapshot/{name}/restore",
+        "post",
+        operation(
+            "rewind every contract and the block to a snapshot",
+            vec![path_param("name", "name of the snapshot")],
+            None,
+            json!({ "type": "object", "properties": {
+                "restored": { "type": "string" },
+                "height": { "type": "integer" },
+                "not_restored": { "type": "array", "items": { "type": "string" } },
+            }}),
+        ),
+    );
+    add(
+        "/ws",
+        "get",
+        json!({
+            "summary": "websocket feed of the call, error, event and storage frames",
+            "responses": { "101": { "description": "switching to the websocket protocol" } },
+        }),
+    );
+    paths
+}
+
+// contract_routes adds the entry points of one contract with the message schemas of its analyzer as body
+fn contract_routes(contract_addr: &str, analyzer: &Analyzer, paths: &mut Map<String, Value>) {
+    for (func, msg_name) in ENTRY_MESSAGES.iter() {
+        if !analyzer.map_of_member.contains_key(*msg_name) {
+            continue;
+        }
+        let mut params = call_params();
+        params.retain(|param| func.eq(&"migrate") || param["name"].ne("code"));
+        let mut operation = operation(
+            &format!("{} {} with {}", func, contract_addr, msg_name),
+            params,
+            Some(json_body(schema_ref(&component_name(
+                contract_addr,
+                msg_name,
+            )))),
+            json!({}),
+        );
+        operation["tags"] = json!([contract_addr]);
+        paths.insert(
+            format!("/wasm/contract/{}/{}", contract_addr, func),
+            json!({ "post": operation }),
+        );
+    }
+}
+
+pub fn document() -> Value {
+    let mut paths = routes();
+    let mut schemas = Map::new();
+    schemas.insert(
+        "Error".to_string(),
+        json!({ "type": "object", "required": ["error"], "properties": {
+            "error": { "type": "string" },
+            "code": { "type": "integer" },
+        }}),
+    );
+    schemas.insert(
+        "Instantiated".to_string(),
+        json!({ "type": "object", "properties": {
+            "contract_address": { "type": "string" },
+            "code_id": { "type": "integer" },
+            "result": {},
+        }}),
+    );
+    schemas.insert(
+        "StateEntry".to_string(),
+        json!({ "type": "object", "properties": {
+            "key": { "type": "string", "format": "byte" },
+            "key_name": { "type": "string" },
+            "value": { "type": "string", "format": "byte", "nullable": true },
+            "value_text": { "type": "string", "nullable": true },
+            "value_json": { "nullable": true },
+        }}),
+    );
+    schemas.insert(
+        "Envelope".to_string(),
+        json!({ "type": "object", "properties": {
+            "result": {},
+            "error": { "type": "object", "nullable": true, "properties": {
+                "message": {},
+                "code": { "type": "integer", "nullable": true },
+            }},
+            "height": { "type": "integer" },
+            "gas_used": { "type": "integer", "nullable": true },
+            "events": { "type": "array", "items": { "type": "object" } },
+            "receipt": { "type": "object", "nullable": true },
+        }}),
+    );
+    for contract_addr in contract_addresses() {
+        if let Some(engine) = get_engine(&contract_addr) {
+            contract_schemas(&contract_addr, &engine.analyzer, &mut schemas);
+            contract_routes(&contract_addr, &engine.analyzer, &mut paths);
+        }
+    }
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "cosmwasm-simulate",
+            "version": env!("CARGO_PKG_VERSION"),
+            "description": "every route is also served under /v1 with the response wrapped in an Envelope",
+        },
+        "paths": paths,
+        "components": { "schemas": schemas },
+    })
+}
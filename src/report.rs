@@ -0,0 +1,364 @@
+// standalone html report of the calls made in a session
+use sha2::{Digest, Sha256};
+use std::fmt::Write;
+
+pub struct StateChange {
+    pub key: String,
+    pub before: Option<String>,
+    pub after: Option<String>,
+}
+
+#[derive(Default)]
+pub struct CallRecord {
+    pub height: u64,
+    pub contract: String,
+    pub call_type: String,
+    pub sender: String,
+    pub msg: String,
+    pub result: String,
+    pub gas_used: u64,
+    pub instructions: u64,
+    pub duration: std::time::Duration,
+    // reads, writes, removes, scans and iterator steps of the storage
+    pub storage_ops: [usize; 5],
+    pub attributes: Vec<(String, String)>,
+    pub messages: String,
+    // storage changes of the called contract
+    pub state_changes: Vec<StateChange>,
+}
+
+const STYLE: &str = "body{font-family:sans-serif;margin:24px}table{border-collapse:collapse;width:100%;margin-bottom:8px}\
+td,th{border:1px solid #ddd;padding:4px 8px;text-align:left;vertical-align:top;font-size:13px}\
+th{background:#f4f4f4}pre{margin:0;white-space:pre-wrap;word-break:break-all}\
+.call{border:1px solid #ccc;border-radius:4px;padding:12px;margin-bottom:16px}\
+.error{color:#c62828}.ok{color:#2e7d32}.removed{background:#ffebee}.added{background:#e8f5e9}";
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn is_error(result: &str) -> bool {
+    serde_json::from_str::<serde_json::Value>(result)
+        .map_or(false, |value| value.get("error").is_some())
+}
+
+fn render(records: &[CallRecord]) -> String {
+    let mut html = String::new();
+    let total_gas: u64 = records.iter().map(|r| r.gas_used).sum();
+    let failed = records.iter().filter(|r| is_error(&r.result)).count();
+    let _ = write!(
+        html,
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>cosmwasm-simulate session report</title>\
+<style>{}</style></head><body><h1>Session report</h1>",
+        STYLE
+    );
+
+    // summary of every call
+    let _ = write!(
+        html,
+        "<p>{} calls, {} failed, {} gas used in total</p><table><tr><th>#</th><th>height</th><th>contract</th>\
+<th>type</th><th>sender</th><th>gas</th><th>status</th></tr>",
+        records.len(),
+        failed,
+        total_gas
+    );
+    for (index, record) in records.iter().enumerate() {
+        let (class, status) = match is_error(&record.result) {
+            true => ("error", "failed"),
+            false => ("ok", "ok"),
+        };
+        let _ = write!(
+            html,
+            "<tr><td><a href=\"#call-{0}\">{0}</a></td><td>{1}</td><td>{2}</td><td>{3}</td><td>{4}</td>\
+<td>{5}</td><td class=\"{6}\">{7}</td></tr>",
+            index + 1,
+            record.height,
+            escape(&record.contract),
+            escape(&record.call_type),
+            escape(&record.sender),
+            record.gas_used,
+            class,
+            status
+        );
+    }
+    html.push_str("</table>");
+
+    // details of every call
+    for (index, record) in records.iter().enumerate() {
+        let _ = write!(
+            html,
+            "<div class=\"call\" id=\"call-{}\"><h3>#{} {} {} at height {}</h3>\
+<table><tr><th>sender</th><td>{}</td></tr><tr><th>message</th><td><pre>{}</pre></td></tr>\
+<tr><th>result</th><td class=\"{}\"><pre>{}</pre></td></tr><tr><th>gas used</th><td>{}</td></tr>\
+<tr><th>storage ops</th><td>{} reads, {} writes, {} removes, {} scans, {} iterator steps</td></tr>",
+            index + 1,
+            index + 1,
+            escape(&record.call_type),
+            escape(&record.contract),
+            record.height,
+            escape(&record.sender),
+            escape(&record.msg),
+            match is_error(&record.result) {
+                true => "error",
+                false => "ok",
+            },
+            escape(&record.result),
+            record.gas_used,
+            record.storage_ops[0],
+            record.storage_ops[1],
+            record.storage_ops[2],
+            record.storage_ops[3],
+            record.storage_ops[4]
+        );
+        if !record.messages.is_empty() && record.messages.ne("[]") {
+            let _ = write!(
+                html,
+                "<tr><th>messages</th><td><pre>{}</pre></td></tr>",
+                escape(&record.messages)
+            );
+        }
+        html.push_str("</table>");
+
+        if !record.attributes.is_empty() {
+            html.push_str("<h4>Events</h4><table><tr><th>key</th><th>value</th></tr>");
+            for (key, value) in record.attributes.iter() {
+                let _ = write!(
+                    html,
+                    "<tr><td>{}</td><td>{}</td></tr>",
+                    escape(key),
+                    escape(value)
+                );
+            }
+            html.push_str("</table>");
+        }
+
+        if !record.state_changes.is_empty() {
+            html.push_str(
+                "<h4>State changes</h4><table><tr><th>key</th><th>before</th><th>after</th></tr>",
+            );
+            for change in record.state_changes.iter() {
+                let _ = write!(
+                    html,
+                    "<tr><td>{}</td><td class=\"removed\"><pre>{}</pre></td><td class=\"added\"><pre>{}</pre></td></tr>",
+                    escape(&change.key),
+                    escape(change.before.as_deref().unwrap_or("-")),
+                    escape(change.after.as_deref().unwrap_or("-"))
+                );
+            }
+            html.push_str("</table>");
+        }
+        html.push_str("</div>");
+    }
+    html.push_str("</body></html>");
+    html
+}
+
+#[derive(Serialize)]
+struct Metrics<'a> {
+    index: usize,
+    height: u64,
+    contract: &'a str,
+    entry_point: &'a str,
+    msg_size: usize,
+    gas_used: u64,
+    instructions: u64,
+    duration_us: u128,
+    storage_reads: usize,
+    storage_writes: usize,
+    storage_removes: usize,
+    storage_scans: usize,
+    storage_nexts: usize,
+    failed: bool,
+}
+
+fn csv_field(text: &str) -> String {
+    match text.contains(|c| c == ',' || c == '"' || c == '\n') {
+        true => format!("\"{}\"", text.replace('"', "\"\"")),
+        false => text.to_string(),
+    }
+}
+
+// write_metrics exports per call metrics as json when the file ends with .json, otherwise as csv
+pub fn write_metrics(file: &str, records: &[CallRecord]) -> Result<(), String> {
+    let metrics: Vec<Metrics> = records
+        .iter()
+        .enumerate()
+        .map(|(index, record)| Metrics {
+            index: index + 1,
+            height: record.height,
+            contract: &record.contract,
+            entry_point: &record.call_type,
+            msg_size: record.msg.len(),
+            gas_used: record.gas_used,
+            instructions: record.instructions,
+            duration_us: record.duration.as_micros(),
+            storage_reads: record.storage_ops[0],
+            storage_writes: record.storage_ops[1],
+            storage_removes: record.storage_ops[2],
+            storage_scans: record.storage_ops[3],
+            storage_nexts: record.storage_ops[4],
+            failed: is_error(&record.result),
+        })
+        .collect();
+
+    let content = match file.ends_with(".json") {
+        true => serde_json::to_string_pretty(&metrics).map_err(|e| e.to_string())?,
+        false => {
+            let mut csv = String::from("index,height,contract,entry_point,msg_size,gas_used,instructions,duration_us,storage_reads,storage_writes,storage_removes,storage_scans,storage_nexts,failed\n");
+            for m in metrics.iter() {
+                let _ = writeln!(
+                    csv,
+                    "{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+                    m.index,
+                    m.height,
+                    csv_field(m.contract),
+                    m.entry_point,
+                    m.msg_size,
+                    m.gas_used,
+                    m.instructions,
+                    m.duration_us,
+                    m.storage_reads,
+                    m.storage_writes,
+                    m.storage_removes,
+                    m.storage_scans,
+                    m.storage_nexts,
+                    m.failed
+                );
+            }
+            csv
+        }
+    };
+    std::fs::write(file, content).map_err(|e| e.to_string())
+}
+
+// fixture turns a call into what the matching CosmJS client method resolves to, failed calls keep the error
+// message CosmJS would throw
+fn fixture(index: usize, record: &CallRecord) -> serde_json::Value {
+    let result: serde_json::Value =
+        serde_json::from_str(&record.result).unwrap_or(serde_json::Value::Null);
+    let method = match record.call_type.as_str() {
+        "init" => "instantiate",
+        "handle" => "execute",
+        _ => "queryContractSmart",
+    };
+    let mut fixture = serde_json::json!({
+        "contract": record.contract,
+        "method": method,
+        "msg": serde_json::from_str::<serde_json::Value>(&record.msg).unwrap_or_default(),
+    });
+    if method.ne("queryContractSmart") {
+        fixture["sender"] = serde_json::json!(record.sender);
+    }
+    if let Some(error) = result.get("error") {
+        fixture["error"] = error.to_owned();
+        return fixture;
+    }
+    if method.eq("queryContractSmart") {
+        fixture["response"] = result;
+        return fixture;
+    }
+
+    let attribute = |key: &str, value: &str| serde_json::json!({ "key": key, "value": value });
+    let mut wasm = vec![attribute("contract_address", &record.contract)];
+    wasm.extend(
+        record
+            .attributes
+            .iter()
+            .map(|(key, value)| attribute(key, value)),
+    );
+    let events = serde_json::json!([
+        {
+            "type": "message",
+            "attributes": [
+                attribute("action", method),
+                attribute("module", "wasm"),
+                attribute("sender", &record.sender),
+            ],
+        },
+        { "type": "wasm", "attributes": wasm },
+    ]);
+    let hash = Sha256::digest(
+        format!(
+            "{}/{}/{}/{}",
+            record.height, index, record.contract, record.msg
+        )
+        .as_bytes(),
+    );
+    fixture["response"] = serde_json::json!({
+        "logs": [{ "msg_index": 0, "log": "", "events": events }],
+        "height": record.height,
+        "transactionHash": hex::encode_upper(hash),
+        "gasWanted": record.gas_used,
+        "gasUsed": record.gas_used,
+        "events": events,
+    });
+    if method.eq("instantiate") {
+        fixture["response"]["contractAddress"] = serde_json::json!(record.contract);
+    }
+    fixture
+}
+
+// write_fixtures exports the calls with their responses for frontend tests that mock CosmJS clients
+pub fn write_fixtures(file: &str, records: &[CallRecord]) -> Result<(), String> {
+    let fixtures: Vec<serde_json::Value> = records
+        .iter()
+        .enumerate()
+        .map(|(index, record)| fixture(index, record))
+        .collect();
+    let content = serde_json::to_string_pretty(&fixtures).map_err(|e| e.to_string())?;
+    std::fs::write(file, content).map_err(|e| e.to_string())
+}
+
+// step of a scenario run as a junit test case
+pub struct TestCase {
+    pub name: String,
+    pub contract: String,
+    pub duration: std::time::Duration,
+    // message and output of a step that did not behave as expected
+    pub failure: Option<(String, String)>,
+}
+
+// write_junit writes the steps of a scenario as one junit test suite so ci systems show them as tests
+pub fn write_junit(file: &str, suite: &str, cases: &[TestCase]) -> Result<(), String> {
+    let failures = cases.iter().filter(|case| case.failure.is_some()).count();
+    let time: f64 = cases.iter().map(|case| case.duration.as_secs_f64()).sum();
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    let _ = writeln!(
+        xml,
+        "<testsuites tests=\"{0}\" failures=\"{1}\" time=\"{2:.3}\">\n\
+<testsuite name=\"{3}\" tests=\"{0}\" failures=\"{1}\" errors=\"0\" skipped=\"0\" time=\"{2:.3}\">",
+        cases.len(),
+        failures,
+        time,
+        escape(suite)
+    );
+    for case in cases.iter() {
+        let _ = write!(
+            xml,
+            "<testcase name=\"{}\" classname=\"{}\" time=\"{:.3}\"",
+            escape(&case.name),
+            escape(&case.contract),
+            case.duration.as_secs_f64()
+        );
+        match case.failure.as_ref() {
+            None => xml.push_str("/>\n"),
+            Some((message, output)) => {
+                let _ = writeln!(
+                    xml,
+                    ">\n<failure message=\"{}\">{}</failure>\n</testcase>",
+                    escape(message),
+                    escape(output)
+                );
+            }
+        }
+    }
+    xml.push_str("</testsuite>\n</testsuites>\n");
+    std::fs::write(file, xml).map_err(|e| e.to_string())
+}
+
+pub fn write_html(file: &str, records: &[CallRecord]) -> Result<(), String> {
+    std::fs::write(file, render(records)).map_err(|e| e.to_string())
+}
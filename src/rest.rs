@@ -0,0 +1,703 @@
+// REST api for dapp development, every route calls into the loaded engines
+use crate::contract_vm::engine::BLOCK_HEIGHT;
+use crate::contract_vm::watcher::storage_key_name;
+use crate::openapi;
+use crate::rpc::search_events;
+use crate::stream;
+use crate::tx;
+use crate::upload::upload;
+use crate::{
+    call_contract_at, contract_addresses, contract_proof, error_json, get_engine,
+    instantiate_contract, is_error_result, lock_engines, migrate_contract, parse_coins,
+    query_simulator, remove_contract, resolve_address, restore_snapshot, save_snapshot,
+    sender_info, SIMULATOR_ADDRESS,
+};
+use colored::*;
+use cosmwasm_std::{Binary, ContractResult, MessageInfo, SystemResult};
+use percent_encoding::percent_decode_str;
+use serde_json::Value;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+pub const DEFAULT_PORT: &str = "1317";
+// web ui served at the root path
+const INDEX_HTML: &str = include_str!("../static/index.html");
+const JSON_CONTENT_TYPE: &str = "application/json";
+const HTML_CONTENT_TYPE: &str = "text/html; charset=utf-8";
+const DEFAULT_EVENTS_LIMIT: usize = 50;
+// entry points called through /wasm/contract/<address>/<entry point>
+const ENTRY_POINTS: [&str; 4] = ["init", "handle", "query", "migrate"];
+
+fn list_contracts() -> String {
+    serde_json::json!(contract_addresses()).to_string()
+}
+
+// list_codes returns the loaded codes with their wasm files, code ids start at 1
+fn list_codes() -> String {
+    let codes: Vec<Value> = unsafe { crate::Config::get() }
+        .codes
+        .iter()
+        .enumerate()
+        .map(|(index, file)| serde_json::json!({ "code_id": index + 1, "wasm_file": file }))
+        .collect();
+    serde_json::json!(codes).to_string()
+}
+
+fn list_snapshots() -> String {
+    let names: Vec<&String> = unsafe { crate::Config::get() }.snapshots.keys().collect();
+    serde_json::json!(names).to_string()
+}
+
+// snapshot saves the state of every contract with POST /snapshot/<name>, POST /snapshot/<name>/restore rewinds to it
+fn snapshot(name: &str, restore: bool) -> (u16, String) {
+    let name = percent_decode_str(name).decode_utf8_lossy().to_string();
+    if !restore {
+        let contracts = save_snapshot(&name);
+        return (
+            200,
+            serde_json::json!({ "saved": name, "contracts": contracts }).to_string(),
+        );
+    }
+    match restore_snapshot(&name) {
+        Ok(missing) => (
+            200,
+            serde_json::json!({
+                "restored": name,
+                "height": unsafe { BLOCK_HEIGHT },
+                "not_restored": missing,
+            })
+            .to_string(),
+        ),
+        Err(e) => (404, error_json(&e)),
+    }
+}
+
+// contract_schema returns the analyzed json schema so clients can build message forms
+fn contract_schema(contract_addr: &str) -> (u16, String) {
+    match get_engine(contract_addr) {
+        None => (
+            404,
+            error_json(&format!("No such contract: {}", contract_addr)),
+        ),
+        Some(engine) => (200, engine.analyzer.to_json().to_string()),
+    }
+}
+
+// state_entry shows a storage entry as base64 with best effort decodings: the key name with its namespaces, the
+// value as text when it is utf8 and as json when it parses
+fn state_entry(key: &[u8], value: Option<&[u8]>) -> Value {
+    let text = value.and_then(|value| std::str::from_utf8(value).ok());
+    serde_json::json!({
+        "key": base64::encode(key),
+        "key_name": storage_key_name(key),
+        "value": value.map(base64::encode),
+        "value_text": text,
+        "value_json": text.and_then(|text| serde_json::from_str::<Value>(text).ok()),
+    })
+}
+
+// contract_state dumps the contract storage as base64 key to base64 value, ?decode=true lists decoded entries instead
+fn contract_state(contract_addr: &str, url: &str) -> (u16, String) {
+    let decode = query_param(url, "decode").map_or(false, |decode| decode.eq("true"));
+    match get_engine(contract_addr) {
+        None => (
+            404,
+            error_json(&format!("No such contract: {}", contract_addr)),
+        ),
+        Some(engine) if decode => {
+            let entries: Vec<Value> = engine
+                .with_storage(|storage| {
+                    Ok(storage
+                        .data
+                        .iter()
+                        .map(|(key, value)| state_entry(key, Some(value)))
+                        .collect())
+                })
+                .unwrap();
+            (200, Value::Array(entries).to_string())
+        }
+        Some(engine) => (
+            200,
+            engine
+                .with_storage(|storage| Ok(storage.to_state()))
+                .unwrap(),
+        ),
+    }
+}
+
+// storage_value reads one base64 storage key of the contract
+fn storage_value(
+    contract_addr: &str,
+    key: &str,
+) -> Result<(Vec<u8>, Option<Vec<u8>>), (u16, String)> {
+    let key = percent_decode_str(key).decode_utf8_lossy().to_string();
+    let key = base64::decode(&key)
+        .or_else(|_| base64::decode_config(&key, base64::URL_SAFE))
+        .map_err(|e| {
+            (
+                400,
+                error_json(&format!("wrong key {}, must be base64: {}", key, e)),
+            )
+        })?;
+    match get_engine(contract_addr) {
+        None => Err((
+            404,
+            error_json(&format!("No such contract: {}", contract_addr)),
+        )),
+        Some(engine) => {
+            let value = engine
+                .with_storage(|storage| Ok(storage.data.get(&key).cloned()))
+                .unwrap();
+            Ok((key, value))
+        }
+    }
+}
+
+// contract_raw returns the value of one base64 storage key like the raw query of wasmd, missing keys have null data
+fn contract_raw(contract_addr: &str, key: &str) -> (u16, String) {
+    match storage_value(contract_addr, key) {
+        Ok((_, value)) => (
+            200,
+            serde_json::json!({ "data": value.map(base64::encode) }).to_string(),
+        ),
+        Err(e) => e,
+    }
+}
+
+// contract_state_key returns the decoded entry of one base64 storage key, missing keys have a null value
+fn contract_state_key(contract_addr: &str, key: &str) -> (u16, String) {
+    match storage_value(contract_addr, key) {
+        Ok((key, value)) => (200, state_entry(&key, value.as_deref()).to_string()),
+        Err(e) => e,
+    }
+}
+
+// simulator_query answers introspection queries without a contract call
+fn simulator_query(msg: &str) -> (u16, String) {
+    let msg = match decode_msg(msg) {
+        Ok(msg) => msg,
+        Err(e) => return (400, error_json(&e)),
+    };
+    match query_simulator(&Binary::from(msg.as_bytes())) {
+        SystemResult::Ok(ContractResult::Ok(response)) => (
+            200,
+            String::from_utf8_lossy(response.as_slice()).to_string(),
+        ),
+        SystemResult::Ok(ContractResult::Err(e)) => (400, error_json(&e)),
+        SystemResult::Err(e) => (400, error_json(&e.to_string())),
+    }
+}
+
+// proof proves the hex key of ?key= against the iavl root of the contract
+fn proof(contract_addr: &str, url: &str) -> (u16, String) {
+    let key = query_param(url, "key").unwrap_or_default();
+    match contract_proof(contract_addr, &key) {
+        Ok(proof) => (200, proof.to_string()),
+        Err(e) if e.starts_with("No such contract") => (404, error_json(&e)),
+        Err(e) => (400, error_json(&e)),
+    }
+}
+
+// query_params returns the percent decoded pairs of the query string
+fn query_params(url: &str) -> Vec<(String, String)> {
+    url.splitn(2, '?')
+        .nth(1)
+        .unwrap_or_default()
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(key, value)| {
+            (
+                key.to_string(),
+                percent_decode_str(value).decode_utf8_lossy().to_string(),
+            )
+        })
+        .collect()
+}
+
+fn query_param(url: &str, name: &str) -> Option<String> {
+    query_params(url)
+        .into_iter()
+        .find(|(key, _)| key.eq(name))
+        .map(|(_, value)| value)
+}
+
+// events searches the wasm events of past calls, attribute filters are key:value
+fn events(url: &str) -> (u16, String) {
+    let mut contract_addr = None;
+    let mut filters = vec![];
+    let mut limit = DEFAULT_EVENTS_LIMIT;
+    for (key, value) in query_params(url) {
+        match key.as_str() {
+            "contract" => contract_addr = Some(resolve_address(&value)),
+            "attribute" => match value.split_once(':') {
+                Some((k, v)) => filters.push((k.to_string(), v.to_string())),
+                None => {
+                    return (
+                        400,
+                        error_json(&format!("wrong attribute {}, must be key:value", value)),
+                    )
+                }
+            },
+            "limit" => match value.parse() {
+                Ok(l) => limit = l,
+                Err(_) => return (400, error_json(&format!("wrong limit {}", value))),
+            },
+            _ => {}
+        }
+    }
+    (
+        200,
+        search_events(contract_addr.as_deref(), &filters, limit).to_string(),
+    )
+}
+
+// decode_msg takes a raw json object as it is, otherwise the message is base64 encoded json
+fn decode_msg(msg: &str) -> Result<String, String> {
+    let msg = percent_decode_str(msg).decode_utf8_lossy().to_string();
+    if let Ok(Value::Object(_)) = serde_json::from_str(&msg) {
+        return Ok(msg);
+    }
+    let msg = base64::decode(msg).map_err(|e| e.to_string())?;
+    String::from_utf8(msg).map_err(|e| e.to_string())
+}
+
+// json_body reads the message of a POST call, the body must be a json object sent as application/json
+fn json_body(content_type: &str, content: &[u8]) -> Result<String, (u16, String)> {
+    let media_type = content_type.split(';').next().unwrap_or_default().trim();
+    if !media_type.is_empty() && !media_type.eq_ignore_ascii_case(JSON_CONTENT_TYPE) {
+        return Err((
+            415,
+            error_json(&format!(
+                "Unsupported content type {}, expected {}",
+                media_type, JSON_CONTENT_TYPE
+            )),
+        ));
+    }
+    let msg = String::from_utf8(content.to_vec()).map_err(|e| (400, error_json(&e.to_string())))?;
+    match serde_json::from_str(&msg) {
+        Ok(Value::Object(_)) => Ok(msg),
+        Ok(_) => Err((400, error_json("message must be a json object"))),
+        Err(e) => Err((400, error_json(&format!("invalid json body: {}", e)))),
+    }
+}
+
+// call_info is the sender of ?sender= or the default sender, with the coins of ?funds=100orai,5uatom attached
+fn call_info(sender_addr: &str, url: &str) -> Result<MessageInfo, (u16, String)> {
+    let sender_addr = query_param(url, "sender").unwrap_or_else(|| sender_addr.to_string());
+    let mut info = sender_info(&sender_addr).ok_or_else(|| {
+        (
+            400,
+            error_json(&format!("No account found: {}", sender_addr)),
+        )
+    })?;
+    if let Some(funds) = query_param(url, "funds") {
+        info.sent_funds = parse_coins(&funds).map_err(|e| (400, error_json(&e)))?;
+    }
+    Ok(info)
+}
+
+// call_engine dispatches the decoded message to an entry point of the contract, queries run at a past block with
+// ?height=N and migrate moves the contract to the code of ?code=<code id or wasm file>, ?sender=<account> calls as
+// another account than the default sender
+fn call_engine(
+    contract_addr: &str,
+    func: &str,
+    msg: Result<String, (u16, String)>,
+    sender_addr: &str,
+    url: &str,
+) -> (u16, String) {
+    let msg = match msg {
+        Ok(m) => m,
+        Err(e) => return e,
+    };
+    let info = match call_info(sender_addr, url) {
+        Ok(info) => info,
+        Err(e) => return e,
+    };
+
+    match get_engine(contract_addr) {
+        None => (
+            404,
+            error_json(&format!("No such contract: {}", contract_addr)),
+        ),
+        Some(_) if func.eq("migrate") => (
+            200,
+            migrate_contract(
+                contract_addr,
+                query_param(url, "code").as_deref(),
+                &msg,
+                &info,
+            ),
+        ),
+        Some(engine) => {
+            let height = query_param(url, "height").and_then(|height| height.parse().ok());
+            (200, call_contract_at(engine, func, &msg, &info, height))
+        }
+    }
+}
+
+// instantiate serves POST /wasm/code/<code id>/instantiate with the init message as body, the new contract is at
+// ?label= when it is free or at a generated address
+fn instantiate(
+    code_id: &str,
+    msg: Result<String, (u16, String)>,
+    sender_addr: &str,
+    url: &str,
+) -> (u16, String) {
+    let msg = match msg {
+        Ok(m) => m,
+        Err(e) => return e,
+    };
+    let code_id = match code_id.parse::<usize>() {
+        Ok(code_id) => code_id,
+        Err(_) => return (400, error_json(&format!("invalid code id: {}", code_id))),
+    };
+    let info = match call_info(sender_addr, url) {
+        Ok(info) => info,
+        Err(e) => return e,
+    };
+    instantiate_code(code_id, query_param(url, "label").as_deref(), &msg, &info)
+}
+
+// instantiate_msg serves POST /wasm/instantiate with {"code_id", "msg", "label", "sender", "funds"} as body like the
+// instantiate message of wasmd, funds are a list of coins or 100orai,5uatom
+fn instantiate_msg(body: Result<String, (u16, String)>, sender_addr: &str) -> (u16, String) {
+    let body: Value = match body {
+        Ok(body) => serde_json::from_str(&body).unwrap_or_default(),
+        Err(e) => return e,
+    };
+    let code_id = match body["code_id"]
+        .as_u64()
+        .or_else(|| body["code_id"].as_str().and_then(|id| id.parse().ok()))
+    {
+        Some(code_id) => code_id as usize,
+        None => return (400, error_json("code_id is missing")),
+    };
+    let msg = match body.get("msg").filter(|msg| msg.is_object()) {
+        Some(msg) => msg.to_string(),
+        None => return (400, error_json("msg must be a json object")),
+    };
+    let sender_addr = body["sender"].as_str().unwrap_or(sender_addr);
+    let mut info = match sender_info(sender_addr) {
+        Some(info) => info,
+        None => {
+            return (
+                400,
+                error_json(&format!("No account found: {}", sender_addr)),
+            )
+        }
+    };
+    let funds = match body.get("funds") {
+        None | Some(Value::Null) => Ok(vec![]),
+        Some(Value::String(funds)) => parse_coins(funds),
+        Some(funds) => serde_json::from_value(funds.to_owned()).map_err(|e| e.to_string()),
+    };
+    info.sent_funds = match funds {
+        Ok(funds) => funds,
+        Err(e) => return (400, error_json(&format!("invalid funds: {}", e))),
+    };
+    instantiate_code(code_id, body["label"].as_str(), &msg, &info)
+}
+
+// instantiate_code runs init of a new contract of a loaded code and answers with its address
+fn instantiate_code(
+    code_id: usize,
+    label: Option<&str>,
+    msg: &str,
+    info: &MessageInfo,
+) -> (u16, String) {
+    match instantiate_contract(code_id, label, msg, info) {
+        Ok((_, result)) if is_error_result(&result) => (200, result),
+        Ok((contract_addr, result)) => (
+            200,
+            serde_json::json!({
+                "contract_address": contract_addr,
+                "code_id": code_id,
+                "result": serde_json::from_str::<Value>(&result).unwrap_or(Value::String(result)),
+            })
+            .to_string(),
+        ),
+        Err(e) => (404, error_json(&e)),
+    }
+}
+
+// broadcast_tx executes a protobuf encoded cosmos tx given as {"tx_bytes": base64}, signatures are
+// only verified when "verify" is true, with the optional "account_number" of the signer
+fn broadcast_tx(content: &str) -> (u16, String) {
+    let request: Value = match serde_json::from_str(content) {
+        Ok(request) => request,
+        Err(e) => return (400, error_json(&e.to_string())),
+    };
+    let tx_bytes = match base64::decode(request["tx_bytes"].as_str().unwrap_or_default()) {
+        Ok(tx_bytes) => tx_bytes,
+        Err(e) => return (400, error_json(&format!("invalid tx_bytes: {}", e))),
+    };
+    let verify_with = match request["verify"].as_bool().unwrap_or_default() {
+        true => Some(request["account_number"].as_u64().unwrap_or_default()),
+        false => None,
+    };
+    tx::execute(&tx_bytes, verify_with)
+}
+
+// delete_contract serves DELETE /wasm/contract/<address>
+fn delete_contract(path: &str) -> (u16, String) {
+    let contract_addr = match path.strip_prefix("/wasm/contract/") {
+        Some(contract_addr) if !contract_addr.contains('/') => contract_addr,
+        _ => return (404, error_json(&format!("No route for {}", path))),
+    };
+    match remove_contract(&resolve_address(contract_addr)) {
+        true => (
+            200,
+            serde_json::json!({ "removed": contract_addr }).to_string(),
+        ),
+        false => (
+            404,
+            error_json(&format!("No such contract: {}", contract_addr)),
+        ),
+    }
+}
+
+// envelope wraps a response of the /v1 routes, calls also report the gas used and the events of the whole call with
+// the messages it dispatched: {"result": .., "error": null | {"message", "code"}, "height": .., "gas_used": null | n,
+// "events": [..], "receipt": null | {..}}
+fn envelope(status: u16, body: &str, call: Option<&str>) -> String {
+    let body: Value =
+        serde_json::from_str(body).unwrap_or_else(|_| Value::String(body.to_string()));
+    let error = match (status, body.get("error")) {
+        (_, Some(message)) => serde_json::json!({ "message": message, "code": body.get("code") }),
+        (status, None) if status >= 400 => serde_json::json!({ "message": body, "code": null }),
+        _ => Value::Null,
+    };
+    // requests rejected before reaching the contract have no gas and events of their own
+    let receipt = call
+        .filter(|_| status == 200)
+        .and_then(get_engine)
+        .map(|engine| engine.last_receipt.clone());
+    let (gas_used, events) = match receipt.as_ref() {
+        Some(receipt) => (
+            serde_json::json!(receipt.gas_used),
+            serde_json::json!(receipt.events),
+        ),
+        None => (Value::Null, serde_json::json!([])),
+    };
+    serde_json::json!({
+        "result": if error.is_null() { body } else { Value::Null },
+        "error": error,
+        "height": unsafe { BLOCK_HEIGHT },
+        "gas_used": gas_used,
+        "events": events,
+        "receipt": receipt,
+    })
+    .to_string()
+}
+
+// route serves the /v1 routes in the stable envelope, the unprefixed routes keep their bare responses
+fn route(
+    method: &Method,
+    url: &str,
+    content_type: &str,
+    content: &[u8],
+    sender_addr: &str,
+) -> (u16, &'static str, String) {
+    let path = url.split('?').next().unwrap_or_default();
+    let path = match path.strip_prefix("/v1/") {
+        Some(path) => path,
+        None => return dispatch(method, url, path, content_type, content, sender_addr),
+    };
+    let path = format!("/{}", path);
+    let (status, content_type, body) =
+        dispatch(method, url, &path, content_type, content, sender_addr);
+    if content_type.ne(JSON_CONTENT_TYPE) {
+        return (
+            404,
+            JSON_CONTENT_TYPE,
+            envelope(404, &error_json(&format!("No route for /v1{}", path)), None),
+        );
+    }
+    // only calls of the entry points and instantiations report gas and events
+    let call = match path
+        .trim_start_matches('/')
+        .splitn(5, '/')
+        .collect::<Vec<&str>>()[..]
+    {
+        ["wasm", "contract", contract_addr, func, _] if method.eq(&Method::Get) => {
+            match ENTRY_POINTS.contains(&func) {
+                true => Some(contract_addr.to_string()),
+                false => None,
+            }
+        }
+        ["wasm", "contract", contract_addr, func] if method.eq(&Method::Post) => {
+            match ENTRY_POINTS.contains(&func) {
+                true => Some(contract_addr.to_string()),
+                false => None,
+            }
+        }
+        // the new contract made the call
+        ["wasm", "code", _, "instantiate"] | ["wasm", "instantiate"]
+            if method.eq(&Method::Post) =>
+        {
+            serde_json::from_str::<Value>(&body)
+                .ok()
+                .and_then(|body| body["contract_address"].as_str().map(String::from))
+        }
+        _ => None,
+    };
+    (
+        status,
+        JSON_CONTENT_TYPE,
+        envelope(status, &body, call.as_deref()),
+    )
+}
+
+fn dispatch(
+    method: &Method,
+    url: &str,
+    path: &str,
+    content_type: &str,
+    content: &[u8],
+    sender_addr: &str,
+) -> (u16, &'static str, String) {
+    if method.eq(&Method::Post) && path.eq("/cosmos/tx/v1beta1/txs") {
+        let (status, body) = broadcast_tx(&String::from_utf8_lossy(content));
+        return (status, JSON_CONTENT_TYPE, body);
+    }
+    if method.eq(&Method::Post) && path.eq("/wasm/code") {
+        let (status, body) = upload(content_type, content, query_param(url, "address"));
+        return (status, JSON_CONTENT_TYPE, body);
+    }
+    if method.eq(&Method::Post) && path.eq("/wasm/instantiate") {
+        let (status, body) = instantiate_msg(json_body(content_type, content), sender_addr);
+        return (status, JSON_CONTENT_TYPE, body);
+    }
+    if method.eq(&Method::Post) {
+        if let ["wasm", "code", code_id, "instantiate"] = path
+            .trim_start_matches('/')
+            .split('/')
+            .collect::<Vec<&str>>()[..]
+        {
+            let (status, body) =
+                instantiate(code_id, json_body(content_type, content), sender_addr, url);
+            return (status, JSON_CONTENT_TYPE, body);
+        }
+    }
+    if method.eq(&Method::Post) {
+        let segments: Vec<&str> = path.trim_start_matches('/').split('/').collect();
+        match segments[..] {
+            ["snapshot", name] => {
+                let (status, body) = snapshot(name, false);
+                return (status, JSON_CONTENT_TYPE, body);
+            }
+            ["snapshot", name, "restore"] => {
+                let (status, body) = snapshot(name, true);
+                return (status, JSON_CONTENT_TYPE, body);
+            }
+            _ => {}
+        }
+    }
+    // entry points also take the message as json body, which has no length limit of the url
+    if method.eq(&Method::Post) {
+        if let ["wasm", "contract", contract_addr, func] = path
+            .trim_start_matches('/')
+            .split('/')
+            .collect::<Vec<&str>>()[..]
+        {
+            let (status, body) = match ENTRY_POINTS.contains(&func) {
+                true => call_engine(
+                    contract_addr,
+                    func,
+                    json_body(content_type, content),
+                    sender_addr,
+                    url,
+                ),
+                false => (405, error_json("Method not allowed")),
+            };
+            return (status, JSON_CONTENT_TYPE, body);
+        }
+    }
+    if method.eq(&Method::Delete) {
+        let (status, body) = delete_contract(path);
+        return (status, JSON_CONTENT_TYPE, body);
+    }
+    if method.ne(&Method::Get) {
+        return (405, JSON_CONTENT_TYPE, error_json("Method not allowed"));
+    }
+
+    // json or base64 message may contain '/' so it is always the last segment
+    let segments: Vec<&str> = path.trim_start_matches('/').splitn(5, '/').collect();
+    let (status, body) = match segments.as_slice() {
+        [""] | ["index.html"] => return (200, HTML_CONTENT_TYPE, INDEX_HTML.to_string()),
+        ["wasm", "contracts"] => (200, list_contracts()),
+        ["wasm", "codes"] => (200, list_codes()),
+        ["wasm", "openapi.json"] => (200, openapi::document().to_string()),
+        ["snapshots"] => (200, list_snapshots()),
+        ["wasm", "events"] => events(url),
+        ["wasm", "contract", contract_addr, "schema"] => contract_schema(contract_addr),
+        ["wasm", "contract", contract_addr, "state"] => contract_state(contract_addr, url),
+        ["wasm", "contract", contract_addr, "state", key] => contract_state_key(contract_addr, key),
+        ["wasm", "contract", contract_addr, "proof"] => proof(contract_addr, url),
+        ["wasm", "contract", contract_addr, "raw", key] => contract_raw(contract_addr, key),
+        ["wasm", "contract", contract_addr, "query", msg]
+            if contract_addr.eq(&SIMULATOR_ADDRESS) =>
+        {
+            simulator_query(msg)
+        }
+        ["wasm", "contract", contract_addr, func, msg] => call_engine(
+            contract_addr,
+            func,
+            decode_msg(msg).map_err(|e| (400, error_json(&e))),
+            sender_addr,
+            url,
+        ),
+        _ => (404, error_json(&format!("No route for {}", path))),
+    };
+    (status, JSON_CONTENT_TYPE, body)
+}
+
+fn respond(mut request: Request, sender_addr: &str) {
+    let path = request.url().split('?').next().unwrap_or_default();
+    if request.method().eq(&Method::Get) && matches!(path, "/ws" | "/v1/ws") {
+        return stream::connect(request);
+    }
+    // uploads are binary, so the body is kept as bytes
+    let mut content = vec![];
+    let _ = request.as_reader().read_to_end(&mut content);
+    let content_type = request
+        .headers()
+        .iter()
+        .find(|header| header.field.equiv("Content-Type"))
+        .map_or(String::new(), |header| header.value.to_string());
+    let (status, content_type, body) = {
+        // the watcher waits for the request before it reloads a contract
+        let _engines = lock_engines();
+        route(
+            request.method(),
+            request.url(),
+            &content_type,
+            &content,
+            sender_addr,
+        )
+    };
+    let response = Response::from_string(body)
+        .with_status_code(status)
+        .with_header(Header::from_bytes(&b"Content-Type"[..], content_type.as_bytes()).unwrap());
+    if let Err(e) = request.respond(response) {
+        println!("REST response error: {}", e.to_string().red());
+    }
+}
+
+// serve blocks forever, it only returns false when the server can not be started
+pub fn serve(port: &str, sender_addr: &str) -> bool {
+    let server = match Server::http(format!("0.0.0.0:{}", port)) {
+        Ok(s) => s,
+        Err(e) => {
+            println!("can not start REST server: {}", e.to_string().red());
+            return false;
+        }
+    };
+    println!("REST server is listening on port {}", port.green().bold());
+
+    // single worker for easier sharing of the engines
+    for request in server.incoming_requests() {
+        respond(request, sender_addr);
+    }
+    true
+}
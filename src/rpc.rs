@@ -0,0 +1,420 @@
+// minimal tendermint rpc fed by the call history: tx_search and websocket subscribe to tx events
+use crate::contract_vm::engine::ContractInstance;
+use colored::*;
+use percent_encoding::percent_decode_str;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::sync::{mpsc, Mutex};
+use std::thread;
+use tiny_http::{Header, Method, Request, Response, Server};
+use tungstenite::handshake::derive_accept_key;
+use tungstenite::protocol::Role;
+use tungstenite::{Message, WebSocket};
+
+const DEFAULT_PER_PAGE: usize = 30;
+const MAX_PER_PAGE: usize = 100;
+
+struct Tx {
+    // tx_search item
+    result: Value,
+    // flattened events like wasm.action => [transfer] that queries match
+    events: BTreeMap<String, Vec<String>>,
+    // attributes of the wasm event, empty when the call failed
+    wasm: Vec<(String, String)>,
+}
+
+struct Subscriber {
+    id: Value,
+    query: String,
+    conditions: Vec<(String, String)>,
+    sender: mpsc::Sender<String>,
+}
+
+lazy_static! {
+    // every init and handle call as an indexed transaction
+    static ref TXS: Mutex<Vec<Tx>> = Mutex::new(vec![]);
+    static ref SUBSCRIBERS: Mutex<Vec<Subscriber>> = Mutex::new(vec![]);
+}
+
+fn event(event_type: &str, attributes: &[(String, String)]) -> Value {
+    json!({
+        "type": event_type,
+        "attributes": attributes.iter().map(|(key, value)| json!({
+            "key": base64::encode(key),
+            "value": base64::encode(value),
+        })).collect::<Vec<Value>>(),
+    })
+}
+
+// record_tx adds a call executed at height to the history and publishes it to the matching subscriptions
+pub fn record_tx(
+    engine: &ContractInstance,
+    height: u64,
+    func_type: &str,
+    msg: &str,
+    sender_addr: &str,
+    result: &str,
+) {
+    let action = match func_type {
+        "init" => "instantiate",
+        "handle" => "execute",
+        _ => return,
+    };
+    let error = serde_json::from_str::<Value>(result).ok().and_then(|r| {
+        r.get("error")
+            .map(|e| e.as_str().unwrap_or_default().to_string())
+    });
+
+    let contract_addr = engine.env.contract.address.as_str();
+    let mut txs = TXS.lock().unwrap();
+    let index = txs
+        .iter()
+        .filter(|tx| tx.result["height"].eq(&json!(height.to_string())))
+        .count();
+    let hash = hex::encode_upper(Sha256::digest(
+        format!("{}/{}/{}/{}", height, index, contract_addr, msg).as_bytes(),
+    ));
+
+    let message = vec![
+        ("action".to_string(), action.to_string()),
+        ("module".to_string(), "wasm".to_string()),
+        ("sender".to_string(), sender_addr.to_string()),
+    ];
+    let mut wasm = vec![("contract_address".to_string(), contract_addr.to_string())];
+    wasm.extend(
+        engine
+            .last_attributes
+            .iter()
+            .map(|attr| (attr.key.to_owned(), attr.value.to_owned())),
+    );
+    // failed transactions are indexed without events like on chain
+    let (code, log, tx_events) = match &error {
+        Some(e) => (1, e.to_owned(), vec![]),
+        None => (
+            0,
+            String::new(),
+            vec![("message", message), ("wasm", wasm.to_owned())],
+        ),
+    };
+
+    let mut events = BTreeMap::new();
+    events.insert("tm.event".to_string(), vec!["Tx".to_string()]);
+    events.insert("tx.hash".to_string(), vec![hash.to_owned()]);
+    events.insert("tx.height".to_string(), vec![height.to_string()]);
+    for (event_type, attributes) in tx_events.iter() {
+        for (key, value) in attributes.iter() {
+            events
+                .entry(format!("{}.{}", event_type, key))
+                .or_insert_with(Vec::new)
+                .push(value.to_owned());
+        }
+    }
+
+    let tx_result = json!({
+        "code": code,
+        "data": null,
+        "log": log,
+        "gas_used": engine.last_gas_used.to_string(),
+        "events": tx_events.iter().map(|(t, a)| event(t, a)).collect::<Vec<Value>>(),
+    });
+    let tx = Tx {
+        result: json!({
+            "hash": hash,
+            "height": height.to_string(),
+            "index": index,
+            "tx_result": tx_result,
+            "tx": base64::encode(msg),
+        }),
+        events,
+        wasm: match error {
+            Some(_) => vec![],
+            None => wasm,
+        },
+    };
+
+    let mut subscribers = SUBSCRIBERS.lock().unwrap();
+    // closed connections drop their receiver, so their subscriptions are removed here
+    subscribers.retain(|s| {
+        if !matches(&s.conditions, &tx.events) {
+            return true;
+        }
+        let data = json!({
+            "jsonrpc": "2.0",
+            "id": s.id,
+            "result": {
+                "query": s.query,
+                "data": {
+                    "type": "tendermint/event/Tx",
+                    "value": { "TxResult": {
+                        "height": tx.result["height"],
+                        "index": index,
+                        "tx": tx.result["tx"],
+                        "result": tx_result,
+                    }},
+                },
+                "events": tx.events,
+            },
+        });
+        s.sender.send(data.to_string()).is_ok()
+    });
+    txs.push(tx);
+}
+
+// search_events returns the wasm events of past calls that match every key:value filter, latest first
+pub fn search_events(
+    contract_addr: Option<&str>,
+    filters: &[(String, String)],
+    limit: usize,
+) -> Value {
+    let mut conditions: Vec<(String, String)> = filters
+        .iter()
+        .map(|(key, value)| (format!("wasm.{}", key), value.to_owned()))
+        .collect();
+    if let Some(addr) = contract_addr {
+        conditions.push(("wasm.contract_address".to_string(), addr.to_string()));
+    }
+    let txs = TXS.lock().unwrap();
+    let events: Vec<Value> = txs
+        .iter()
+        .rev()
+        .filter(|tx| !tx.wasm.is_empty() && matches(&conditions, &tx.events))
+        .take(limit)
+        .map(|tx| {
+            json!({
+                "height": tx.result["height"],
+                "hash": tx.result["hash"],
+                "contract": tx.wasm[0].1,
+                "sender": tx.events["message.sender"][0],
+                "action": tx.events["message.action"][0],
+                "attributes": tx.wasm[1..].iter().map(|(key, value)| json!({
+                    "key": key,
+                    "value": value,
+                })).collect::<Vec<Value>>(),
+            })
+        })
+        .collect();
+    json!(events)
+}
+
+// parse_query reads conditions like tm.event='Tx' AND wasm.action='transfer', only = is supported
+fn parse_query(query: &str) -> Result<Vec<(String, String)>, String> {
+    query
+        .split(" AND ")
+        .map(|condition| match condition.split_once('=') {
+            Some((key, value)) => Ok((
+                key.trim().to_string(),
+                value.trim().trim_matches('\'').to_string(),
+            )),
+            None => Err(format!("only = conditions are supported: {}", condition)),
+        })
+        .collect()
+}
+
+fn matches(conditions: &[(String, String)], events: &BTreeMap<String, Vec<String>>) -> bool {
+    conditions.iter().all(|(key, value)| {
+        events
+            .get(key)
+            .map_or(false, |values| values.contains(value))
+    })
+}
+
+fn rpc_result(id: &Value, result: Value) -> String {
+    json!({ "jsonrpc": "2.0", "id": id, "result": result }).to_string()
+}
+
+fn rpc_error(id: &Value, error: &str) -> String {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": -32603, "message": "Internal error", "data": error },
+    })
+    .to_string()
+}
+
+// param reads a string or number parameter, uri parameters keep their json quotes
+fn param(params: &Value, name: &str) -> Option<String> {
+    match params.get(name)? {
+        Value::String(s) => Some(s.trim_matches('"').to_string()),
+        Value::Null => None,
+        value => Some(value.to_string()),
+    }
+}
+
+fn tx_search(id: &Value, params: &Value) -> (u16, String) {
+    let conditions = match param(params, "query").map(|q| parse_query(&q)) {
+        Some(Ok(conditions)) => conditions,
+        Some(Err(e)) => return (400, rpc_error(id, &e)),
+        None => return (400, rpc_error(id, "query is required")),
+    };
+    let page: usize = param(params, "page")
+        .and_then(|p| p.parse().ok())
+        .unwrap_or(1)
+        .max(1);
+    let per_page: usize = param(params, "per_page")
+        .and_then(|p| p.parse().ok())
+        .unwrap_or(DEFAULT_PER_PAGE)
+        .min(MAX_PER_PAGE)
+        .max(1);
+
+    let txs = TXS.lock().unwrap();
+    let mut found: Vec<&Value> = txs
+        .iter()
+        .filter(|tx| matches(&conditions, &tx.events))
+        .map(|tx| &tx.result)
+        .collect();
+    if param(params, "order_by").map_or(false, |o| o.eq("desc")) {
+        found.reverse();
+    }
+    let total = found.len();
+    let page_txs: Vec<&Value> = found
+        .into_iter()
+        .skip((page - 1) * per_page)
+        .take(per_page)
+        .collect();
+    (
+        200,
+        rpc_result(
+            id,
+            json!({ "txs": page_txs, "total_count": total.to_string() }),
+        ),
+    )
+}
+
+fn uri_params(url: &str) -> Value {
+    let mut params = serde_json::Map::new();
+    for pair in url.splitn(2, '?').nth(1).unwrap_or_default().split('&') {
+        if let Some((key, value)) = pair.split_once('=') {
+            let value = percent_decode_str(&value.replace('+', " "))
+                .decode_utf8_lossy()
+                .to_string();
+            params.insert(key.to_string(), Value::String(value));
+        }
+    }
+    Value::Object(params)
+}
+
+fn call(method: &str, id: &Value, params: &Value) -> (u16, String) {
+    match method {
+        "tx_search" => tx_search(id, params),
+        _ => (404, rpc_error(id, &format!("Method not found: {}", method))),
+    }
+}
+
+// websocket serves one subscription per connection, events are pushed until it is closed
+fn websocket(request: Request) {
+    let key = match request
+        .headers()
+        .iter()
+        .find(|h| h.field.equiv("Sec-WebSocket-Key"))
+    {
+        Some(header) => derive_accept_key(header.value.as_bytes()),
+        None => {
+            let _ = request.respond(Response::from_string("websocket only").with_status_code(400));
+            return;
+        }
+    };
+    let response = Response::empty(101)
+        .with_header(Header::from_bytes(&b"Sec-WebSocket-Accept"[..], key.as_bytes()).unwrap());
+    let stream = request.upgrade("websocket", response);
+
+    thread::spawn(move || {
+        let mut socket = WebSocket::from_raw_socket(stream, Role::Server, None);
+        let (sender, receiver) = mpsc::channel();
+        loop {
+            let text = match socket.read_message() {
+                Ok(Message::Text(text)) => text,
+                Ok(Message::Close(_)) | Err(_) => return,
+                Ok(_) => continue,
+            };
+            let request: Value = serde_json::from_str(&text).unwrap_or_default();
+            let id = request.get("id").cloned().unwrap_or(json!(-1));
+            let params = request.get("params").cloned().unwrap_or_default();
+            let reply = match request["method"].as_str().unwrap_or_default() {
+                "subscribe" => {
+                    let query = param(&params, "query").unwrap_or_default();
+                    match parse_query(&query) {
+                        Ok(conditions) => {
+                            SUBSCRIBERS.lock().unwrap().push(Subscriber {
+                                id: id.to_owned(),
+                                query,
+                                conditions,
+                                sender: sender.clone(),
+                            });
+                            let reply = rpc_result(&id, json!({}));
+                            if socket.write_message(Message::Text(reply)).is_err() {
+                                return;
+                            }
+                            break;
+                        }
+                        Err(e) => rpc_error(&id, &e),
+                    }
+                }
+                method => call(method, &id, &params).1,
+            };
+            if socket.write_message(Message::Text(reply)).is_err() {
+                return;
+            }
+        }
+        for data in receiver {
+            if socket.write_message(Message::Text(data)).is_err() {
+                return;
+            }
+        }
+    });
+}
+
+// serve blocks forever, it only returns false when the server can not be started
+pub fn serve(port: &str) -> bool {
+    let server = match Server::http(format!("0.0.0.0:{}", port)) {
+        Ok(s) => s,
+        Err(e) => {
+            println!("can not start RPC server: {}", e.to_string().red());
+            return false;
+        }
+    };
+    println!("RPC server is listening on port {}", port.green().bold());
+
+    for mut request in server.incoming_requests() {
+        let path = request
+            .url()
+            .split('?')
+            .next()
+            .unwrap_or_default()
+            .to_string();
+        let (status, body) = match (request.method(), path.as_str()) {
+            (Method::Get, "/websocket") => {
+                websocket(request);
+                continue;
+            }
+            (Method::Get, method) => call(
+                method.trim_start_matches('/'),
+                &json!(-1),
+                &uri_params(request.url()),
+            ),
+            // json rpc
+            (Method::Post, _) => {
+                let mut content = String::new();
+                let _ = request.as_reader().read_to_string(&mut content);
+                match serde_json::from_str::<Value>(&content) {
+                    Ok(body) => call(
+                        body["method"].as_str().unwrap_or_default(),
+                        body.get("id").unwrap_or(&json!(-1)),
+                        body.get("params").unwrap_or(&Value::Null),
+                    ),
+                    Err(e) => (400, rpc_error(&json!(-1), &e.to_string())),
+                }
+            }
+            _ => (405, rpc_error(&json!(-1), "Method not allowed")),
+        };
+        let response = Response::from_string(body)
+            .with_status_code(status)
+            .with_header(
+                Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).unwrap(),
+            );
+        if let Err(e) = request.respond(response) {
+            println!("RPC response error: {}", e.to_string().red());
+        }
+    }
+    true
+}
@@ -0,0 +1,361 @@
+// embeddable simulator for integration tests, contracts run in process like in the REPL but without its frontends:
+//
+//     let mut simulator = Simulator::new();
+//     simulator.set_balance("alice", &coins(1000, "orai"));
+//     simulator.load_contract("artifacts/cw20_base.wasm", "token")?;
+//     simulator.instantiate("token", &init_msg, "alice", &[])?;
+//     simulator.execute("token", &transfer_msg, "alice", &[])?;
+//     let balance: BalanceResponse = simulator.query("token", &balance_msg)?;
+//
+// contracts call each other through plain function handlers, so the contracts, codes and balances live in a registry
+// of the thread, every test thread gets its own. Messages and queries are run by contract_vm::chain like in the
+// binary. The block height and the settings of the engine module are global
+use crate::contract_vm::chain::{self, is_error_result, Chain};
+use crate::contract_vm::engine::{ContractInstance, ROLLBACK};
+use crate::contract_vm::ibc::IbcModule;
+use crate::contract_vm::mock::{self, CustomQueryHandler, MockStorage, SpecialMsg, SpecialQuery};
+use crate::contract_vm::querier::{BankQuerier, GovQuerier};
+use crate::contract_vm::receipt::{MessageReceipt, Receipt};
+use cosmwasm_std::testing::MockQuerierCustomHandlerResult;
+use cosmwasm_std::{Coin, CosmosMsg, HumanAddr, MessageInfo, QuerierResult, WasmQuery};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::cell::UnsafeCell;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+#[derive(Default)]
+struct Registry {
+    contracts: BTreeMap<String, ContractInstance>,
+    // wasm files by code id, code id is the load order starting from 1
+    codes: Vec<String>,
+    bank: BankQuerier,
+    gov: GovQuerier,
+    ibc: IbcModule,
+}
+
+thread_local! {
+    static REGISTRY: UnsafeCell<Registry> = UnsafeCell::new(Registry::default());
+}
+
+// contracts are reentered while they run, like the config of the binary the registry is handed out mutably
+fn registry() -> &'static mut Registry {
+    REGISTRY.with(|registry| unsafe { &mut *registry.get() })
+}
+
+impl Chain for Registry {
+    fn engine(&mut self, contract_addr: &str) -> Option<&mut ContractInstance> {
+        self.contracts.get_mut(contract_addr)
+    }
+
+    fn engines(&mut self) -> Vec<&mut ContractInstance> {
+        self.contracts.values_mut().collect()
+    }
+
+    // the registry keeps the balances, they exist before any contract is loaded
+    fn bank(&mut self) -> BankQuerier {
+        self.bank.clone()
+    }
+
+    fn set_bank(&mut self, bank: BankQuerier) {
+        for engine in self.contracts.values_mut() {
+            let _ = engine.with_querier(|querier| {
+                querier.set_bank(bank.clone());
+                Ok(())
+            });
+        }
+        self.bank = bank;
+    }
+
+    fn code(&self, code_id: u64) -> Option<String> {
+        self.codes.get((code_id as usize).wrapping_sub(1)).cloned()
+    }
+
+    // instance_address is the label, or the file stem with the first free index
+    fn instance_address(&mut self, wasm_file: &str, label: Option<&str>) -> String {
+        if let Some(label) = label.filter(|label| !self.contracts.contains_key(*label)) {
+            return label.to_string();
+        }
+        let stem = Path::new(wasm_file)
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or("contract");
+        let mut index = 1;
+        while self.contracts.contains_key(&format!("{}_{}", stem, index)) {
+            index += 1;
+        }
+        format!("{}_{}", stem, index)
+    }
+
+    fn load(&mut self, wasm_file: &str, contract_addr: &str) -> Result<(), String> {
+        let mut engine = ContractInstance::new_instance(
+            wasm_file,
+            contract_addr,
+            query_wasm,
+            query_special,
+            &MockStorage::default(),
+            dispatch,
+        )?;
+        let _ = engine.with_querier(|querier| {
+            querier.set_bank(self.bank.clone());
+            Ok(())
+        });
+        self.contracts.insert(contract_addr.to_string(), engine);
+        Ok(())
+    }
+
+    fn remove(&mut self, contract_addr: &str) {
+        self.contracts.remove(contract_addr);
+    }
+
+    fn gov(&mut self) -> &mut GovQuerier {
+        &mut self.gov
+    }
+
+    fn ibc(&mut self) -> &mut IbcModule {
+        &mut self.ibc
+    }
+}
+
+fn query_wasm(request: &WasmQuery) -> QuerierResult {
+    chain::query_wasm(registry(), request)
+}
+
+fn query_special(request: &SpecialQuery) -> MockQuerierCustomHandlerResult {
+    chain::query_special(registry(), request)
+}
+
+fn dispatch(sender_addr: &str, messages: Vec<CosmosMsg<SpecialMsg>>) -> Vec<MessageReceipt> {
+    chain::dispatch(registry(), sender_addr, messages)
+}
+
+// Simulator loads contracts and calls them like the REPL, call results are the json the REPL prints and failed calls
+// return it as error. Only one simulator of a thread has contracts, creating another one drops them
+pub struct Simulator;
+
+impl Default for Simulator {
+    fn default() -> Self {
+        Simulator::new()
+    }
+}
+
+impl Simulator {
+    pub fn new() -> Self {
+        *registry() = Registry::default();
+        Simulator
+    }
+
+    // load_contract compiles a wasm file and adds it with empty storage at the address, its schema is read from the
+    // schema folder next to it like in the binary. The file becomes a code that contracts can instantiate, code ids
+    // follow the order files are first loaded in
+    pub fn load_contract(&mut self, wasm_file: &str, contract_addr: &str) -> Result<(), String> {
+        let registry = registry();
+        if registry.contracts.contains_key(contract_addr) {
+            return Err(format!("contract {} already loaded", contract_addr));
+        }
+        registry.load(wasm_file, contract_addr)?;
+        if !registry.codes.iter().any(|code| code.eq(wasm_file)) {
+            registry.codes.push(wasm_file.to_string());
+        }
+        Ok(())
+    }
+
+    pub fn contracts(&self) -> Vec<String> {
+        registry().contracts.keys().cloned().collect()
+    }
+
+    // code_id returns the code id of a loaded wasm file
+    pub fn code_id(&self, wasm_file: &str) -> Option<u64> {
+        registry()
+            .codes
+            .iter()
+            .position(|code| code.eq(wasm_file))
+            .map(|index| index as u64 + 1)
+    }
+
+    // set_balance replaces the balance of an account or contract
+    pub fn set_balance(&mut self, addr: &str, amount: &[Coin]) {
+        let addr = HumanAddr::from(addr);
+        let _ = chain::update_bank(registry(), |bank| {
+            for coin in bank.balance(&addr) {
+                bank.debit(&addr, &coin)?;
+            }
+            for coin in amount {
+                bank.credit(&addr, coin);
+            }
+            Ok(())
+        });
+    }
+
+    // add_query_handler answers the custom queries of a chain module, handlers are global like the engine settings
+    pub fn add_query_handler<H: CustomQueryHandler + 'static>(&mut self, handler: H) {
+        mock::register_custom_query_handler(Box::new(handler));
+    }
+
+    pub fn balance(&self, addr: &str) -> Vec<Coin> {
+        registry().bank.balance(&HumanAddr::from(addr))
+    }
+
+    pub fn instantiate<T: Serialize>(
+        &mut self,
+        contract_addr: &str,
+        msg: &T,
+        sender: &str,
+        funds: &[Coin],
+    ) -> Result<Receipt, String> {
+        self.call(contract_addr, "init", msg, sender, funds)
+    }
+
+    pub fn execute<T: Serialize>(
+        &mut self,
+        contract_addr: &str,
+        msg: &T,
+        sender: &str,
+        funds: &[Coin],
+    ) -> Result<Receipt, String> {
+        self.call(contract_addr, "handle", msg, sender, funds)
+    }
+
+    pub fn migrate<T: Serialize>(
+        &mut self,
+        contract_addr: &str,
+        msg: &T,
+        sender: &str,
+    ) -> Result<Receipt, String> {
+        self.call(contract_addr, "migrate", msg, sender, &[])
+    }
+
+    // instantiate_code creates a new instance of a loaded code at the label or at the file stem with an index, like
+    // WasmMsg::Instantiate does. Returns the address of the new contract with the receipt of its init
+    pub fn instantiate_code<T: Serialize>(
+        &mut self,
+        code_id: u64,
+        label: Option<&str>,
+        msg: &T,
+        sender: &str,
+        funds: &[Coin],
+    ) -> Result<(String, Receipt), String> {
+        let msg = serde_json::to_string(msg).map_err(|e| e.to_string())?;
+        let snapshot = chain::snapshot(registry());
+        let info = MessageInfo {
+            sender: HumanAddr::from(sender),
+            sent_funds: funds.to_vec(),
+        };
+        let mut receipt = None;
+        let (contract_addr, result) =
+            chain::instantiate(registry(), code_id, label, |registry, contract_addr| {
+                if let Err(e) =
+                    chain::transfer(registry, sender, &HumanAddr::from(contract_addr), funds)
+                {
+                    return serde_json::json!({ "error": e }).to_string();
+                }
+                let init = registry
+                    .contracts
+                    .get_mut(contract_addr)
+                    .unwrap()
+                    .call("init", &msg, &info);
+                let result = init.result.to_owned();
+                receipt = Some(init);
+                result
+            })?;
+        match receipt {
+            Some(receipt) if !is_error_result(&result) => Ok((contract_addr, receipt)),
+            _ => {
+                if unsafe { ROLLBACK } {
+                    chain::revert(registry(), snapshot);
+                }
+                Err(result)
+            }
+        }
+    }
+
+    // query parses the query data as the response type
+    pub fn query<T: Serialize, R: DeserializeOwned>(
+        &mut self,
+        contract_addr: &str,
+        msg: &T,
+    ) -> Result<R, String> {
+        let receipt = self.call(contract_addr, "query", msg, "", &[])?;
+        serde_json::from_str(&receipt.result)
+            .map_err(|e| format!("can not parse query response {}: {}", receipt.result, e))
+    }
+
+    // state returns the storage of a contract
+    pub fn state(&mut self, contract_addr: &str) -> Result<BTreeMap<Vec<u8>, Vec<u8>>, String> {
+        let engine = registry()
+            .contracts
+            .get_mut(contract_addr)
+            .ok_or_else(|| format!("No such contract: {}", contract_addr))?;
+        engine
+            .with_storage(|storage| Ok(storage.data.clone()))
+            .map_err(|e| e.to_string())
+    }
+
+    pub fn set_state(
+        &mut self,
+        contract_addr: &str,
+        key: &[u8],
+        value: Option<&[u8]>,
+    ) -> Result<(), String> {
+        let engine = registry()
+            .contracts
+            .get_mut(contract_addr)
+            .ok_or_else(|| format!("No such contract: {}", contract_addr))?;
+        engine
+            .with_storage(|storage| {
+                match value {
+                    Some(value) => storage.data.insert(key.to_vec(), value.to_vec()),
+                    None => storage.data.remove(key),
+                };
+                Ok(())
+            })
+            .map_err(|e| e.to_string())
+    }
+
+    // call moves the funds to the contract and runs the entry point, a failed call is reverted like a transaction
+    fn call<T: Serialize>(
+        &mut self,
+        contract_addr: &str,
+        func_type: &str,
+        msg: &T,
+        sender: &str,
+        funds: &[Coin],
+    ) -> Result<Receipt, String> {
+        let msg = serde_json::to_string(msg).map_err(|e| e.to_string())?;
+        if !registry().contracts.contains_key(contract_addr) {
+            return Err(format!("No such contract: {}", contract_addr));
+        }
+        let snapshot = match func_type.eq("query") {
+            true => None,
+            false => Some(chain::snapshot(registry())),
+        };
+        if func_type.ne("query") {
+            chain::transfer(registry(), sender, &HumanAddr::from(contract_addr), funds)?;
+        }
+        let info = MessageInfo {
+            sender: HumanAddr::from(sender),
+            sent_funds: funds.to_vec(),
+        };
+        let receipt = registry()
+            .contracts
+            .get_mut(contract_addr)
+            .unwrap()
+            .call(func_type, &msg, &info);
+        // query data is not wrapped in a json object
+        if func_type.eq("query") {
+            return match serde_json::from_str::<serde_json::Value>(&receipt.result) {
+                Ok(result) if result.get("error").is_some() && result.get("code").is_some() => {
+                    Err(receipt.result)
+                }
+                _ => Ok(receipt),
+            };
+        }
+        if is_error_result(&receipt.result) {
+            if let Some(snapshot) = snapshot.filter(|_| unsafe { ROLLBACK }) {
+                chain::revert(registry(), snapshot);
+            }
+            return Err(receipt.result);
+        }
+        Ok(receipt)
+    }
+}
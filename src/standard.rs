@@ -0,0 +1,63 @@
+// prebuilt cw-plus contracts, downloaded on first use and cached in the home folder
+use crate::{deploy_wasm, free_address};
+use colored::*;
+use std::io::Read;
+use std::path::PathBuf;
+
+// last cw-plus release built against cosmwasm 0.13
+const CW_PLUS_VERSION: &str = "v0.5.0";
+pub const STANDARD_CONTRACTS: &[&str] = &["cw20-base", "cw721-base", "cw1-whitelist", "cw4-group"];
+
+// cache_dir is $COSMWASM_SIMULATE_CACHE or ~/.cosmwasm-simulate/standard/<version>
+fn cache_dir() -> PathBuf {
+    match std::env::var("COSMWASM_SIMULATE_CACHE") {
+        Ok(dir) => PathBuf::from(dir),
+        Err(_) => PathBuf::from(std::env::var("HOME").unwrap_or_else(|_| ".".to_string()))
+            .join(".cosmwasm-simulate")
+            .join("standard"),
+    }
+    .join(CW_PLUS_VERSION)
+}
+
+fn download(url: &str, file: &PathBuf) -> Result<(), String> {
+    let response = ureq::get(url).call().map_err(|e| e.to_string())?;
+    let mut data = vec![];
+    response
+        .into_reader()
+        .read_to_end(&mut data)
+        .map_err(|e| e.to_string())?;
+    std::fs::create_dir_all(cache_dir()).map_err(|e| e.to_string())?;
+    std::fs::write(file, data).map_err(|e| e.to_string())
+}
+
+// wasm_file returns the cached build of a standard contract, downloading it when missing
+pub fn wasm_file(name: &str) -> Result<String, String> {
+    if !STANDARD_CONTRACTS.contains(&name) {
+        return Err(format!(
+            "unknown standard contract {}, must be one of {}",
+            name,
+            STANDARD_CONTRACTS.join(" | ")
+        ));
+    }
+    // release artifacts are named after the crate, cw20-base => cw20_base.wasm
+    let file_name = format!("{}.wasm", name.replace('-', "_"));
+    let file = cache_dir().join(&file_name);
+    if !file.is_file() {
+        let url = format!(
+            "https://github.com/CosmWasm/cw-plus/releases/download/{}/{}",
+            CW_PLUS_VERSION, file_name
+        );
+        println!("downloading {}", url.blue().bold());
+        download(&url, &file).map_err(|e| format!("can not download {}: {}", name, e))?;
+    }
+    Ok(file.to_string_lossy().to_string())
+}
+
+// load registers a standard contract as a new code and loads it at its name, or name_1, name_2... when taken,
+// returns the address and the code id
+pub fn load(name: &str) -> Result<(String, usize), String> {
+    let wasm_file = wasm_file(name)?;
+    let contract_addr = free_address(name);
+    let code_id = deploy_wasm(&wasm_file, &contract_addr)?;
+    Ok((contract_addr, code_id))
+}
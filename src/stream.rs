@@ -0,0 +1,141 @@
+// live feed of the calls at /ws of the REST server, every websocket client gets the call results, events and storage
+// changes as json frames once they happen, with --output json they are also written to stdout as json lines
+use colored::*;
+use nix::unistd::{dup, dup2};
+use serde_json::{json, Value};
+use std::fs::File;
+use std::io::Write;
+use std::os::unix::io::FromRawFd;
+use std::sync::{mpsc, Mutex};
+use std::thread;
+use tiny_http::{Header, Request, Response};
+use tungstenite::handshake::derive_accept_key;
+use tungstenite::protocol::Role;
+use tungstenite::{Message, WebSocket};
+
+use crate::contract_vm::receipt::Receipt;
+use crate::contract_vm::watcher::storage_key_name;
+
+lazy_static! {
+    static ref CLIENTS: Mutex<Vec<mpsc::Sender<String>>> = Mutex::new(vec![]);
+    // stdout of --output json, what is printed for humans goes to stderr instead so the json lines can be parsed
+    static ref JSON_OUTPUT: Mutex<Option<File>> = Mutex::new(None);
+}
+
+// json_output keeps stdout for the frames and points the file descriptor of println at stderr
+pub fn json_output() -> Result<(), String> {
+    let _ = std::io::stdout().flush();
+    let stdout = dup(1).map_err(|e| e.to_string())?;
+    dup2(2, 1).map_err(|e| e.to_string())?;
+    *JSON_OUTPUT.lock().unwrap() = Some(unsafe { File::from_raw_fd(stdout) });
+    Ok(())
+}
+
+// listening tells if any client is connected or json is written, so calls only collect frames for someone
+pub fn listening() -> bool {
+    !CLIENTS.lock().unwrap().is_empty() || JSON_OUTPUT.lock().unwrap().is_some()
+}
+
+// publish sends a frame to every client, closed connections drop their receiver and are removed here
+fn publish(frame: Value) {
+    let text = frame.to_string();
+    if let Some(output) = JSON_OUTPUT.lock().unwrap().as_mut() {
+        // one write per line under the lock, so lines of other threads do not interleave
+        let _ = output.write_all(format!("{}\n", text).as_bytes());
+    }
+    CLIENTS
+        .lock()
+        .unwrap()
+        .retain(|client| client.send(text.to_owned()).is_ok());
+}
+
+// results and messages are json strings, they are embedded as json when they parse
+fn as_json(text: &str) -> Value {
+    serde_json::from_str(text).unwrap_or_else(|_| Value::String(text.to_string()))
+}
+
+// publish_error sends the error of a call, also of calls rejected before they reach the contract
+pub fn publish_error(contract_addr: &str, call_type: &str, error: &Value, code: Option<&Value>) {
+    publish(json!({
+        "type": "error",
+        "contract": contract_addr,
+        "call_type": call_type,
+        "error": error,
+        "code": code,
+    }));
+}
+
+// publish_call sends the frame of a finished call followed by its error, the events of its receipt and the storage
+// it changed: {"type": "call", "contract", "call_type", "sender", "msg", "result", "height", "gas_used"},
+// {"type": "error", "contract", "call_type", "error", "code"}, {"type": "event", "contract", "key", "value"} and
+// {"type": "storage", "contract", "key", "key_name", "value"} where the value is null when the key was removed
+pub fn publish_call(
+    receipt: &Receipt,
+    call_type: &str,
+    sender_addr: &str,
+    msg: &str,
+    height: u64,
+    changes: &[(String, Vec<u8>, Option<Vec<u8>>)],
+) {
+    publish(json!({
+        "type": "call",
+        "contract": receipt.contract,
+        "call_type": call_type,
+        "sender": sender_addr,
+        "msg": as_json(msg),
+        "result": as_json(&receipt.result),
+        "height": height,
+        "gas_used": receipt.gas_used,
+    }));
+    let result = as_json(&receipt.result);
+    if let Some(error) = result.get("error") {
+        publish_error(&receipt.contract, call_type, error, result.get("code"));
+    }
+    for event in receipt.events.iter() {
+        publish(json!({
+            "type": "event",
+            "contract": event.contract,
+            "key": event.key,
+            "value": event.value,
+        }));
+    }
+    for (contract_addr, key, value) in changes {
+        publish(json!({
+            "type": "storage",
+            "contract": contract_addr,
+            "key": base64::encode(key),
+            "key_name": storage_key_name(key),
+            "value": value.as_ref().map(base64::encode),
+        }));
+    }
+}
+
+// connect upgrades a request of /ws, the client only receives, frames it sends are ignored
+pub fn connect(request: Request) {
+    let key = match request
+        .headers()
+        .iter()
+        .find(|h| h.field.equiv("Sec-WebSocket-Key"))
+    {
+        Some(header) => derive_accept_key(header.value.as_bytes()),
+        None => {
+            let _ = request.respond(Response::from_string("websocket only").with_status_code(400));
+            return;
+        }
+    };
+    let response = Response::empty(101)
+        .with_header(Header::from_bytes(&b"Sec-WebSocket-Accept"[..], key.as_bytes()).unwrap());
+    let stream = request.upgrade("websocket", response);
+    let (sender, receiver) = mpsc::channel();
+    CLIENTS.lock().unwrap().push(sender);
+    println!("{}", "websocket client connected to /ws".green());
+
+    thread::spawn(move || {
+        let mut socket = WebSocket::from_raw_socket(stream, Role::Server, None);
+        for frame in receiver {
+            if socket.write_message(Message::Text(frame)).is_err() {
+                return;
+            }
+        }
+    });
+}
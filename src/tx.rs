@@ -0,0 +1,545 @@
+// decoding and execution of protobuf encoded cosmos transactions with wasm messages
+use crate::contract_vm::engine::{restore_block, save_block, BECH32_PREFIX, CHAIN_ID};
+use crate::{
+    call_contract, error_json, get_engine, instantiate_engine, is_error_result, revert_state,
+    save_storage, snapshot_state,
+};
+use bech32::{ToBase32, Variant};
+use cosmwasm_std::{Coin, HumanAddr, MessageInfo, Uint128};
+use k256::ecdsa::signature::Verifier;
+use k256::ecdsa::{Signature, VerifyingKey};
+use ripemd160::Ripemd160;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::convert::TryFrom;
+
+const EXECUTE_TYPES: &[&str] = &[
+    "/cosmwasm.wasm.v1.MsgExecuteContract",
+    "/cosmwasm.wasm.v1beta1.MsgExecuteContract",
+];
+const INSTANTIATE_TYPES: &[&str] = &[
+    "/cosmwasm.wasm.v1.MsgInstantiateContract",
+    "/cosmwasm.wasm.v1beta1.MsgInstantiateContract",
+];
+const SECP256K1_PUBKEY_TYPE: &str = "/cosmos.crypto.secp256k1.PubKey";
+
+enum Field<'a> {
+    Varint(u64),
+    Bytes(&'a [u8]),
+}
+
+fn read_varint(data: &[u8], pos: &mut usize) -> Result<u64, String> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let byte = *data.get(*pos).ok_or("truncated varint")?;
+        *pos += 1;
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err("varint too long".to_string())
+}
+
+// fields reads the varint and length delimited fields of a protobuf message, fixed size fields are skipped
+fn fields(data: &[u8]) -> Result<Vec<(u64, Field<'_>)>, String> {
+    let mut pos = 0;
+    let mut result = vec![];
+    while pos < data.len() {
+        let key = read_varint(data, &mut pos)?;
+        let field = match key & 7 {
+            0 => Field::Varint(read_varint(data, &mut pos)?),
+            1 => {
+                pos += 8;
+                continue;
+            }
+            2 => {
+                let len = read_varint(data, &mut pos)? as usize;
+                // a crafted length must not overflow the position
+                let end = pos
+                    .checked_add(len)
+                    .ok_or("length delimited field too long")?;
+                let bytes = data
+                    .get(pos..end)
+                    .ok_or("truncated length delimited field")?;
+                pos = end;
+                Field::Bytes(bytes)
+            }
+            5 => {
+                pos += 4;
+                continue;
+            }
+            wire_type => return Err(format!("unsupported wire type {}", wire_type)),
+        };
+        result.push((key >> 3, field));
+    }
+    Ok(result)
+}
+
+fn bytes_of<'a>(fields: &[(u64, Field<'a>)], number: u64) -> Vec<&'a [u8]> {
+    fields
+        .iter()
+        .filter_map(|(n, field)| match field {
+            Field::Bytes(bytes) if *n == number => Some(*bytes),
+            _ => None,
+        })
+        .collect()
+}
+
+fn first_bytes<'a>(fields: &[(u64, Field<'a>)], number: u64) -> &'a [u8] {
+    bytes_of(fields, number)
+        .first()
+        .cloned()
+        .unwrap_or_default()
+}
+
+fn string_of(fields: &[(u64, Field)], number: u64) -> Result<String, String> {
+    String::from_utf8(first_bytes(fields, number).to_vec()).map_err(|e| e.to_string())
+}
+
+fn varint_of(fields: &[(u64, Field)], number: u64) -> u64 {
+    fields
+        .iter()
+        .find_map(|(n, field)| match field {
+            Field::Varint(value) if *n == number => Some(*value),
+            _ => None,
+        })
+        .unwrap_or_default()
+}
+
+fn coins_of(fields: &[(u64, Field)], number: u64) -> Result<Vec<Coin>, String> {
+    bytes_of(fields, number)
+        .into_iter()
+        .map(|coin| {
+            let coin = self::fields(coin)?;
+            let amount = string_of(&coin, 2)?;
+            Ok(Coin {
+                denom: string_of(&coin, 1)?,
+                amount: Uint128::from(
+                    amount
+                        .parse::<u128>()
+                        .map_err(|_| format!("wrong amount {}", amount))?,
+                ),
+            })
+        })
+        .collect()
+}
+
+enum ContractMsg {
+    Execute {
+        sender: String,
+        contract: String,
+        msg: Vec<u8>,
+        funds: Vec<Coin>,
+    },
+    Instantiate {
+        sender: String,
+        code_id: u64,
+        msg: Vec<u8>,
+        funds: Vec<Coin>,
+    },
+}
+
+impl ContractMsg {
+    fn sender(&self) -> &str {
+        match self {
+            ContractMsg::Execute { sender, .. } | ContractMsg::Instantiate { sender, .. } => sender,
+        }
+    }
+}
+
+struct DecodedTx<'a> {
+    body_bytes: &'a [u8],
+    auth_info_bytes: &'a [u8],
+    signatures: Vec<&'a [u8]>,
+    messages: Vec<ContractMsg>,
+}
+
+// decode reads a TxRaw, which is also the wire format of Tx, only wasm execute and instantiate messages are supported
+fn decode(tx_bytes: &[u8]) -> Result<DecodedTx<'_>, String> {
+    let tx = fields(tx_bytes)?;
+    let body_bytes = first_bytes(&tx, 1);
+    let body = fields(body_bytes)?;
+
+    let mut messages = vec![];
+    for any in bytes_of(&body, 1) {
+        let any = fields(any)?;
+        let type_url = string_of(&any, 1)?;
+        let msg = fields(first_bytes(&any, 2))?;
+        if EXECUTE_TYPES.contains(&type_url.as_str()) {
+            messages.push(ContractMsg::Execute {
+                sender: string_of(&msg, 1)?,
+                contract: string_of(&msg, 2)?,
+                msg: first_bytes(&msg, 3).to_vec(),
+                funds: coins_of(&msg, 5)?,
+            });
+        } else if INSTANTIATE_TYPES.contains(&type_url.as_str()) {
+            messages.push(ContractMsg::Instantiate {
+                sender: string_of(&msg, 1)?,
+                code_id: varint_of(&msg, 3),
+                msg: first_bytes(&msg, 5).to_vec(),
+                funds: coins_of(&msg, 6)?,
+            });
+        } else {
+            return Err(format!("unsupported message type {}", type_url));
+        }
+    }
+    if messages.is_empty() {
+        return Err("transaction has no messages".to_string());
+    }
+
+    Ok(DecodedTx {
+        body_bytes,
+        auth_info_bytes: first_bytes(&tx, 2),
+        signatures: bytes_of(&tx, 3),
+        messages,
+    })
+}
+
+// address_of derives the bech32 account address of a compressed secp256k1 public key
+fn address_of(pubkey: &[u8]) -> String {
+    let hash = Ripemd160::digest(&Sha256::digest(pubkey));
+    bech32::encode(BECH32_PREFIX, hash.to_base32(), Variant::Bech32).unwrap_or_default()
+}
+
+// verify checks the direct mode signature of every signer over the sign doc, every message must be sent by one
+// of the signers
+fn verify(tx: &DecodedTx, account_number: u64) -> Result<(), String> {
+    let signer_infos = bytes_of(&fields(tx.auth_info_bytes)?, 1);
+    if signer_infos.is_empty() || signer_infos.len() != tx.signatures.len() {
+        return Err("signatures do not match signers".to_string());
+    }
+
+    // SignDoc { body_bytes, auth_info_bytes, chain_id, account_number }
+    let mut sign_doc = vec![];
+    for &(number, bytes) in &[
+        (1u8, tx.body_bytes),
+        (2, tx.auth_info_bytes),
+        (3, unsafe { CHAIN_ID }.as_bytes()),
+    ] {
+        sign_doc.push(number << 3 | 2);
+        write_varint(&mut sign_doc, bytes.len() as u64);
+        sign_doc.extend_from_slice(bytes);
+    }
+    if account_number > 0 {
+        sign_doc.push(4 << 3);
+        write_varint(&mut sign_doc, account_number);
+    }
+
+    let mut signers = vec![];
+    for (signer_info, signature) in signer_infos.iter().zip(tx.signatures.iter()) {
+        let public_key = fields(first_bytes(&fields(signer_info)?, 1))?;
+        let type_url = string_of(&public_key, 1)?;
+        if type_url.ne(SECP256K1_PUBKEY_TYPE) {
+            return Err(format!("unsupported public key type {}", type_url));
+        }
+        let key = first_bytes(&fields(first_bytes(&public_key, 2))?, 1);
+        let verifying_key = VerifyingKey::from_sec1_bytes(key).map_err(|e| e.to_string())?;
+        let signature = Signature::try_from(*signature).map_err(|e| e.to_string())?;
+        verifying_key
+            .verify(&sign_doc, &signature)
+            .map_err(|_| format!("signature verification failed for {}", address_of(key)))?;
+        signers.push(address_of(key));
+    }
+    match tx
+        .messages
+        .iter()
+        .find(|msg| !signers.iter().any(|signer| signer.eq(msg.sender())))
+    {
+        Some(msg) => Err(format!("sender {} did not sign the tx", msg.sender())),
+        None => Ok(()),
+    }
+}
+
+fn write_varint(data: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        data.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    data.push(value as u8);
+}
+
+// execute runs the messages in order like one transaction, the first failure reverts the state and the block of
+// the earlier messages
+pub fn execute(tx_bytes: &[u8], verify_with: Option<u64>) -> (u16, String) {
+    let tx = match decode(tx_bytes) {
+        Ok(tx) => tx,
+        Err(e) => return (400, error_json(&format!("can not decode tx: {}", e))),
+    };
+    if let Some(account_number) = verify_with {
+        if let Err(e) = verify(&tx, account_number) {
+            return (401, error_json(&e));
+        }
+    }
+
+    let snapshot = snapshot_state();
+    let block = save_block();
+    let mut results: Vec<Value> = vec![];
+    let mut raw_log = String::new();
+    for msg in tx.messages.iter() {
+        let result = match msg {
+            ContractMsg::Execute {
+                sender,
+                contract,
+                msg,
+                funds,
+            } => match get_engine(contract) {
+                None => error_json(&format!("No such contract: {}", contract)),
+                Some(engine) => call_contract(
+                    engine,
+                    "handle",
+                    &String::from_utf8_lossy(msg),
+                    &MessageInfo {
+                        sender: HumanAddr::from(sender.as_str()),
+                        sent_funds: funds.to_vec(),
+                    },
+                ),
+            },
+            ContractMsg::Instantiate {
+                sender,
+                code_id,
+                msg,
+                funds,
+            } => {
+                let (contract_addr, result) = instantiate_engine(*code_id, sender, msg, funds);
+                match serde_json::from_str::<Value>(&result) {
+                    Ok(value) if value.get("error").is_none() => {
+                        json!({ "contract_address": contract_addr, "result": value }).to_string()
+                    }
+                    Ok(_) => result,
+                    Err(_) => error_json(&result),
+                }
+            }
+        };
+        let failed = is_error_result(&result);
+        results.push(serde_json::from_str(&result).unwrap_or(Value::String(result)));
+        if failed {
+            raw_log = format!("message {} failed", results.len() - 1);
+            break;
+        }
+    }
+    if !raw_log.is_empty() {
+        revert_state(snapshot);
+        restore_block(block);
+        save_storage();
+    }
+
+    let response = json!({
+        "tx_response": {
+            "txhash": hex::encode_upper(Sha256::digest(tx_bytes)),
+            "code": if raw_log.is_empty() { 0 } else { 1 },
+            "raw_log": raw_log,
+            "results": results,
+        }
+    });
+    (200, response.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bech32::FromBase32;
+    use k256::ecdsa::signature::Signer;
+    use k256::ecdsa::SigningKey;
+
+    fn field(out: &mut Vec<u8>, number: u64, bytes: &[u8]) {
+        write_varint(out, number << 3 | 2);
+        write_varint(out, bytes.len() as u64);
+        out.extend_from_slice(bytes);
+    }
+
+    fn any(type_url: &str, value: &[u8]) -> Vec<u8> {
+        let mut any = vec![];
+        field(&mut any, 1, type_url.as_bytes());
+        field(&mut any, 2, value);
+        any
+    }
+
+    fn coin(denom: &str, amount: &str) -> Vec<u8> {
+        let mut coin = vec![];
+        field(&mut coin, 1, denom.as_bytes());
+        field(&mut coin, 2, amount.as_bytes());
+        coin
+    }
+
+    fn execute_msg(sender: &str, contract: &str, msg: &str) -> Vec<u8> {
+        let mut execute = vec![];
+        field(&mut execute, 1, sender.as_bytes());
+        field(&mut execute, 2, contract.as_bytes());
+        field(&mut execute, 3, msg.as_bytes());
+        field(&mut execute, 5, &coin("orai", "100"));
+        any(EXECUTE_TYPES[0], &execute)
+    }
+
+    fn body(messages: &[Vec<u8>]) -> Vec<u8> {
+        let mut body = vec![];
+        for msg in messages {
+            field(&mut body, 1, msg);
+        }
+        body
+    }
+
+    // auth_info has one signer info with the secp256k1 public key of the key
+    fn auth_info(key: &SigningKey) -> Vec<u8> {
+        let mut public_key = vec![];
+        field(&mut public_key, 1, &key.verifying_key().to_bytes());
+        let mut signer_info = vec![];
+        field(
+            &mut signer_info,
+            1,
+            &any(SECP256K1_PUBKEY_TYPE, &public_key),
+        );
+        let mut auth_info = vec![];
+        field(&mut auth_info, 1, &signer_info);
+        auth_info
+    }
+
+    fn tx_raw(body: &[u8], auth_info: &[u8], signatures: &[Vec<u8>]) -> Vec<u8> {
+        let mut tx = vec![];
+        field(&mut tx, 1, body);
+        field(&mut tx, 2, auth_info);
+        for signature in signatures {
+            field(&mut tx, 3, signature);
+        }
+        tx
+    }
+
+    // signed signs the sign doc of direct mode like a wallet
+    fn signed(body: &[u8], key: &SigningKey, account_number: u64) -> Vec<u8> {
+        let auth_info = auth_info(key);
+        let mut sign_doc = vec![];
+        field(&mut sign_doc, 1, body);
+        field(&mut sign_doc, 2, &auth_info);
+        field(&mut sign_doc, 3, unsafe { CHAIN_ID }.as_bytes());
+        if account_number > 0 {
+            write_varint(&mut sign_doc, 4 << 3);
+            write_varint(&mut sign_doc, account_number);
+        }
+        let signature: Signature = key.sign(&sign_doc);
+        tx_raw(body, &auth_info, &[signature.as_ref().to_vec()])
+    }
+
+    fn key(byte: u8) -> SigningKey {
+        SigningKey::from_bytes(&[byte; 32]).unwrap()
+    }
+
+    fn address(key: &SigningKey) -> String {
+        address_of(&key.verifying_key().to_bytes())
+    }
+
+    #[test]
+    fn decodes_fixture() {
+        // TxRaw with a MsgExecuteContract of 100orai and a MsgInstantiateContract of code 2, unsigned
+        let tx_bytes = hex::decode(concat!(
+            "0ab0010a5b0a242f636f736d7761736d2e7761736d2e76312e4d736745786563757465436f6e7472616374",
+            "12330a0b6f7261693173656e6465721205746f6b656e1a107b22696e6372656d656e74223a7b7d7d2a0b0a",
+            "046f72616912033130300a510a282f636f736d7761736d2e7761736d2e76312e4d7367496e7374616e7469",
+            "617465436f6e747261637412250a0b6f7261693173656e64657218022207636f756e7465722a0b7b22636f",
+            "756e74223a317d1200"
+        ))
+        .unwrap();
+        let tx = decode(&tx_bytes).unwrap();
+        assert!(tx.signatures.is_empty() && tx.auth_info_bytes.is_empty());
+        assert_eq!(tx.messages.len(), 2);
+        match &tx.messages[0] {
+            ContractMsg::Execute {
+                sender,
+                contract,
+                msg,
+                funds,
+            } => {
+                assert_eq!(
+                    (sender.as_str(), contract.as_str()),
+                    ("orai1sender", "token")
+                );
+                assert_eq!(msg.as_slice(), br#"{"increment":{}}"#);
+                assert_eq!(
+                    funds,
+                    &vec![Coin {
+                        denom: "orai".to_string(),
+                        amount: Uint128::from(100u128),
+                    }]
+                );
+            }
+            _ => panic!("first message is not an execute"),
+        }
+        match &tx.messages[1] {
+            ContractMsg::Instantiate {
+                sender,
+                code_id,
+                msg,
+                funds,
+            } => {
+                assert_eq!((sender.as_str(), *code_id), ("orai1sender", 2));
+                assert_eq!(msg.as_slice(), br#"{"count":1}"#);
+                assert!(funds.is_empty());
+            }
+            _ => panic!("second message is not an instantiate"),
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_tx() {
+        // length far past the end, and one that overflows the position
+        assert!(fields(&[0x0a, 0x05, 0x01]).is_err());
+        assert!(
+            fields(&[0x0a, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01]).is_err()
+        );
+        assert!(fields(&[0x80]).is_err());
+        assert!(fields(&[0x0b]).is_err());
+
+        let send = any("/cosmos.bank.v1beta1.MsgSend", &[]);
+        assert!(decode(&tx_raw(&body(&[send]), &[], &[]))
+            .err()
+            .unwrap()
+            .contains("unsupported message type"));
+        assert!(decode(&tx_raw(&body(&[]), &[], &[])).is_err());
+    }
+
+    #[test]
+    fn address_of_public_key() {
+        // compressed generator point, its hash160 is well known from bitcoin
+        let generator =
+            hex::decode("0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798")
+                .unwrap();
+        let (hrp, data, _) = bech32::decode(&address_of(&generator)).unwrap();
+        let hash: Vec<u8> = FromBase32::from_base32(&data).unwrap();
+        assert_eq!(hrp, BECH32_PREFIX);
+        assert_eq!(
+            hex::encode(hash),
+            "751e76e8199196d454941c45d1b3a323f1433bd6"
+        );
+    }
+
+    #[test]
+    fn verifies_signatures() {
+        let (alice, bob) = (key(1), key(2));
+        let tx_body = body(&[execute_msg(&address(&alice), "token", "{}")]);
+
+        let tx_bytes = signed(&tx_body, &alice, 7);
+        assert_eq!(verify(&decode(&tx_bytes).unwrap(), 7), Ok(()));
+        // the account number is part of the sign doc
+        assert!(verify(&decode(&tx_bytes).unwrap(), 8).is_err());
+        // the message of alice signed by bob
+        let tx_bytes = signed(&tx_body, &bob, 7);
+        assert_eq!(
+            verify(&decode(&tx_bytes).unwrap(), 7),
+            Err(format!("sender {} did not sign the tx", address(&alice)))
+        );
+        // a body changed after signing
+        let tx_bytes = signed(&tx_body, &alice, 7);
+        let forged = body(&[execute_msg(&address(&alice), "token", "[]")]);
+        let tx = decode(&tx_bytes).unwrap();
+        let tx = DecodedTx {
+            body_bytes: &forged,
+            ..tx
+        };
+        assert!(verify(&tx, 7)
+            .err()
+            .unwrap()
+            .contains("signature verification failed"));
+        // unsigned
+        let tx_bytes = tx_raw(&tx_body, &auth_info(&alice), &[]);
+        assert!(verify(&decode(&tx_bytes).unwrap(), 7).is_err());
+    }
+}
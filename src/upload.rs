@@ -0,0 +1,269 @@
+// upload of a wasm with its schema as a zip or multipart form, the files are unpacked next to each other so the
+// analyzer finds the schema like for contracts loaded from the command line
+use crate::{contract_addresses, deploy_wasm, error_json, free_address, strict_address};
+use byteorder::{ByteOrder, LittleEndian};
+use flate2::read::DeflateDecoder;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+const ZIP_LOCAL_HEADER: u32 = 0x0403_4b50;
+const ZIP_CENTRAL_HEADER: u32 = 0x0201_4b50;
+const ZIP_END_OF_CENTRAL_DIRECTORY: u32 = 0x0605_4b50;
+const WASM_MAGIC: &[u8] = b"\0asm";
+// total size of the unpacked files of a zip, so a zip bomb can not exhaust the memory
+const MAX_UNZIPPED_SIZE: u64 = 64 * 1024 * 1024;
+
+type Files = Vec<(String, Vec<u8>)>;
+
+fn bytes(data: &[u8], start: usize, len: usize) -> Result<&[u8], String> {
+    data.get(start..start + len)
+        .ok_or_else(|| "truncated zip".to_string())
+}
+
+fn u16_at(data: &[u8], pos: usize) -> Result<usize, String> {
+    Ok(LittleEndian::read_u16(bytes(data, pos, 2)?) as usize)
+}
+
+fn u32_at(data: &[u8], pos: usize) -> Result<usize, String> {
+    Ok(LittleEndian::read_u32(bytes(data, pos, 4)?) as usize)
+}
+
+// unzip reads the stored and deflated files listed in the central directory, directories are skipped
+fn unzip(data: &[u8]) -> Result<Files, String> {
+    let end = (0..data.len().saturating_sub(21))
+        .rev()
+        .find(|&pos| u32_at(data, pos).ok() == Some(ZIP_END_OF_CENTRAL_DIRECTORY as usize))
+        .ok_or("not a zip file")?;
+    let count = u16_at(data, end + 10)?;
+    let mut pos = u32_at(data, end + 16)?;
+
+    let mut files = vec![];
+    let mut unzipped = 0;
+    for _ in 0..count {
+        if u32_at(data, pos)? != ZIP_CENTRAL_HEADER as usize {
+            return Err("broken zip central directory".to_string());
+        }
+        let method = u16_at(data, pos + 10)?;
+        let compressed_size = u32_at(data, pos + 20)?;
+        let name_len = u16_at(data, pos + 28)?;
+        let extra_len = u16_at(data, pos + 30)?;
+        let comment_len = u16_at(data, pos + 32)?;
+        let offset = u32_at(data, pos + 42)?;
+        let name = String::from_utf8_lossy(bytes(data, pos + 46, name_len)?).to_string();
+        pos += 46 + name_len + extra_len + comment_len;
+        if name.ends_with('/') {
+            continue;
+        }
+
+        // sizes of the local header may be zero when they follow the data, the central directory has them
+        if u32_at(data, offset)? != ZIP_LOCAL_HEADER as usize {
+            return Err(format!("broken zip entry {}", name));
+        }
+        let start = offset + 30 + u16_at(data, offset + 26)? + u16_at(data, offset + 28)?;
+        let compressed = bytes(data, start, compressed_size)?;
+        let content = match method {
+            0 => compressed.to_vec(),
+            8 => {
+                let mut content = vec![];
+                DeflateDecoder::new(compressed)
+                    .take(MAX_UNZIPPED_SIZE - unzipped + 1)
+                    .read_to_end(&mut content)
+                    .map_err(|e| format!("can not inflate {}: {}", name, e))?;
+                content
+            }
+            _ => return Err(format!("unsupported compression of {}", name)),
+        };
+        unzipped += content.len() as u64;
+        if unzipped > MAX_UNZIPPED_SIZE {
+            return Err(format!(
+                "zip unpacks to more than {} bytes",
+                MAX_UNZIPPED_SIZE
+            ));
+        }
+        files.push((name, content));
+    }
+    Ok(files)
+}
+
+fn find(data: &[u8], pattern: &[u8], from: usize) -> Option<usize> {
+    data.get(from..)?
+        .windows(pattern.len())
+        .position(|window| window.eq(pattern))
+        .map(|pos| pos + from)
+}
+
+// header_param reads name="value" of a content disposition header
+fn header_param(headers: &str, name: &str) -> Option<String> {
+    let start = headers.find(&format!("{}=\"", name))? + name.len() + 2;
+    let len = headers[start..].find('"')?;
+    Some(headers[start..start + len].to_string())
+}
+
+// multipart returns the files of a multipart form and the value of its address field, zip files are unpacked
+fn multipart(content_type: &str, body: &[u8]) -> Result<(Files, Option<String>), String> {
+    let boundary = content_type
+        .split(';')
+        .find_map(|param| param.trim().strip_prefix("boundary="))
+        .ok_or("multipart boundary is missing")?
+        .trim_matches('"');
+    let delimiter = format!("--{}", boundary).into_bytes();
+
+    let mut files = vec![];
+    let mut address = None;
+    let mut pos = find(body, &delimiter, 0).ok_or("multipart body is empty")?;
+    loop {
+        pos += delimiter.len();
+        // the last delimiter ends with --
+        if body.get(pos..).map_or(true, |rest| rest.starts_with(b"--")) {
+            break;
+        }
+        let headers_end = find(body, b"\r\n\r\n", pos).ok_or("broken multipart headers")?;
+        let headers = String::from_utf8_lossy(&body[pos..headers_end]).to_string();
+        let next = find(body, &delimiter, headers_end).ok_or("multipart body is not closed")?;
+        // the part ends with the line break before the next delimiter
+        let content = &body[headers_end + 4..next.saturating_sub(2).max(headers_end + 4)];
+        match (
+            header_param(&headers, "filename"),
+            header_param(&headers, "name"),
+        ) {
+            (Some(filename), _) if filename.ends_with(".zip") => files.extend(unzip(content)?),
+            (Some(filename), _) => files.push((filename, content.to_vec())),
+            (None, Some(name)) if name.eq("address") => {
+                address = Some(String::from_utf8_lossy(content).trim().to_string())
+            }
+            _ => {}
+        }
+        pos = next;
+    }
+    Ok((files, address))
+}
+
+fn file_name(path: &str) -> String {
+    Path::new(path)
+        .file_name()
+        .map_or(String::new(), |name| name.to_string_lossy().to_string())
+}
+
+// valid_address only accepts the characters of sanitized file stems and bech32 addresses, the address names the
+// upload folder so it must not reach outside of it
+fn valid_address(address: &str) -> bool {
+    !address.is_empty()
+        && address
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+// upload_dir creates a new folder for an upload, an existing folder of an earlier upload is left alone
+fn upload_dir(contract_addr: &str) -> Result<PathBuf, String> {
+    let uploads = std::env::temp_dir()
+        .join("cosmwasm-simulate")
+        .join("uploads");
+    std::fs::create_dir_all(&uploads).map_err(|e| e.to_string())?;
+    let mut name = contract_addr.to_string();
+    let mut index = 1;
+    loop {
+        let dir = uploads.join(&name);
+        match std::fs::create_dir(&dir) {
+            Ok(_) => return Ok(dir),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                name = format!("{}_{}", contract_addr, index);
+                index += 1;
+            }
+            Err(e) => return Err(e.to_string()),
+        }
+    }
+}
+
+// install writes the wasm and the json files as its schema folder, then deploys it
+fn install(files: Files, address: Option<String>) -> Result<(String, usize, usize), String> {
+    let mut wasm = files
+        .iter()
+        .filter(|(name, content)| name.ends_with(".wasm") || content.starts_with(WASM_MAGIC));
+    let (wasm_name, wasm_code) = match (wasm.next(), wasm.next()) {
+        (Some(wasm), None) => wasm,
+        (None, _) => return Err("no wasm file uploaded".to_string()),
+        (Some(_), Some(_)) => return Err("more than one wasm file uploaded".to_string()),
+    };
+    let stem = file_name(wasm_name).trim_end_matches(".wasm").replace(
+        |c: char| !c.is_ascii_alphanumeric() && c != '_' && c != '-',
+        "_",
+    );
+    let stem = match stem.is_empty() {
+        true => "contract".to_string(),
+        false => stem,
+    };
+    let contract_addr = address
+        .filter(|address| !address.is_empty())
+        .unwrap_or_else(|| free_address(&stem));
+    if !valid_address(&contract_addr) {
+        return Err(format!(
+            "wrong address {}, only letters, digits, '_' and '-' are allowed",
+            contract_addr
+        ));
+    }
+    let contract_addr = strict_address(&contract_addr);
+    if contract_addresses().contains(&contract_addr) {
+        return Err(format!("contract {} already loaded", contract_addr));
+    }
+
+    // every upload gets its own folder so schemas of earlier uploads are not mixed in
+    let dir = upload_dir(&contract_addr)?;
+    let schema_dir = dir.join("schema");
+    std::fs::create_dir_all(&schema_dir).map_err(|e| e.to_string())?;
+    let wasm_file = dir.join(format!("{}.wasm", stem));
+    std::fs::write(&wasm_file, wasm_code).map_err(|e| e.to_string())?;
+    let mut schemas = 0;
+    for (name, content) in files.iter().filter(|(name, _)| name.ends_with(".json")) {
+        std::fs::write(schema_dir.join(file_name(name)), content).map_err(|e| e.to_string())?;
+        schemas += 1;
+    }
+
+    let code_id = deploy_wasm(&wasm_file.to_string_lossy(), &contract_addr)?;
+    Ok((contract_addr, code_id, schemas))
+}
+
+// json_upload reads {"wasm_byte_code": base64, "address": ..} like the store code message of wasmd, the code is a
+// wasm or a zip of it with its schemas
+fn json_upload(body: &[u8]) -> Result<(Files, Option<String>), String> {
+    let request: serde_json::Value =
+        serde_json::from_slice(body).map_err(|e| format!("invalid json body: {}", e))?;
+    let code = request["wasm_byte_code"]
+        .as_str()
+        .ok_or("wasm_byte_code is missing")?;
+    let code = base64::decode(code).map_err(|e| format!("invalid wasm_byte_code: {}", e))?;
+    let address = request["address"].as_str().map(String::from);
+    match code.starts_with(WASM_MAGIC) {
+        true => Ok((vec![("contract.wasm".to_string(), code)], address)),
+        false => unzip(&code).map(|files| (files, address)),
+    }
+}
+
+// upload serves POST /wasm/code with a zip, a multipart form, base64 code in json or a bare wasm as body,
+// the address comes from ?address= or the address field of the form or json
+pub fn upload(content_type: &str, body: &[u8], address: Option<String>) -> (u16, String) {
+    let files = if content_type.starts_with("multipart/form-data") {
+        multipart(content_type, body).map(|(files, field)| (files, field.or(address)))
+    } else if content_type.starts_with("application/json") {
+        json_upload(body).map(|(files, field)| (files, field.or(address)))
+    } else if body.starts_with(WASM_MAGIC) {
+        Ok((vec![("contract.wasm".to_string(), body.to_vec())], address))
+    } else {
+        unzip(body).map(|files| (files, address))
+    };
+    let (files, address) = match files {
+        Ok(files) => files,
+        Err(e) => return (400, error_json(&e)),
+    };
+    match install(files, address) {
+        Ok((contract_addr, code_id, schemas)) => (
+            200,
+            serde_json::json!({
+                "contract_address": contract_addr,
+                "code_id": code_id,
+                "schema_files": schemas,
+            })
+            .to_string(),
+        ),
+        Err(e) => (400, error_json(&e)),
+    }
+}